@@ -0,0 +1,150 @@
+//! Choosing the color that fills the parts of the square icon not covered by the image.
+
+use crate::{get_top_left_color, Error};
+use image::{DynamicImage, GenericImageView, Rgba};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// How the background of the square icon is filled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Background {
+    /// Fully transparent background.
+    Transparent,
+    /// An explicit color.
+    Color(Rgba<u8>),
+    /// The color of the top-left pixel of the input image.
+    #[default]
+    TopLeft,
+    /// The most common color along all four borders of the input image.
+    Edge,
+}
+
+impl FromStr for Background {
+    type Err = Error;
+
+    /// Parses `transparent`, `top-left`, `edge` or a hex color such as `#ff8800`.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "transparent" => Ok(Background::Transparent),
+            "top-left" => Ok(Background::TopLeft),
+            "edge" => Ok(Background::Edge),
+            _ => parse_hex_color(value).map(Background::Color),
+        }
+    }
+}
+
+impl fmt::Display for Background {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Background::Transparent => write!(f, "transparent"),
+            Background::Color(color) => write!(f, "{}", format_hex_color(*color)),
+            Background::TopLeft => write!(f, "top-left"),
+            Background::Edge => write!(f, "edge"),
+        }
+    }
+}
+
+/// Parses a hex color in the `#rgb`, `#rrggbb` or `#rrggbbaa` form, the `#` being optional.
+///
+/// # Errors
+/// Returns [`Error::InvalidColor`] if the value is not a hex color.
+///
+/// # Examples
+/// ```
+/// use image::Rgba;
+/// assert_eq!(chinenshichanaka::parse_hex_color("#f80")?, Rgba([255, 136, 0, 255]));
+/// assert_eq!(chinenshichanaka::parse_hex_color("00ff0080")?, Rgba([0, 255, 0, 128]));
+/// # Ok::<(), chinenshichanaka::Error>(())
+/// ```
+pub fn parse_hex_color(value: &str) -> Result<Rgba<u8>, Error> {
+    let hex = value.strip_prefix('#').unwrap_or(value);
+    let invalid = || Error::InvalidColor(value.to_string());
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+    // Every digit of the short form is doubled, so "f80" equals "ff8800"
+    let expanded: String = match hex.len() {
+        3 => hex
+            .chars()
+            .flat_map(|c| [c, c])
+            .chain("ff".chars())
+            .collect(),
+        6 => format!("{hex}ff"),
+        8 => hex.to_string(),
+        _ => return Err(invalid()),
+    };
+    let mut channels = [0u8; 4];
+    for (i, channel) in channels.iter_mut().enumerate() {
+        *channel = u8::from_str_radix(&expanded[i * 2..i * 2 + 2], 16).map_err(|_| invalid())?;
+    }
+    Ok(Rgba(channels))
+}
+
+/// Formats a color as `#rrggbb`, or as `#rrggbbaa` when it is not fully opaque.
+///
+/// # Examples
+/// ```
+/// use image::Rgba;
+/// assert_eq!(chinenshichanaka::format_hex_color(Rgba([255, 136, 0, 255])), "#ff8800");
+/// assert_eq!(chinenshichanaka::format_hex_color(Rgba([0, 0, 0, 0])), "#00000000");
+/// ```
+pub fn format_hex_color(color: Rgba<u8>) -> String {
+    let [r, g, b, a] = color.0;
+    if a == 255 {
+        format!("#{r:02x}{g:02x}{b:02x}")
+    } else {
+        format!("#{r:02x}{g:02x}{b:02x}{a:02x}")
+    }
+}
+
+/// Gets the most common color along all four borders of an image.
+///
+/// Ties are resolved by picking the numerically smallest color, so the result is stable.
+///
+/// # Arguments
+/// * `input_image` - Reference to the input image.
+///
+/// # Returns
+/// The dominant border color, or transparent for an empty image.
+///
+/// # Examples
+/// ```
+/// use image::{DynamicImage, Rgba, RgbaImage};
+/// let img = DynamicImage::ImageRgba8(RgbaImage::from_pixel(4, 4, Rgba([0, 0, 255, 255])));
+/// assert_eq!(chinenshichanaka::get_edge_color(&img), Rgba([0, 0, 255, 255]));
+/// ```
+pub fn get_edge_color(input_image: &DynamicImage) -> Rgba<u8> {
+    let (width, height) = input_image.dimensions();
+    let mut counts: HashMap<[u8; 4], usize> = HashMap::new();
+    for (x, y, pixel) in input_image.pixels() {
+        if x == 0 || y == 0 || x == width - 1 || y == height - 1 {
+            *counts.entry(pixel.0).or_default() += 1;
+        }
+    }
+    counts
+        .into_iter()
+        .max_by(|(color_a, count_a), (color_b, count_b)| {
+            count_a.cmp(count_b).then(color_b.cmp(color_a))
+        })
+        .map(|(color, _)| Rgba(color))
+        .unwrap_or(Rgba([0, 0, 0, 0]))
+}
+
+/// Resolves the background fill of an image into a single color.
+///
+/// # Examples
+/// ```
+/// use chinenshichanaka::Background;
+/// use image::{DynamicImage, Rgba};
+/// let img = DynamicImage::new_rgba8(4, 4);
+/// assert_eq!(chinenshichanaka::background_color(&img, Background::Transparent), Rgba([0, 0, 0, 0]));
+/// ```
+pub fn background_color(input_image: &DynamicImage, background: Background) -> Rgba<u8> {
+    match background {
+        Background::Transparent => Rgba([0, 0, 0, 0]),
+        Background::Color(color) => color,
+        Background::TopLeft => get_top_left_color(input_image),
+        Background::Edge => get_edge_color(input_image),
+    }
+}
@@ -0,0 +1,110 @@
+//! Color spaces for measuring the distance between colors.
+
+use crate::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// Color space in which the distance of a pixel to the palette colors is measured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorSpace {
+    /// Plain distance between the sRGB channel values.
+    #[default]
+    Srgb,
+    /// CIELAB with the D65 white point, where distances follow the CIE76 color difference.
+    Lab,
+    /// OKLab, a more uniform perceptual space especially for blues and saturated colors.
+    Oklab,
+}
+
+impl FromStr for ColorSpace {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "srgb" | "rgb" => Ok(ColorSpace::Srgb),
+            "lab" | "cielab" => Ok(ColorSpace::Lab),
+            "oklab" => Ok(ColorSpace::Oklab),
+            _ => Err(Error::InvalidOption {
+                name: "color space",
+                value: value.to_string(),
+                expected: "srgb, lab or oklab",
+            }),
+        }
+    }
+}
+
+impl fmt::Display for ColorSpace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ColorSpace::Srgb => write!(f, "srgb"),
+            ColorSpace::Lab => write!(f, "lab"),
+            ColorSpace::Oklab => write!(f, "oklab"),
+        }
+    }
+}
+
+impl ColorSpace {
+    /// Converts an RGBA color, with channels from 0 to 255, into the coordinates of this space.
+    ///
+    /// The alpha channel is kept as the fourth coordinate, scaled to the range of lightness,
+    /// so that the Euclidean distance of two coordinates is their color difference.
+    ///
+    /// # Examples
+    /// ```
+    /// use chinenshichanaka::ColorSpace;
+    /// let [lightness, a, b, alpha] = ColorSpace::Lab.coordinates([255.0, 255.0, 255.0, 255.0]);
+    /// assert!((lightness - 100.0).abs() < 0.01 && a.abs() < 0.01 && b.abs() < 0.01);
+    /// assert_eq!(alpha, 100.0);
+    /// ```
+    pub fn coordinates(self, rgba: [f32; 4]) -> [f32; 4] {
+        let [red, green, blue, alpha] = rgba;
+        match self {
+            ColorSpace::Srgb => rgba,
+            ColorSpace::Lab => {
+                let (r, g, b) = (linear(red), linear(green), linear(blue));
+                // Relative to the D65 reference white
+                let x = (0.4124564 * r + 0.3575761 * g + 0.1804375 * b) / 0.95047;
+                let y = 0.2126729 * r + 0.7151522 * g + 0.0721750 * b;
+                let z = (0.0193339 * r + 0.1191920 * g + 0.9503041 * b) / 1.08883;
+                let (fx, fy, fz) = (lab_f(x), lab_f(y), lab_f(z));
+                [
+                    (116.0 * fy - 16.0) as f32,
+                    (500.0 * (fx - fy)) as f32,
+                    (200.0 * (fy - fz)) as f32,
+                    alpha / 255.0 * 100.0,
+                ]
+            }
+            ColorSpace::Oklab => {
+                let (r, g, b) = (linear(red), linear(green), linear(blue));
+                let l = (0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b).cbrt();
+                let m = (0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b).cbrt();
+                let s = (0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b).cbrt();
+                [
+                    (0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s) as f32,
+                    (1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s) as f32,
+                    (0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s) as f32,
+                    alpha / 255.0,
+                ]
+            }
+        }
+    }
+}
+
+// Converts an sRGB channel value from 0 to 255 into linear light from 0 to 1
+fn linear(value: f32) -> f64 {
+    let value = value as f64 / 255.0;
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn lab_f(t: f64) -> f64 {
+    const DELTA: f64 = 6.0 / 29.0;
+    if t > DELTA * DELTA * DELTA {
+        t.cbrt()
+    } else {
+        t / (3.0 * DELTA * DELTA) + 4.0 / 29.0
+    }
+}
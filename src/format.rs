@@ -0,0 +1,152 @@
+//! Detecting the format of the input from its content rather than its file name,
+//! and choosing the format of the output.
+
+use crate::{Error, Result};
+use flate2::read::GzDecoder;
+use image::ImageFormat;
+use std::fmt;
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+
+/// The gzip magic number that SVGZ files start with.
+const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+
+/// How many bytes from the beginning are searched for the SVG root element.
+const SVG_SNIFF_LENGTH: usize = 4096;
+
+/// The kind of input data, as detected by [`detect_format`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputFormat {
+    /// An SVG document.
+    Svg,
+    /// A gzip-compressed SVG document.
+    Svgz,
+    /// A raster image format supported by the `image` crate.
+    Raster(ImageFormat),
+}
+
+impl InputFormat {
+    /// Returns whether the input is rendered with resvg.
+    pub fn is_svg(&self) -> bool {
+        matches!(self, InputFormat::Svg | InputFormat::Svgz)
+    }
+}
+
+/// The format of the output file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// An ICO file with all the icon sizes.
+    #[default]
+    Ico,
+    /// A PNG image of the largest icon size.
+    Png,
+}
+
+impl OutputFormat {
+    /// Chooses the output format by the suffix of a file name, if it is `.ico` or `.png`.
+    ///
+    /// # Examples
+    /// ```
+    /// use chinenshichanaka::OutputFormat;
+    /// assert_eq!(OutputFormat::from_path("favicon.ICO"), Some(OutputFormat::Ico));
+    /// assert_eq!(OutputFormat::from_path("favicon.jpg"), None);
+    /// ```
+    pub fn from_path(path: impl AsRef<Path>) -> Option<OutputFormat> {
+        let extension = path.as_ref().extension()?.to_str()?;
+        extension.parse().ok()
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = Error;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "ico" => Ok(OutputFormat::Ico),
+            "png" => Ok(OutputFormat::Png),
+            _ => Err(Error::InvalidOption {
+                name: "output format",
+                value: value.to_string(),
+                expected: "ico or png",
+            }),
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputFormat::Ico => write!(f, "ico"),
+            OutputFormat::Png => write!(f, "png"),
+        }
+    }
+}
+
+/// Detects the format of the input by sniffing its content.
+///
+/// Raster formats are recognized by their magic numbers, SVGZ by the gzip magic number
+/// followed by compressed SVG, and SVG by an `<svg` root element near the beginning of a document starting with
+/// markup, optionally after a byte order mark, an XML declaration, comments or a doctype.
+///
+/// # Errors
+/// Returns [`Error::UnsupportedFormat`] when the content is not recognized, or when the
+/// raster format cannot be decoded by this build.
+///
+/// # Examples
+/// ```
+/// use chinenshichanaka::InputFormat;
+/// let svg = br#"<?xml version="1.0"?><svg xmlns="http://www.w3.org/2000/svg"/>"#;
+/// assert_eq!(chinenshichanaka::detect_format(svg)?, InputFormat::Svg);
+/// assert!(chinenshichanaka::detect_format(b"not an image").is_err());
+/// # Ok::<(), chinenshichanaka::Error>(())
+/// ```
+pub fn detect_format(input: &[u8]) -> Result<InputFormat> {
+    if input.starts_with(&GZIP_MAGIC) {
+        // Only the beginning is needed, and any gzip stream could have the magic number
+        let mut head = Vec::new();
+        let _ = GzDecoder::new(input)
+            .take(SVG_SNIFF_LENGTH as u64)
+            .read_to_end(&mut head);
+        return match looks_like_svg(&head) {
+            true => Ok(InputFormat::Svgz),
+            false => Err(Error::UnsupportedFormat),
+        };
+    }
+    if let Ok(format) = image::guess_format(input) {
+        return match format.reading_enabled() {
+            true => Ok(InputFormat::Raster(format)),
+            false => Err(Error::UnsupportedFormat),
+        };
+    }
+    if looks_like_svg(input) {
+        return Ok(InputFormat::Svg);
+    }
+    Err(Error::UnsupportedFormat)
+}
+
+// Markup that contains the SVG root element within the first few kilobytes
+fn looks_like_svg(input: &[u8]) -> bool {
+    let head = &input[..input.len().min(SVG_SNIFF_LENGTH)];
+    let text = String::from_utf8_lossy(head);
+    let text = text.trim_start_matches('\u{feff}').trim_start();
+    text.starts_with('<') && text.contains("<svg")
+}
+
+/// Lists the names of the input formats this build can decode.
+///
+/// # Examples
+/// ```
+/// let formats = chinenshichanaka::supported_input_formats();
+/// assert!(formats.contains(&"SVG".to_string()));
+/// assert!(formats.contains(&"PNG".to_string()));
+/// ```
+pub fn supported_input_formats() -> Vec<String> {
+    let mut formats = vec!["SVG".to_string(), "SVGZ".to_string()];
+    formats.extend(
+        ImageFormat::all()
+            .filter(ImageFormat::reading_enabled)
+            .map(|format| format!("{format:?}").to_uppercase()),
+    );
+    formats
+}
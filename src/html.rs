@@ -0,0 +1,317 @@
+//! Replacing the favicon tags in the `<head>` of an existing HTML document.
+
+use crate::{Error, Result};
+use std::ops::Range;
+
+/// Replaces the favicon tags of an HTML document with the given ones.
+///
+/// The stale tags are the `<link>` tags whose `rel` is `icon`, `shortcut icon`,
+/// `apple-touch-icon`, `apple-touch-icon-precomposed` or `manifest`, and the
+/// `<meta name="theme-color">` tags, looked for in the `<head>` while skipping comments,
+/// scripts and styles. The new tags go where the first stale tag was, or at the end of the
+/// `<head>`, indented like the surrounding lines. The rest of the document is kept
+/// byte for byte, and patching it again with the same tags changes nothing.
+///
+/// # Arguments
+/// * `html` - The HTML document.
+/// * `tags` - The new tags, one per line, such as the lines of [`crate::html_snippet`].
+///
+/// # Returns
+/// The patched document.
+///
+/// # Errors
+/// Returns [`Error::InvalidHtml`] if the document has neither favicon tags nor a `<head>`
+/// element to put the new tags in.
+///
+/// # Examples
+/// ```
+/// let html = "<head>\n  <link rel=\"icon\" href=\"old.ico\">\n  <title>Dojo</title>\n</head>\n";
+/// let patched = chinenshichanaka::patch_html(html, "<link rel=\"icon\" href=\"/favicon.ico\">\n")?;
+/// assert_eq!(
+///     patched,
+///     "<head>\n  <link rel=\"icon\" href=\"/favicon.ico\">\n  <title>Dojo</title>\n</head>\n"
+/// );
+/// # Ok::<(), chinenshichanaka::Error>(())
+/// ```
+pub fn patch_html(html: &str, tags: &str) -> Result<String> {
+    // ASCII lowercasing keeps the byte offsets, so positions found in it apply to the original
+    let lower = html.to_ascii_lowercase();
+    let head = head_range(&lower);
+    let region = head.clone().map_or(0..html.len(), |(head, _)| head);
+    let newline = match html.contains("\r\n") {
+        true => "\r\n",
+        false => "\n",
+    };
+    let tags: Vec<&str> = tags
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect();
+
+    let stale = stale_tags(html, &lower, region.clone());
+    let mut removals: Vec<Range<usize>> = Vec::new();
+    let mut insertion = None;
+    for group in group_by_line(html, &stale) {
+        let first = group[0].start;
+        let last = group[group.len() - 1].end;
+        let line = line_start(html, first)..line_end(html, last);
+        let rest_is_blank = html[line.start..first].trim().is_empty()
+            && html[last..line.end].trim().is_empty()
+            && group
+                .windows(2)
+                .all(|pair| html[pair[0].end..pair[1].start].trim().is_empty());
+        if rest_is_blank {
+            insertion.get_or_insert_with(|| {
+                let indent = &html[line.start..first];
+                let text: String = tags
+                    .iter()
+                    .map(|tag| format!("{indent}{tag}{newline}"))
+                    .collect();
+                (line.start, text)
+            });
+            removals.push(line);
+        } else {
+            insertion.get_or_insert_with(|| {
+                let indent = format!("{newline}{}", indentation(html, first));
+                (first, tags.join(&indent))
+            });
+            removals.extend(group);
+        }
+    }
+
+    let (position, text) = match insertion {
+        Some(insertion) => insertion,
+        None => match head {
+            Some((head, true)) => insertion_at_end(html, head, &tags, newline),
+            Some((head, false)) => {
+                let text = tags.iter().map(|tag| format!("{newline}{tag}")).collect();
+                (head.start, text)
+            }
+            None => {
+                return Err(Error::InvalidHtml(
+                    "no <head> element for the tags".to_string(),
+                ))
+            }
+        },
+    };
+
+    // The insertion is either at the start of the first removal or after all of them
+    let mut patched = String::with_capacity(html.len() + text.len());
+    let mut copied = 0;
+    let mut text = Some(text);
+    for removal in &removals {
+        if removal.start == position {
+            patched.push_str(&html[copied..position]);
+            patched.push_str(&text.take().unwrap_or_default());
+            copied = position;
+        }
+        patched.push_str(&html[copied..removal.start]);
+        copied = removal.end;
+    }
+    if let Some(text) = text {
+        patched.push_str(&html[copied..position]);
+        patched.push_str(&text);
+        copied = position;
+    }
+    patched.push_str(&html[copied..]);
+    Ok(patched)
+}
+
+// The content of the head element, between the end of `<head>` and the start of `</head>`,
+// and whether `</head>` is there, as the end of the document otherwise
+fn head_range(lower: &str) -> Option<(Range<usize>, bool)> {
+    let mut from = 0;
+    let start = loop {
+        let index = from + lower[from..].find("<head")?;
+        let after = lower.as_bytes().get(index + 5).copied();
+        if matches!(after, Some(b'>' | b'/' | b' ' | b'\t' | b'\r' | b'\n')) {
+            break index + lower[index..].find('>')? + 1;
+        }
+        from = index + 5;
+    };
+    Some(match lower[start..].find("</head") {
+        Some(index) => (start..start + index, true),
+        None => (start..lower.len(), false),
+    })
+}
+
+// The byte ranges of the favicon tags within the region, in document order
+fn stale_tags(html: &str, lower: &str, region: Range<usize>) -> Vec<Range<usize>> {
+    let bytes = lower.as_bytes();
+    let mut tags = Vec::new();
+    let mut index = region.start;
+    while let Some(offset) = lower[index..region.end].find('<') {
+        let start = index + offset;
+        if lower[start..].starts_with("<!--") {
+            index = lower[start..]
+                .find("-->")
+                .map_or(region.end, |end| start + end + 3);
+            continue;
+        }
+        let name_end = start
+            + 1
+            + bytes[start + 1..]
+                .iter()
+                .position(|byte| !byte.is_ascii_alphanumeric())
+                .unwrap_or(bytes.len() - start - 1);
+        let name = &lower[start + 1..name_end];
+        if name.is_empty() {
+            index = start + 1;
+            continue;
+        }
+        let (attributes, end) = parse_attributes(html, name_end);
+        if is_favicon_tag(name, &attributes) {
+            tags.push(start..end);
+        }
+        index = match name {
+            // Their content is not markup, so a "<link" in it is not a tag
+            "script" | "style" => lower[end..]
+                .find(&format!("</{name}"))
+                .map_or(region.end, |close| end + close),
+            _ => end,
+        }
+        .min(region.end);
+    }
+    tags
+}
+
+// The lowercased names and the values of the attributes of a tag, and the end of the tag
+fn parse_attributes(html: &str, from: usize) -> (Vec<(String, String)>, usize) {
+    let bytes = html.as_bytes();
+    let mut attributes = Vec::new();
+    let mut index = from;
+    loop {
+        while index < bytes.len() && (bytes[index].is_ascii_whitespace() || bytes[index] == b'/') {
+            index += 1;
+        }
+        if index >= bytes.len() {
+            return (attributes, bytes.len());
+        }
+        if bytes[index] == b'>' {
+            return (attributes, index + 1);
+        }
+        let name_start = index;
+        while index < bytes.len()
+            && !bytes[index].is_ascii_whitespace()
+            && !matches!(bytes[index], b'=' | b'>' | b'/')
+        {
+            index += 1;
+        }
+        let name = html[name_start..index].to_ascii_lowercase();
+        while index < bytes.len() && bytes[index].is_ascii_whitespace() {
+            index += 1;
+        }
+        let mut value = String::new();
+        if bytes.get(index) == Some(&b'=') {
+            index += 1;
+            while index < bytes.len() && bytes[index].is_ascii_whitespace() {
+                index += 1;
+            }
+            let value_start = index;
+            match bytes.get(index) {
+                Some(&quote @ (b'"' | b'\'')) => {
+                    let value_end = html[index + 1..]
+                        .find(quote as char)
+                        .map_or(bytes.len(), |end| index + 1 + end);
+                    value = html[value_start + 1..value_end].to_string();
+                    index = (value_end + 1).min(bytes.len());
+                }
+                _ => {
+                    while index < bytes.len()
+                        && !bytes[index].is_ascii_whitespace()
+                        && bytes[index] != b'>'
+                    {
+                        index += 1;
+                    }
+                    value = html[value_start..index].to_string();
+                }
+            }
+        }
+        attributes.push((name, value));
+    }
+}
+
+fn is_favicon_tag(name: &str, attributes: &[(String, String)]) -> bool {
+    let attribute = |wanted: &str| {
+        attributes
+            .iter()
+            .find(|(name, _)| name == wanted)
+            .map(|(_, value)| value.to_ascii_lowercase())
+            .unwrap_or_default()
+    };
+    match name {
+        "link" => attribute("rel").split_ascii_whitespace().any(|rel| {
+            matches!(
+                rel,
+                "icon" | "apple-touch-icon" | "apple-touch-icon-precomposed" | "manifest"
+            )
+        }),
+        "meta" => attribute("name").trim() == "theme-color",
+        _ => false,
+    }
+}
+
+// Tags that follow each other on the same line form a group, removed together
+fn group_by_line(html: &str, tags: &[Range<usize>]) -> Vec<Vec<Range<usize>>> {
+    let mut groups: Vec<Vec<Range<usize>>> = Vec::new();
+    for tag in tags {
+        match groups.last_mut() {
+            Some(group) if !html[group[group.len() - 1].end..tag.start].contains('\n') => {
+                group.push(tag.clone());
+            }
+            _ => groups.push(vec![tag.clone()]),
+        }
+    }
+    groups
+}
+
+fn line_start(html: &str, index: usize) -> usize {
+    html[..index].rfind('\n').map_or(0, |newline| newline + 1)
+}
+
+// The end of the line including its line break
+fn line_end(html: &str, index: usize) -> usize {
+    html[index..]
+        .find('\n')
+        .map_or(html.len(), |newline| index + newline + 1)
+}
+
+// The leading whitespace of the line of the index
+fn indentation(html: &str, index: usize) -> &str {
+    let start = line_start(html, index);
+    let line = &html[start..];
+    &line[..line.len() - line.trim_start_matches([' ', '\t']).len()]
+}
+
+// Without old tags, the new ones go on their own lines before `</head>`, indented like
+// the last line of the head
+fn insertion_at_end(
+    html: &str,
+    head: Range<usize>,
+    tags: &[&str],
+    newline: &str,
+) -> (usize, String) {
+    let close_line = line_start(html, head.end);
+    if close_line > head.start && html[close_line..head.end].trim().is_empty() {
+        let indent = html[head.start..close_line]
+            .lines()
+            .rev()
+            .find(|line| !line.trim().is_empty())
+            .map_or_else(
+                || indentation(html, head.end),
+                |line| &line[..line.len() - line.trim_start_matches([' ', '\t']).len()],
+            );
+        let text = tags
+            .iter()
+            .map(|tag| format!("{indent}{tag}{newline}"))
+            .collect();
+        (close_line, text)
+    } else {
+        let text = tags
+            .iter()
+            .map(|tag| format!("{newline}{tag}"))
+            .collect::<String>()
+            + newline;
+        (head.end, text)
+    }
+}
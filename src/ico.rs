@@ -0,0 +1,567 @@
+//! Encoding the images of an ICO file and writing its directory.
+
+use crate::Error;
+use image::codecs::png::PngEncoder;
+use image::{ExtendedColorType, ImageEncoder, ImageFormat, Rgba, RgbaImage};
+use std::fmt;
+use std::str::FromStr;
+
+// Sizes of the ICO header, a directory entry and the DIB header
+const ICO_HEADER_SIZE: usize = 6;
+const ICO_ENTRY_SIZE: usize = 16;
+const DIB_HEADER_SIZE: u32 = 40;
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+// Under the AND mask, black leaves the background as it is
+const OPAQUE_BLACK: Rgba<u8> = Rgba([0, 0, 0, 255]);
+
+/// How each image of an ICO file is encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IconEncoding {
+    /// PNG compressed entries, read by Windows Vista and later.
+    Png,
+    /// BMP DIB entries, read by any software that supports ICO files.
+    Bmp,
+    /// PNG for the 256 pixel entries, where BMP would be large, and BMP for smaller ones.
+    #[default]
+    Auto,
+    /// Whichever of PNG and BMP takes fewer bytes, BMP when equal.
+    Smallest,
+}
+
+impl FromStr for IconEncoding {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "png" => Ok(IconEncoding::Png),
+            "bmp" => Ok(IconEncoding::Bmp),
+            "auto" => Ok(IconEncoding::Auto),
+            "smallest" => Ok(IconEncoding::Smallest),
+            _ => Err(Error::InvalidOption {
+                name: "encoding",
+                value: value.to_string(),
+                expected: "png, bmp, auto or smallest",
+            }),
+        }
+    }
+}
+
+impl fmt::Display for IconEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IconEncoding::Png => write!(f, "png"),
+            IconEncoding::Bmp => write!(f, "bmp"),
+            IconEncoding::Auto => write!(f, "auto"),
+            IconEncoding::Smallest => write!(f, "smallest"),
+        }
+    }
+}
+
+/// One image of an ICO file, already encoded as PNG or as a BMP DIB.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IconEntry {
+    /// Width of the image in pixels, from 1 to 256.
+    pub width: u32,
+    /// Height of the image in pixels, from 1 to 256.
+    pub height: u32,
+    /// Number of colors in the color table, or zero for 256 colors and images without one.
+    pub color_count: u8,
+    /// Bits per pixel of the image data.
+    pub bits_per_pixel: u16,
+    /// The encoded image, a PNG file or a DIB without the BMP file header.
+    pub data: Vec<u8>,
+}
+
+impl IconEntry {
+    /// Encodes an image as PNG or BMP, as chosen by the encoding policy.
+    ///
+    /// # Errors
+    /// Returns the errors of [`IconEntry::png`] and [`IconEntry::bmp`].
+    ///
+    /// # Examples
+    /// ```
+    /// use chinenshichanaka::{IconEncoding, IconEntry};
+    /// use image::RgbaImage;
+    /// let small = IconEntry::encode(&RgbaImage::new(16, 16), IconEncoding::Auto)?;
+    /// let large = IconEntry::encode(&RgbaImage::new(256, 256), IconEncoding::Auto)?;
+    /// assert!(!small.is_png() && large.is_png());
+    /// # Ok::<(), chinenshichanaka::Error>(())
+    /// ```
+    pub fn encode(img: &RgbaImage, encoding: IconEncoding) -> Result<IconEntry, Error> {
+        match encoding {
+            IconEncoding::Png => IconEntry::png(img),
+            IconEncoding::Bmp => IconEntry::bmp(img),
+            IconEncoding::Auto if img.width() == 256 || img.height() == 256 => IconEntry::png(img),
+            IconEncoding::Auto => IconEntry::bmp(img),
+            IconEncoding::Smallest => {
+                let png = IconEntry::png(img)?;
+                let bmp = IconEntry::bmp(img)?;
+                Ok(if png.data.len() < bmp.data.len() {
+                    png
+                } else {
+                    bmp
+                })
+            }
+        }
+    }
+
+    /// Whether the image data is a PNG file rather than a BMP DIB.
+    pub fn is_png(&self) -> bool {
+        self.data.starts_with(PNG_SIGNATURE)
+    }
+
+    /// Bits per pixel of the image data, read from the PNG or DIB header.
+    ///
+    /// Falls back to the bits per pixel of the directory entry when the header is truncated.
+    pub fn bit_depth(&self) -> u16 {
+        let header = if self.is_png() {
+            // Bit depth and color type of the IHDR chunk
+            self.data.get(24..26).and_then(|header| {
+                let channels = match header[1] {
+                    0 | 3 => 1,
+                    4 => 2,
+                    2 => 3,
+                    6 => 4,
+                    _ => return None,
+                };
+                Some(header[0] as u16 * channels)
+            })
+        } else {
+            self.data
+                .get(14..16)
+                .map(|bits| u16::from_le_bytes([bits[0], bits[1]]))
+        };
+        header.unwrap_or(self.bits_per_pixel)
+    }
+
+    /// Number of colors in the color table of the image data, zero for true color images.
+    pub fn palette_size(&self) -> usize {
+        if self.is_png() {
+            // The palette of an indexed PNG is in its PLTE chunk, three bytes per color
+            let mut offset = PNG_SIGNATURE.len();
+            while let Some(chunk) = offset
+                .checked_add(8)
+                .and_then(|end| self.data.get(offset..end))
+            {
+                let length = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]) as usize;
+                match &chunk[4..8] {
+                    b"PLTE" => return length / 3,
+                    b"IDAT" | b"IEND" => break,
+                    // A chunk length running past the data ends the search
+                    _ => match length
+                        .checked_add(12)
+                        .and_then(|size| offset.checked_add(size))
+                    {
+                        Some(next) if next <= self.data.len() => offset = next,
+                        _ => break,
+                    },
+                }
+            }
+            0
+        } else {
+            match (self.bit_depth(), self.data.get(32..36)) {
+                (bits @ 1..=8, Some(used)) => {
+                    match u32::from_le_bytes([used[0], used[1], used[2], used[3]]) {
+                        0 => 1 << bits,
+                        used => used as usize,
+                    }
+                }
+                _ => 0,
+            }
+        }
+    }
+
+    /// Decodes the image data of the entry.
+    ///
+    /// # Errors
+    /// Returns [`Error::Decode`] if the image data cannot be decoded.
+    ///
+    /// # Examples
+    /// ```
+    /// use chinenshichanaka::IconEntry;
+    /// use image::{Rgba, RgbaImage};
+    /// let img = RgbaImage::from_pixel(16, 16, Rgba([255, 0, 0, 255]));
+    /// assert_eq!(IconEntry::bmp(&img)?.decode()?, img);
+    /// # Ok::<(), chinenshichanaka::Error>(())
+    /// ```
+    pub fn decode(&self) -> Result<RgbaImage, Error> {
+        // The image crate decodes BMP entries, with their AND mask, only as part of an ICO file
+        let ico = write_ico(std::slice::from_ref(self))?;
+        image::load_from_memory_with_format(&ico, ImageFormat::Ico)
+            .map(|img| img.into_rgba8())
+            .map_err(Error::Decode)
+    }
+
+    /// Encodes an image as a PNG entry, keeping the full RGBA color.
+    ///
+    /// # Errors
+    /// Returns [`Error::InvalidSize`] if the image is empty or larger than 256x256,
+    /// and [`Error::Encode`] if encoding fails.
+    ///
+    /// # Examples
+    /// ```
+    /// use chinenshichanaka::IconEntry;
+    /// use image::RgbaImage;
+    /// let entry = IconEntry::png(&RgbaImage::new(16, 16))?;
+    /// assert!(entry.data.starts_with(b"\x89PNG"));
+    /// # Ok::<(), chinenshichanaka::Error>(())
+    /// ```
+    pub fn png(img: &RgbaImage) -> Result<IconEntry, Error> {
+        let (width, height) = check_entry_size(img)?;
+        let mut data = Vec::new();
+        PngEncoder::new(&mut data)
+            .write_image(img.as_raw(), width, height, ExtendedColorType::Rgba8)
+            .map_err(Error::Encode)?;
+        Ok(IconEntry {
+            width,
+            height,
+            color_count: 0,
+            bits_per_pixel: 32,
+            data,
+        })
+    }
+
+    /// Encodes an image as a BMP DIB entry with an AND transparency mask.
+    ///
+    /// When the image has at most 256 colors and each pixel is either fully
+    /// opaque or fully transparent, the DIB is indexed with 1, 4 or 8 bits per
+    /// pixel and a color table. Otherwise it stores 32 bit BGRA pixels.
+    /// Transparent pixels point to a black entry of the color table, which legacy
+    /// renderers combine with the background so that it shows through, so a table
+    /// without black needs room for one more color.
+    ///
+    /// # Errors
+    /// Returns [`Error::InvalidSize`] if the image is empty or larger than 256x256.
+    ///
+    /// # Examples
+    /// ```
+    /// use chinenshichanaka::IconEntry;
+    /// use image::{Rgba, RgbaImage};
+    /// let img = RgbaImage::from_fn(16, 16, |x, _| {
+    ///     if x < 8 { Rgba([255, 0, 0, 255]) } else { Rgba([0, 0, 0, 0]) }
+    /// });
+    /// let entry = IconEntry::bmp(&img)?;
+    /// // One color and transparency fit in one bit per pixel
+    /// assert_eq!((entry.bits_per_pixel, entry.color_count), (1, 2));
+    /// # Ok::<(), chinenshichanaka::Error>(())
+    /// ```
+    pub fn bmp(img: &RgbaImage) -> Result<IconEntry, Error> {
+        let (width, height) = check_entry_size(img)?;
+        let (bits_per_pixel, color_table, xor) = match indexed_palette(img) {
+            Some(palette) if table_entries(img, &palette) <= 256 => {
+                let bits_per_pixel = match table_entries(img, &palette) {
+                    0..=2 => 1,
+                    3..=16 => 4,
+                    _ => 8,
+                };
+                let xor = pack_indexed(img, &palette, bits_per_pixel);
+                (bits_per_pixel, palette, xor)
+            }
+            _ => (32, Vec::new(), pack_bgra(img)),
+        };
+        // The color table is written in full, so that every index has a color
+        let table_size = match bits_per_pixel {
+            32 => 0,
+            bits => 1usize << bits,
+        };
+
+        let mut data = Vec::new();
+        data.extend_from_slice(&DIB_HEADER_SIZE.to_le_bytes());
+        data.extend_from_slice(&(width as i32).to_le_bytes());
+        // The height covers both the XOR bitmap and the AND mask
+        data.extend_from_slice(&(height as i32 * 2).to_le_bytes());
+        data.extend_from_slice(&1u16.to_le_bytes());
+        data.extend_from_slice(&bits_per_pixel.to_le_bytes());
+        // Uncompressed, with the image size, resolution and used colors left to their defaults
+        data.extend_from_slice(&[0; 24]);
+        for index in 0..table_size {
+            let color = color_table.get(index).copied().unwrap_or(Rgba([0; 4]));
+            data.extend_from_slice(&[color[2], color[1], color[0], 0]);
+        }
+        data.extend_from_slice(&xor);
+        data.extend_from_slice(&pack_and_mask(img));
+
+        Ok(IconEntry {
+            width,
+            height,
+            // The directory counts the colors of the table, but 256 does not fit in the byte
+            color_count: if table_size < 256 {
+                table_size as u8
+            } else {
+                0
+            },
+            bits_per_pixel,
+            data,
+        })
+    }
+}
+
+/// Writes ICO entries into an ICO file, in the given order.
+///
+/// # Errors
+/// Returns [`Error::InvalidSize`] if the list of entries is empty.
+///
+/// # Examples
+/// ```
+/// use chinenshichanaka::IconEntry;
+/// use image::RgbaImage;
+/// let entries = vec![
+///     IconEntry::bmp(&RgbaImage::new(16, 16))?,
+///     IconEntry::png(&RgbaImage::new(256, 256))?,
+/// ];
+/// let ico_bytes = chinenshichanaka::write_ico(&entries)?;
+/// assert_eq!(u16::from_le_bytes([ico_bytes[4], ico_bytes[5]]), 2);
+/// # Ok::<(), chinenshichanaka::Error>(())
+/// ```
+pub fn write_ico(entries: &[IconEntry]) -> Result<Vec<u8>, Error> {
+    if entries.is_empty() {
+        return Err(Error::InvalidSize {
+            width: 0,
+            height: 0,
+        });
+    }
+    let mut output = Vec::new();
+    output.extend_from_slice(&0u16.to_le_bytes());
+    // Image type 1 is an icon, 2 would be a cursor
+    output.extend_from_slice(&1u16.to_le_bytes());
+    output.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+
+    let mut offset = ICO_HEADER_SIZE + ICO_ENTRY_SIZE * entries.len();
+    for entry in entries {
+        // The full 256 pixels do not fit in a byte and are stored as zero
+        output.push(entry.width as u8);
+        output.push(entry.height as u8);
+        output.push(entry.color_count);
+        output.push(0);
+        output.extend_from_slice(&1u16.to_le_bytes());
+        output.extend_from_slice(&entry.bits_per_pixel.to_le_bytes());
+        output.extend_from_slice(&(entry.data.len() as u32).to_le_bytes());
+        output.extend_from_slice(&(offset as u32).to_le_bytes());
+        offset += entry.data.len();
+    }
+    for entry in entries {
+        output.extend_from_slice(&entry.data);
+    }
+    Ok(output)
+}
+
+/// Adds entries to the entries of an ICO file, replacing the existing entries of the same size.
+///
+/// The other entries are kept as they are, without encoding them again. The added
+/// entries are placed in the order of their size, before the first larger entry.
+///
+/// # Examples
+/// ```
+/// use chinenshichanaka::IconEntry;
+/// use image::{Rgba, RgbaImage};
+/// let pixel_art = IconEntry::bmp(&RgbaImage::from_pixel(16, 16, Rgba([255, 0, 0, 255])))?;
+/// let mut entries = vec![pixel_art.clone(), IconEntry::bmp(&RgbaImage::new(48, 48))?];
+/// let generated = vec![IconEntry::bmp(&RgbaImage::new(32, 32))?, IconEntry::png(&RgbaImage::new(48, 48))?];
+/// chinenshichanaka::merge_entries(&mut entries, generated);
+/// let sizes: Vec<u32> = entries.iter().map(|entry| entry.width).collect();
+/// assert_eq!(sizes, vec![16, 32, 48]);
+/// assert_eq!(entries[0], pixel_art);
+/// assert!(entries[2].is_png());
+/// # Ok::<(), chinenshichanaka::Error>(())
+/// ```
+pub fn merge_entries(entries: &mut Vec<IconEntry>, new_entries: Vec<IconEntry>) {
+    for new_entry in new_entries {
+        let size = (new_entry.width, new_entry.height);
+        match entries
+            .iter_mut()
+            .find(|entry| (entry.width, entry.height) == size)
+        {
+            Some(entry) => *entry = new_entry,
+            None => {
+                let index = entries
+                    .iter()
+                    .position(|entry| (entry.width, entry.height) > size)
+                    .unwrap_or(entries.len());
+                entries.insert(index, new_entry);
+            }
+        }
+    }
+}
+
+/// Removes the entries of the given square sizes from the entries of an ICO file.
+///
+/// # Returns
+/// The number of removed entries.
+///
+/// # Examples
+/// ```
+/// use chinenshichanaka::IconEntry;
+/// use image::RgbaImage;
+/// let mut entries = vec![IconEntry::bmp(&RgbaImage::new(16, 16))?, IconEntry::bmp(&RgbaImage::new(24, 24))?];
+/// assert_eq!(chinenshichanaka::remove_entries(&mut entries, &[24, 64]), 1);
+/// assert_eq!(entries.len(), 1);
+/// # Ok::<(), chinenshichanaka::Error>(())
+/// ```
+pub fn remove_entries(entries: &mut Vec<IconEntry>, sizes: &[u32]) -> usize {
+    let count = entries.len();
+    entries.retain(|entry| !(entry.width == entry.height && sizes.contains(&entry.width)));
+    count - entries.len()
+}
+
+/// Reads the entries of an ICO or CUR file.
+///
+/// The entries keep their encoded image data as is. The directory of a CUR file
+/// stores the cursor hotspot instead of the bits per pixel, so for cursors the bits
+/// per pixel are read from the image data.
+///
+/// # Errors
+/// Returns [`Error::InvalidIco`] if the header or the directory is malformed, or
+/// an entry points outside the file.
+///
+/// # Examples
+/// ```
+/// use chinenshichanaka::IconEntry;
+/// use image::RgbaImage;
+/// let entries = vec![IconEntry::bmp(&RgbaImage::new(16, 16))?, IconEntry::png(&RgbaImage::new(256, 256))?];
+/// let ico_bytes = chinenshichanaka::write_ico(&entries)?;
+/// assert_eq!(chinenshichanaka::read_ico(&ico_bytes)?, entries);
+/// # Ok::<(), chinenshichanaka::Error>(())
+/// ```
+pub fn read_ico(data: &[u8]) -> Result<Vec<IconEntry>, Error> {
+    let invalid = |reason: &str| Error::InvalidIco(reason.to_string());
+    let header = data
+        .get(..ICO_HEADER_SIZE)
+        .ok_or_else(|| invalid("the file is shorter than the header"))?;
+    let field =
+        |bytes: &[u8], offset: usize| u16::from_le_bytes([bytes[offset], bytes[offset + 1]]);
+    let cursor = match (field(header, 0), field(header, 2)) {
+        (0, 1) => false,
+        (0, 2) => true,
+        _ => return Err(invalid("the header is not of an icon or a cursor")),
+    };
+    let count = field(header, 4) as usize;
+    if count == 0 {
+        return Err(invalid("the directory has no entries"));
+    }
+
+    (0..count)
+        .map(|index| {
+            let start = ICO_HEADER_SIZE + index * ICO_ENTRY_SIZE;
+            let entry = data
+                .get(start..start + ICO_ENTRY_SIZE)
+                .ok_or_else(|| invalid("the directory is truncated"))?;
+            let length = u32::from_le_bytes([entry[8], entry[9], entry[10], entry[11]]) as usize;
+            let offset = u32::from_le_bytes([entry[12], entry[13], entry[14], entry[15]]) as usize;
+            let image_data = offset
+                .checked_add(length)
+                .and_then(|end| data.get(offset..end))
+                .ok_or_else(|| invalid(&format!("entry {} points outside the file", index + 1)))?;
+            // Zero stands for the full 256 pixels
+            let size = |value: u8| if value == 0 { 256 } else { value as u32 };
+            let mut icon_entry = IconEntry {
+                width: size(entry[0]),
+                height: size(entry[1]),
+                color_count: entry[2],
+                bits_per_pixel: field(entry, 6),
+                data: image_data.to_vec(),
+            };
+            if cursor {
+                icon_entry.bits_per_pixel = 0;
+                icon_entry.bits_per_pixel = icon_entry.bit_depth();
+            }
+            Ok(icon_entry)
+        })
+        .collect()
+}
+
+fn check_entry_size(img: &RgbaImage) -> Result<(u32, u32), Error> {
+    let (width, height) = img.dimensions();
+    if !(1..=256).contains(&width) || !(1..=256).contains(&height) {
+        return Err(Error::InvalidSize { width, height });
+    }
+    Ok((width, height))
+}
+
+// The colors of an image in order of appearance, when it can be stored with a color table
+fn indexed_palette(img: &RgbaImage) -> Option<Vec<Rgba<u8>>> {
+    let mut palette: Vec<Rgba<u8>> = Vec::new();
+    for pixel in img.pixels() {
+        match pixel[3] {
+            // Transparent pixels come from the AND mask, not from the color table
+            0 => continue,
+            255 => {}
+            _ => return None,
+        }
+        if !palette.contains(pixel) {
+            if palette.len() == 256 {
+                return None;
+            }
+            palette.push(*pixel);
+        }
+    }
+    Some(palette)
+}
+
+// The colors of the palette, and black for the transparent pixels when it is not one of them
+fn table_entries(img: &RgbaImage, palette: &[Rgba<u8>]) -> usize {
+    let needs_black = !palette.contains(&OPAQUE_BLACK) && img.pixels().any(|pixel| pixel[3] == 0);
+    palette.len() + usize::from(needs_black)
+}
+
+// Length in bytes of a bitmap row, which is padded to a multiple of four bytes
+fn row_length(width: u32, bits_per_pixel: u16) -> usize {
+    (width as usize * bits_per_pixel as usize).div_ceil(32) * 4
+}
+
+// Rows are stored from the bottom up
+fn bottom_up_rows(img: &RgbaImage) -> impl Iterator<Item = u32> {
+    (0..img.height()).rev()
+}
+
+fn pack_indexed(img: &RgbaImage, palette: &[Rgba<u8>], bits_per_pixel: u16) -> Vec<u8> {
+    let length = row_length(img.width(), bits_per_pixel);
+    let per_byte = 8 / bits_per_pixel as u32;
+    // Transparent pixels use black, either from the palette or from the padding of the table
+    let transparent = palette
+        .iter()
+        .position(|color| *color == OPAQUE_BLACK)
+        .unwrap_or(palette.len()) as u8;
+    let mut output = Vec::with_capacity(length * img.height() as usize);
+    for y in bottom_up_rows(img) {
+        let mut row = vec![0u8; length];
+        for x in 0..img.width() {
+            let pixel = img.get_pixel(x, y);
+            let index = match pixel[3] {
+                0 => transparent,
+                _ => palette.iter().position(|color| color == pixel).unwrap_or(0) as u8,
+            };
+            // The leftmost pixel is in the most significant bits
+            let shift = (per_byte - 1 - x % per_byte) * bits_per_pixel as u32;
+            row[(x / per_byte) as usize] |= index << shift;
+        }
+        output.extend_from_slice(&row);
+    }
+    output
+}
+
+fn pack_bgra(img: &RgbaImage) -> Vec<u8> {
+    let mut output = Vec::with_capacity(img.as_raw().len());
+    for y in bottom_up_rows(img) {
+        for x in 0..img.width() {
+            let [red, green, blue, alpha] = img.get_pixel(x, y).0;
+            output.extend_from_slice(&[blue, green, red, alpha]);
+        }
+    }
+    output
+}
+
+// One bit for each pixel, set for the fully transparent ones
+fn pack_and_mask(img: &RgbaImage) -> Vec<u8> {
+    let length = row_length(img.width(), 1);
+    let mut output = Vec::with_capacity(length * img.height() as usize);
+    for y in bottom_up_rows(img) {
+        let mut row = vec![0u8; length];
+        for x in 0..img.width() {
+            if img.get_pixel(x, y)[3] == 0 {
+                row[(x / 8) as usize] |= 0x80 >> (x % 8);
+            }
+        }
+        output.extend_from_slice(&row);
+    }
+    output
+}
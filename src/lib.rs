@@ -1,97 +1,535 @@
-use color_quant::NeuQuant;
-use image::codecs::ico::IcoEncoder;
-use image::{DynamicImage, GenericImageView, ImageEncoder};
-use resvg::tiny_skia::Pixmap;
-use resvg::usvg::{Options, Tree};
+use image::{ColorType, DynamicImage, GenericImageView, ImageError, Rgba, RgbaImage};
+use std::borrow::Cow;
+use std::fmt;
+
+mod background;
+mod color_space;
+mod format;
+mod html;
+mod ico;
+mod package;
+mod palette;
+mod quantize;
+mod quantizer;
+mod resize;
+mod svg;
+mod trim;
+
+pub use background::{
+    background_color, format_hex_color, get_edge_color, parse_hex_color, Background,
+};
+pub use color_space::ColorSpace;
+pub use format::{detect_format, supported_input_formats, InputFormat, OutputFormat};
+pub use html::patch_html;
+pub use ico::{merge_entries, read_ico, remove_entries, write_ico, IconEncoding, IconEntry};
+pub use package::{
+    favicon_package, html_snippet, web_manifest, PackageFile, PackageOptions, ANDROID_CHROME_SIZES,
+    APPLE_TOUCH_ICON_SIZE, PACKAGE_ICO_SIZES,
+};
+pub use palette::{parse_palette, used_colors, write_palette, FixedPalette, PaletteFormat};
+pub use quantize::{
+    choose_palette, quantize, quantize_with, reduce_colors, Dither, QuantizeOptions,
+    DEFAULT_SAMPLE_FACTOR, MAX_SAMPLE_FACTOR,
+};
+pub use quantizer::{
+    MedianCutQuantizer, NeuQuantQuantizer, OctreeQuantizer, PaletteQuantizer, Quantizer,
+};
+pub use resize::{
+    calculate_crop, calculate_size, create_square_image, fit_to_square, get_top_left_color,
+    paste_resized_image, resize_image, resize_to_square, smart_crop, CropRect, Fit,
+};
+pub use svg::{render_svg_to_image, SvgDocument, SVG_REFERENCE_SIZE};
+pub use trim::{
+    add_padding, content_bounds, parse_padding, trim, DEFAULT_TRIM_TOLERANCE, MAX_PADDING,
+};
+
+/// Icon sizes used by default, covering browser tabs, taskbars and high-DPI displays.
+pub const DEFAULT_SIZES: [u32; 7] = [16, 24, 32, 48, 64, 128, 256];
+
+/// Number of palette colors used by default when reducing the colors of an icon.
+pub const DEFAULT_COLORS: usize = 16;
+
+/// Errors that can occur while converting an image to a favicon.
+#[derive(Debug)]
+pub enum Error {
+    /// The SVG data could not be parsed.
+    SvgParse(resvg::usvg::Error),
+    /// The raster image data could not be decoded.
+    Decode(ImageError),
+    /// The output image could not be encoded.
+    Encode(ImageError),
+    /// The image uses a color type that cannot be stored in the output.
+    UnsupportedColorType(ColorType),
+    /// The image dimensions are not usable for the requested operation.
+    InvalidSize { width: u32, height: u32 },
+    /// The requested number of palette colors is not usable.
+    InvalidColorCount(usize),
+    /// The value is not a recognized color.
+    InvalidColor(String),
+    /// The input data is not in any format this build can decode.
+    UnsupportedFormat,
+    /// The palette file is malformed, with the reason.
+    InvalidPalette(String),
+    /// The ICO file is malformed, with the reason.
+    InvalidIco(String),
+    /// The existing web app manifest is malformed, with the reason.
+    InvalidManifest(String),
+    /// The HTML document cannot take the favicon tags, with the reason.
+    InvalidHtml(String),
+    /// The value is not one of the choices of a named option.
+    InvalidOption {
+        name: &'static str,
+        value: String,
+        expected: &'static str,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::SvgParse(err) => write!(f, "Failed to parse SVG. {err}"),
+            Error::Decode(err) => write!(f, "Failed to decode image. {err}"),
+            Error::Encode(err) => write!(f, "Failed to encode image. {err}"),
+            Error::UnsupportedColorType(color) => {
+                write!(
+                    f,
+                    "Unsupported color type {color:?}, expected RGB8 or RGBA8"
+                )
+            }
+            Error::InvalidSize { width, height } => {
+                write!(f, "Invalid image size {width}x{height}")
+            }
+            Error::InvalidColorCount(colors) => {
+                write!(f, "Invalid color count {colors}, expected at least 1")
+            }
+            Error::InvalidOption {
+                name,
+                value,
+                expected,
+            } => {
+                write!(f, "Invalid {name} '{value}', expected {expected}")
+            }
+            Error::UnsupportedFormat => {
+                write!(
+                    f,
+                    "Unrecognized input format, this build can decode {}",
+                    supported_input_formats().join(", ")
+                )
+            }
+            Error::InvalidPalette(reason) => write!(f, "Invalid palette, {reason}"),
+            Error::InvalidIco(reason) => write!(f, "Invalid ICO file, {reason}"),
+            Error::InvalidManifest(reason) => write!(f, "Invalid web app manifest, {reason}"),
+            Error::InvalidHtml(reason) => write!(f, "Invalid HTML file, {reason}"),
+            Error::InvalidColor(value) => {
+                write!(
+                    f,
+                    "Invalid color '{value}', expected a hex color such as #ff8800"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::SvgParse(err) => Some(err),
+            Error::Decode(err) | Error::Encode(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// A specialized `Result` type for the conversion functions of this crate.
+pub type Result<T> = std::result::Result<T, Error>;
 
 /// Converts a `DynamicImage` to ICO format and returns the encoded bytes.
 ///
-/// # Panics
-/// Panics if the image cannot be converted to RGB8 or if encoding fails.
+/// The image must use the RGB8 or RGBA8 color type. Any transparency is preserved
+/// in the ICO file, which is written with the [`IconEncoding::Auto`] policy.
+///
+/// # Errors
+/// Returns [`Error::UnsupportedColorType`] if the image is neither RGB8 nor RGBA8,
+/// [`Error::InvalidSize`] if the image is empty or larger than 256x256, and
+/// [`Error::Encode`] if encoding fails.
 ///
 /// # Examples
 /// ```
 /// use image::DynamicImage;
-/// let img = DynamicImage::new_rgb8(32, 32);
-/// let ico_bytes = chinenshichanaka::convert(img);
+/// let img = DynamicImage::new_rgba8(32, 32);
+/// let ico_bytes = chinenshichanaka::convert(img)?;
 /// assert!(!ico_bytes.is_empty());
+/// # Ok::<(), chinenshichanaka::Error>(())
 /// ```
-pub fn convert(img: DynamicImage) -> Vec<u8> {
-    let mut output: Vec<u8> = Vec::new();
-    let rgb8 = img.as_rgb8().expect("Failed to convert image to RGB8");
-    let raw = rgb8.as_raw();
-    IcoEncoder::new(&mut output)
-        .write_image(
-            raw,
-            img.width(),
-            img.height(),
-            image::ExtendedColorType::Rgb8,
-        )
-        .expect("Failed to encode output image");
-    output
+pub fn convert(img: DynamicImage) -> Result<Vec<u8>> {
+    convert_frames(&[img])
 }
 
-/// Reduces the number of colors in a `DynamicImage` using the NeuQuant algorithm.
+/// Converts several `DynamicImage`s to a single ICO file and returns the encoded bytes.
 ///
-/// # Arguments
-/// * `img` - Reference to the input image.
-/// * `colors` - Number of colors to reduce to.
+/// Each image becomes its own entry in the ICO directory, so the images should
+/// have distinct dimensions, usually ordered from the smallest to the largest.
+/// The entries are encoded with the [`IconEncoding::Auto`] policy, see
+/// [`convert_frames_with`] for choosing another one.
 ///
-/// # Returns
-/// A new `DynamicImage` with reduced colors.
+/// # Errors
+/// Returns the same errors as [`convert`] for any of the images, and
+/// [`Error::InvalidSize`] if the list of images is empty.
 ///
 /// # Examples
 /// ```
-/// use image::{DynamicImage, GenericImageView, Rgba};
-/// let img = DynamicImage::new_rgba8(10, 10);
-/// let reduced = chinenshichanaka::reduce_colors(&img, 4);
-/// assert_eq!(reduced.dimensions(), (10, 10));
+/// use image::DynamicImage;
+/// let images = vec![
+///     DynamicImage::new_rgba8(16, 16),
+///     DynamicImage::new_rgba8(32, 32),
+///     DynamicImage::new_rgba8(48, 48),
+/// ];
+/// let ico_bytes = chinenshichanaka::convert_frames(&images)?;
+/// // The image count is stored in the ICO header
+/// assert_eq!(u16::from_le_bytes([ico_bytes[4], ico_bytes[5]]), 3);
+/// # Ok::<(), chinenshichanaka::Error>(())
 /// ```
-pub fn reduce_colors(img: &DynamicImage, colors: usize) -> DynamicImage {
-    let (width, height) = img.dimensions();
-    let pixels = img.to_rgba8().into_raw();
-    let quantizer = NeuQuant::new(1, colors, &pixels);
-    let mut indices = vec![0; pixels.len() / 4];
-    let palette = quantizer.color_map_rgb();
-    for (i, chunk) in pixels.chunks(4).enumerate() {
-        indices[i] = quantizer.index_of(chunk);
+pub fn convert_frames(images: &[DynamicImage]) -> Result<Vec<u8>> {
+    convert_frames_with(images, IconEncoding::default())
+}
+
+/// Converts several `DynamicImage`s to a single ICO file, encoding each entry
+/// as PNG or BMP according to the encoding policy.
+///
+/// BMP entries are indexed with a color table when the image has at most 256 colors
+/// and no partial transparency, see [`IconEntry::bmp`].
+///
+/// # Errors
+/// Returns the same errors as [`convert_frames`].
+///
+/// # Examples
+/// ```
+/// use chinenshichanaka::IconEncoding;
+/// use image::DynamicImage;
+/// let images = vec![DynamicImage::new_rgba8(16, 16), DynamicImage::new_rgba8(32, 32)];
+/// let ico_bytes = chinenshichanaka::convert_frames_with(&images, IconEncoding::Png)?;
+/// // The data of the first entry starts after the header and the two directory entries
+/// assert_eq!(&ico_bytes[38..42], b"\x89PNG");
+/// # Ok::<(), chinenshichanaka::Error>(())
+/// ```
+pub fn convert_frames_with(images: &[DynamicImage], encoding: IconEncoding) -> Result<Vec<u8>> {
+    if images.is_empty() {
+        return Err(Error::InvalidSize {
+            width: 0,
+            height: 0,
+        });
+    }
+    let entries = images
+        .iter()
+        .map(|img| IconEntry::encode(&to_rgba8(img)?, encoding))
+        .collect::<Result<Vec<IconEntry>>>()?;
+    write_ico(&entries)
+}
+
+// Only the color types produced by the conversion pipeline are accepted
+fn to_rgba8(img: &DynamicImage) -> Result<RgbaImage> {
+    match img {
+        DynamicImage::ImageRgb8(_) => Ok(img.to_rgba8()),
+        DynamicImage::ImageRgba8(rgba8) => Ok(rgba8.clone()),
+        _ => Err(Error::UnsupportedColorType(img.color())),
     }
-    let mut quantized_pixels = Vec::with_capacity(pixels.len());
-    for &index in &indices {
-        quantized_pixels.extend_from_slice(&palette[index * 3..index * 3 + 3]);
+}
+
+/// Options for producing a favicon with [`favicon_from_bytes`] or [`favicon_images`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct FaviconOptions {
+    /// Sizes of the square icons, each between 1 and 256.
+    pub sizes: Vec<u32>,
+    /// Palette reduction applied to each icon, or `None` to keep true color.
+    pub quantize: Option<QuantizeOptions>,
+    /// Fill of the areas not covered by the resized image.
+    pub background: Background,
+    /// How the image is fitted into the square icons.
+    pub fit: Fit,
+    /// When set, uniform margins within this color tolerance are removed before resizing.
+    pub trim: Option<u8>,
+    /// Margin added around the image before resizing, as a fraction of its longer side.
+    pub padding: f32,
+    /// How the icons are encoded in the ICO file.
+    pub encoding: IconEncoding,
+}
+
+impl Default for FaviconOptions {
+    fn default() -> Self {
+        FaviconOptions {
+            sizes: DEFAULT_SIZES.to_vec(),
+            quantize: Some(QuantizeOptions::default()),
+            background: Background::default(),
+            fit: Fit::default(),
+            trim: None,
+            padding: 0.0,
+            encoding: IconEncoding::default(),
+        }
     }
-    DynamicImage::ImageRgb8(image::RgbImage::from_raw(width, height, quantized_pixels).unwrap())
 }
 
-/// Renders SVG data to a 32x32 `DynamicImage` using resvg.
+/// Decodes an input image, rendering it with resvg when it is SVG or SVGZ.
+///
+/// The format is detected from the content with [`detect_format`]. SVG documents
+/// are rendered so that their longer side is [`SVG_REFERENCE_SIZE`] pixels.
 ///
 /// # Arguments
-/// * `input` - SVG data as a byte slice.
+/// * `input` - Raster image, SVG or SVGZ data as a byte slice.
+///
+/// # Errors
+/// Returns [`Error::UnsupportedFormat`] if the format is not recognized,
+/// [`Error::Decode`] if a raster image cannot be decoded, and [`Error::SvgParse`]
+/// if an SVG document cannot be parsed.
+///
+/// # Examples
+/// ```
+/// use image::GenericImageView;
+/// let svg = br#"<svg width='32' height='16' xmlns='http://www.w3.org/2000/svg'/>"#;
+/// let img = chinenshichanaka::decode_image(svg)?;
+/// assert_eq!(img.dimensions(), (512, 256));
+/// # Ok::<(), chinenshichanaka::Error>(())
+/// ```
+pub fn decode_image(input: &[u8]) -> Result<DynamicImage> {
+    match detect_format(input)? {
+        InputFormat::Raster(format) => {
+            image::load_from_memory_with_format(input, format).map_err(Error::Decode)
+        }
+        InputFormat::Svg | InputFormat::Svgz => render_svg_to_image(input, SVG_REFERENCE_SIZE),
+    }
+}
+
+// Removes the margins of an image and adds the requested padding, as configured
+fn trim_and_pad<'a>(
+    img: &'a DynamicImage,
+    background: Rgba<u8>,
+    options: &FaviconOptions,
+) -> Result<Cow<'a, DynamicImage>> {
+    let trimmed = match options.trim {
+        Some(tolerance) => Cow::Owned(trim(img, tolerance)),
+        None => Cow::Borrowed(img),
+    };
+    if options.padding > 0.0 {
+        Ok(Cow::Owned(add_padding(
+            &trimmed,
+            options.padding,
+            background,
+        )?))
+    } else {
+        Ok(trimmed)
+    }
+}
+
+// Reduces the colors of an icon as configured, or only normalizes its color type
+fn reduce(img: DynamicImage, options: &FaviconOptions) -> Result<DynamicImage> {
+    match &options.quantize {
+        Some(quantize_options) => quantize(&img, quantize_options),
+        None => Ok(DynamicImage::ImageRgba8(img.into_rgba8())),
+    }
+}
+
+/// Largest size of the icons in an ICO file.
+const MAX_ICON_SIZE: u32 = 256;
+
+fn check_size(size: u32, max_size: u32) -> Result<()> {
+    if !(1..=max_size).contains(&size) {
+        return Err(Error::InvalidSize {
+            width: size,
+            height: size,
+        });
+    }
+    Ok(())
+}
+
+/// Resizes an image to each of the requested square sizes and reduces its colors.
+///
+/// The background color is resolved from the original image, before the optional
+/// trimming, and it is also used for filling the optional padding.
+///
+/// This is the same pipeline the command line tool uses for every icon in the ICO file.
+///
+/// # Errors
+/// Returns [`Error::InvalidSize`] if the image is empty or for sizes outside `1..=256`,
+/// and the errors of [`quantize`].
+///
+/// # Examples
+/// ```
+/// use image::{DynamicImage, GenericImageView};
+/// use chinenshichanaka::FaviconOptions;
+/// let img = DynamicImage::new_rgba8(100, 50);
+/// let options = FaviconOptions { sizes: vec![16, 32], ..Default::default() };
+/// let images = chinenshichanaka::favicon_images(&img, &options)?;
+/// assert_eq!(images[1].dimensions(), (32, 32));
+/// # Ok::<(), chinenshichanaka::Error>(())
+/// ```
+pub fn favicon_images(img: &DynamicImage, options: &FaviconOptions) -> Result<Vec<DynamicImage>> {
+    square_images(img, options, MAX_ICON_SIZE)
+}
+
+// The pipeline of favicon_images, for images that may be larger than icons
+fn square_images(
+    img: &DynamicImage,
+    options: &FaviconOptions,
+    max_size: u32,
+) -> Result<Vec<DynamicImage>> {
+    // Without pixels there is no background to sample nor content to resize
+    let (width, height) = img.dimensions();
+    if width == 0 || height == 0 {
+        return Err(Error::InvalidSize { width, height });
+    }
+    let background = background_color(img, options.background);
+    let img = trim_and_pad(img, background, options)?;
+    options
+        .sizes
+        .iter()
+        .map(|&size| {
+            check_size(size, max_size)?;
+            reduce(fit_to_square(&img, size, background, options.fit), options)
+        })
+        .collect()
+}
+
+/// Renders an SVG separately for each of the requested square sizes and reduces its colors.
+///
+/// Instead of rasterizing once and resizing, the document is rendered at the scale
+/// where, after the optional trimming and padding, the fitted image matches the icon
+/// size. The background color and the content for trimming are sampled from a
+/// rendering at [`SVG_REFERENCE_SIZE`].
+///
+/// # Errors
+/// Returns [`Error::SvgParse`] if the SVG data cannot be parsed, and the errors of
+/// [`favicon_images`].
+///
+/// # Examples
+/// ```
+/// use image::{GenericImageView, Rgba};
+/// use chinenshichanaka::FaviconOptions;
+/// let svg = br#"<svg width='1024' height='1024' xmlns='http://www.w3.org/2000/svg'><rect x='512' width='512' height='1024' style='fill:rgb(255,0,0);'/></svg>"#;
+/// let options = FaviconOptions { sizes: vec![16], ..Default::default() };
+/// let images = chinenshichanaka::favicon_images_from_svg(svg, &options)?;
+/// // The whole document is scaled down, not only its top-left corner
+/// assert_eq!(images[0].get_pixel(12, 8), Rgba([255, 0, 0, 255]));
+/// # Ok::<(), chinenshichanaka::Error>(())
+/// ```
+pub fn favicon_images_from_svg(
+    input: &[u8],
+    options: &FaviconOptions,
+) -> Result<Vec<DynamicImage>> {
+    square_images_from_svg(&SvgDocument::parse(input)?, options, MAX_ICON_SIZE)
+}
+
+/// Renders a parsed SVG document separately for each of the requested square sizes and
+/// reduces its colors.
 ///
-/// # Returns
-/// A `DynamicImage` containing the rendered SVG.
+/// The same as [`favicon_images_from_svg`], for a document that is already parsed and
+/// rendered at the reference size, for example to inspect it first.
 ///
-/// # Panics
-/// Panics if SVG parsing or image creation fails.
+/// # Errors
+/// Returns the errors of [`favicon_images`].
 ///
 /// # Examples
 /// ```
+/// use chinenshichanaka::{FaviconOptions, SvgDocument};
 /// use image::GenericImageView;
+/// let svg = br#"<svg width='64' height='64' xmlns='http://www.w3.org/2000/svg'/>"#;
+/// let document = SvgDocument::parse(svg)?;
+/// let options = FaviconOptions { sizes: vec![16, 32], ..Default::default() };
+/// let images = chinenshichanaka::favicon_images_from_svg_document(&document, &options)?;
+/// assert_eq!(images[1].dimensions(), (32, 32));
+/// # Ok::<(), chinenshichanaka::Error>(())
+/// ```
+pub fn favicon_images_from_svg_document(
+    document: &SvgDocument,
+    options: &FaviconOptions,
+) -> Result<Vec<DynamicImage>> {
+    square_images_from_svg(document, options, MAX_ICON_SIZE)
+}
+
+// The pipeline of favicon_images_from_svg, for images that may be larger than icons
+fn square_images_from_svg(
+    document: &SvgDocument,
+    options: &FaviconOptions,
+    max_size: u32,
+) -> Result<Vec<DynamicImage>> {
+    let reference = document.reference();
+    let background = background_color(reference, options.background);
+    let (reference_width, reference_height) =
+        trim_and_pad(reference, background, options)?.dimensions();
+    // The side of the prepared image that ends up matching the icon size
+    let fitted_side = match options.fit {
+        Fit::Contain | Fit::Stretch => reference_width.max(reference_height),
+        Fit::Cover | Fit::Smart => reference_width.min(reference_height),
+    };
+    options
+        .sizes
+        .iter()
+        .map(|&size| {
+            check_size(size, max_size)?;
+            let scale = size as f64 / fitted_side.max(1) as f64;
+            // Tiny content in a large document would otherwise need a huge rendering
+            let render_size = (SVG_REFERENCE_SIZE as f64 * scale).ceil() as u32;
+            let rendered = document.render(render_size.clamp(1, svg::MAX_RENDER_SIZE))?;
+            let img = trim_and_pad(&rendered, background, options)?;
+            reduce(fit_to_square(&img, size, background, options.fit), options)
+        })
+        .collect()
+}
+
+/// Converts an input image to a favicon and returns the encoded ICO bytes.
+///
+/// Runs [`decode_image`], [`favicon_images`] and [`write_ico`] in sequence,
+/// using [`favicon_images_from_svg`] instead for SVG and SVGZ input.
+///
+/// # Errors
+/// Returns the errors of the individual stages.
+///
+/// # Examples
+/// ```
+/// use chinenshichanaka::FaviconOptions;
 /// let svg = br#"<svg width='32' height='32' xmlns='http://www.w3.org/2000/svg'><rect width='32' height='32' style='fill:rgb(255,0,0);'/></svg>"#;
-/// let img = chinenshichanaka::render_svg_to_image(svg);
-/// assert_eq!(img.dimensions(), (32, 32));
-/// ```
-pub fn render_svg_to_image(input: &[u8]) -> DynamicImage {
-    let opt = Options::default();
-    let rtree = Tree::from_data(input, &opt).expect("Failed to parse SVG");
-    let mut pixmap = Pixmap::new(32, 32).expect("Failed to create pixmap");
-    resvg::render(
-        &rtree,
-        resvg::tiny_skia::Transform::default(),
-        &mut pixmap.as_mut(),
-    );
-    DynamicImage::ImageRgba8(
-        image::RgbaImage::from_raw(32, 32, pixmap.data().to_vec())
-            .expect("Failed to create image from pixmap"),
-    )
+/// let ico_bytes = chinenshichanaka::favicon_from_bytes(svg, &FaviconOptions::default())?;
+/// // All the default sizes are included
+/// assert_eq!(u16::from_le_bytes([ico_bytes[4], ico_bytes[5]]), 7);
+/// # Ok::<(), chinenshichanaka::Error>(())
+/// ```
+pub fn favicon_from_bytes(input: &[u8], options: &FaviconOptions) -> Result<Vec<u8>> {
+    write_ico(&favicon_entries(input, options)?)
+}
+
+// Decodes an input image and runs the pipeline for raster or SVG images, as detected
+pub(crate) fn square_images_from_bytes(
+    input: &[u8],
+    options: &FaviconOptions,
+    max_size: u32,
+) -> Result<Vec<DynamicImage>> {
+    match detect_format(input)?.is_svg() {
+        true => square_images_from_svg(&SvgDocument::parse(input)?, options, max_size),
+        false => square_images(&decode_image(input)?, options, max_size),
+    }
+}
+
+/// Converts an input image to the encoded ICO entries of a favicon, one for each size.
+///
+/// The entries can be added to an existing ICO file with [`merge_entries`].
+///
+/// # Errors
+/// Returns the errors of the individual stages.
+///
+/// # Examples
+/// ```
+/// use chinenshichanaka::FaviconOptions;
+/// let svg = br#"<svg width='32' height='32' xmlns='http://www.w3.org/2000/svg'/>"#;
+/// let options = FaviconOptions {
+///     sizes: vec![32, 256],
+///     ..Default::default()
+/// };
+/// let entries = chinenshichanaka::favicon_entries(svg, &options)?;
+/// assert_eq!(entries.len(), 2);
+/// assert!(entries[1].is_png());
+/// # Ok::<(), chinenshichanaka::Error>(())
+/// ```
+pub fn favicon_entries(input: &[u8], options: &FaviconOptions) -> Result<Vec<IconEntry>> {
+    square_images_from_bytes(input, options, MAX_ICON_SIZE)?
+        .iter()
+        .map(|img| IconEntry::encode(&to_rgba8(img)?, options.encoding))
+        .collect()
 }
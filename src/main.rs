@@ -1,306 +1,1696 @@
-use chinenshichanaka::{convert, reduce_colors, render_svg_to_image};
-use clap::Parser;
-use image::{imageops, DynamicImage, GenericImageView, Pixel, Rgba};
+use chinenshichanaka::{
+    background_color, content_bounds, convert_frames_with, decode_image, detect_format,
+    favicon_entries, favicon_images, favicon_images_from_svg_document, favicon_package,
+    format_hex_color, html_snippet, merge_entries, parse_hex_color, parse_padding, parse_palette,
+    patch_html, read_ico, remove_entries, used_colors, write_ico, write_palette, Background,
+    ColorSpace, Dither, FaviconOptions, Fit, IconEncoding, IconEntry, InputFormat, OutputFormat,
+    PackageOptions, PaletteFormat, QuantizeOptions, Quantizer, SvgDocument, DEFAULT_COLORS,
+    DEFAULT_SAMPLE_FACTOR, DEFAULT_SIZES, DEFAULT_TRIM_TOLERANCE, MAX_SAMPLE_FACTOR,
+    PACKAGE_ICO_SIZES,
+};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand};
+use image::{DynamicImage, GenericImageView, ImageFormat};
+use notify::event::ModifyKind;
+use notify::{EventKind, RecursiveMode, Watcher};
+use rayon::prelude::*;
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::collections::HashSet;
+use std::fmt;
 use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
 use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, OnceLock};
+use std::time::{Duration, Instant};
 
 // Input file support depends on the set of features in Cargo.toml
 
 // https://docs.rs/clap/latest/clap/_derive/index.html
 #[derive(Parser, Debug)]
-#[command(version, about, author, long_about = None)]
+#[command(
+    version,
+    about,
+    author,
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Args {
-    /// The input image file. Supports SVG and many other formats, see
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// The input image files, directories or glob patterns such as "logos/*.svg", followed by
+    /// the output file unless it is given with --output. "-" reads the standard input and
+    /// writes the standard output. Supports SVG, SVGZ and many other formats, see
     /// https://github.com/image-rs/image?tab=readme-ov-file#supported-image-formats
-    #[arg(index = 1)]
-    input: String,
+    /// The format is detected from the file content, not from its name
+    /// The inputs may also be given in the configuration file
+    #[arg(index = 1, value_name = "INPUT")]
+    paths: Vec<String>,
 
-    /// The output file which should end with ".ico"
+    /// The output file which should end with ".ico", by default "favicon.ico"
     /// https://en.wikipedia.org/wiki/ICO_(file_format)
-    #[arg(index = 2, default_value = "favicon.ico")]
-    output: String,
+    /// With several inputs, a template such as "out/{stem}.ico", where "{stem}" is the name
+    /// of the input file without its suffix and "{dir}" is the directory of the input file
+    #[arg(short, long)]
+    output: Option<String>,
+
+    /// Format of the output: "ico" with all the sizes, or "png" with the largest size only.
+    /// Chosen by the suffix of the output file by default, but needed for the standard output
+    #[arg(long)]
+    format: Option<OutputFormat>,
+
+    /// Comma separated list of icon sizes to include in the output, each between 1 and 256
+    #[arg(
+        short,
+        long,
+        value_delimiter = ',',
+        value_parser = clap::value_parser!(u32).range(1..=256),
+        default_values_t = DEFAULT_SIZES
+    )]
+    sizes: Vec<u32>,
+
+    #[command(flatten)]
+    image: ImageArgs,
+
+    /// Write the colors of the reduced icons, as saved in the output, to this file in the format
+    /// given by its suffix. With several inputs, a template like the output file
+    #[arg(long)]
+    export_palette: Option<String>,
+
+    /// Number of inputs converted in parallel, by default one for each processor core
+    #[arg(short, long)]
+    jobs: Option<usize>,
+
+    /// Keep running and convert the inputs again whenever they are saved
+    #[arg(short, long)]
+    watch: bool,
+
+    /// Configuration file with the default options, instead of "chinenshichanaka.toml" or the
+    /// [package.metadata.chinenshichanaka] table of "Cargo.toml" in the working directory
+    #[arg(long, global = true)]
+    config: Option<String>,
+
+    /// Named preset of the configuration file whose options are used over the top-level ones
+    #[arg(long, global = true)]
+    preset: Option<String>,
 
     /// Verbose mode gives more details about the conversion process
     #[arg(short, long)]
     verbose: bool,
 }
 
-/// Entry point for the CLI tool. Parses arguments and runs the conversion process.
-fn main() {
-    let args: Args = Args::parse();
-    if args.verbose {
-        println!("Converting '{}' to '{}'", args.input, args.output);
+/// Output file used when none is given.
+const DEFAULT_OUTPUT: &str = "favicon.ico";
+
+/// How long the watch mode waits for the input files to stay unchanged before converting them.
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Path that stands for the standard input or the standard output.
+const STDIO_PATH: &str = "-";
+
+// Human readable messages go to stderr while the output itself is written to stdout
+static MESSAGES_TO_STDERR: AtomicBool = AtomicBool::new(false);
+
+// Prints a human readable message, to stdout unless it carries the output
+macro_rules! message {
+    ($($arg:tt)*) => {
+        if MESSAGES_TO_STDERR.load(Ordering::Relaxed) {
+            eprintln!($($arg)*);
+        } else {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// Configuration file looked up in the working directory.
+const CONFIG_FILE: &str = "chinenshichanaka.toml";
+
+/// Settings of a configuration file, either at the top level or in a named preset.
+/// The keys are the long names of the command line options.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct Settings {
+    #[serde(alias = "input")]
+    inputs: Option<OneOrMany>,
+    output: Option<String>,
+    format: Option<String>,
+    sizes: Option<Vec<u32>>,
+    background: Option<String>,
+    fit: Option<String>,
+    trim: Option<bool>,
+    trim_tolerance: Option<u8>,
+    padding: Option<String>,
+    colors: Option<u32>,
+    quantizer: Option<String>,
+    sample_factor: Option<i32>,
+    dither: Option<String>,
+    color_space: Option<String>,
+    palette: Option<String>,
+    encoding: Option<String>,
+    export_palette: Option<String>,
+    jobs: Option<usize>,
+    presets: BTreeMap<String, Settings>,
+}
+
+/// A single value or a list of them, such as the inputs of a configuration file.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Settings {
+    // The values of the preset take precedence over the top-level values
+    fn with_preset(self, preset: Settings) -> Settings {
+        Settings {
+            inputs: preset.inputs.or(self.inputs),
+            output: preset.output.or(self.output),
+            format: preset.format.or(self.format),
+            sizes: preset.sizes.or(self.sizes),
+            background: preset.background.or(self.background),
+            fit: preset.fit.or(self.fit),
+            trim: preset.trim.or(self.trim),
+            trim_tolerance: preset.trim_tolerance.or(self.trim_tolerance),
+            padding: preset.padding.or(self.padding),
+            colors: preset.colors.or(self.colors),
+            quantizer: preset.quantizer.or(self.quantizer),
+            sample_factor: preset.sample_factor.or(self.sample_factor),
+            dither: preset.dither.or(self.dither),
+            color_space: preset.color_space.or(self.color_space),
+            palette: preset.palette.or(self.palette),
+            encoding: preset.encoding.or(self.encoding),
+            export_palette: preset.export_palette.or(self.export_palette),
+            jobs: preset.jobs.or(self.jobs),
+            presets: BTreeMap::new(),
+        }
     }
+}
+
+/// Options for rendering and encoding the icons, shared by the conversion and the edit subcommand.
+#[derive(clap::Args, Debug)]
+struct ImageArgs {
+    /// Background fill for the areas not covered by the image: "transparent",
+    /// a hex color such as "#ff8800", "top-left" for the color of the top-left pixel,
+    /// or "edge" for the most common color along the borders of the image
+    #[arg(short, long, default_value_t = Background::TopLeft)]
+    background: Background,
+
+    /// How the image is fitted into the square: "contain" scales it to fit within the square,
+    /// "cover" fills the square and crops the overflow, "stretch" ignores the aspect ratio,
+    /// and "smart" crops the window with the most visual detail
+    #[arg(short, long, default_value_t = Fit::Contain)]
+    fit: Fit,
+
+    /// Remove uniform margins, either transparent or of the border color, before resizing
+    #[arg(short, long)]
+    trim: bool,
+
+    /// Largest difference per color channel still counted as margin when trimming
+    #[arg(long, default_value_t = DEFAULT_TRIM_TOLERANCE)]
+    trim_tolerance: u8,
+
+    /// Margin added around the image before resizing, as a percentage of its longer side
+    #[arg(short, long, value_parser = parse_padding, default_value = "0%")]
+    padding: f32,
+
+    /// Number of palette colors each icon is reduced to, between 1 and 256,
+    /// or 0 to skip the color reduction and keep true color icons
+    #[arg(
+        short,
+        long,
+        value_parser = clap::value_parser!(u32).range(0..=256),
+        default_value_t = DEFAULT_COLORS as u32
+    )]
+    colors: u32,
+
+    /// Algorithm choosing the reduced palette: "neuquant", "median-cut" or "octree"
+    #[arg(short, long, default_value_t = Quantizer::NeuQuant)]
+    quantizer: Quantizer,
+
+    /// Sample factor of the NeuQuant color quantizer, from 1 which samples every pixel for the best
+    /// quality, up to 30 which is the fastest
+    #[arg(
+        long,
+        value_parser = clap::value_parser!(i32).range(1..=MAX_SAMPLE_FACTOR as i64),
+        default_value_t = DEFAULT_SAMPLE_FACTOR
+    )]
+    sample_factor: i32,
+
+    /// Dithering used when mapping the pixels to the reduced palette: "none",
+    /// "floyd-steinberg" for error diffusion, or "ordered" for a Bayer matrix pattern
+    #[arg(short, long, default_value_t = Dither::None)]
+    dither: Dither,
+
+    /// Color space for choosing the palette and finding the nearest palette color of each
+    /// pixel: "srgb", or the perceptual "lab" and "oklab" that keep brand colors closer
+    /// to the original
+    #[arg(long, default_value_t = ColorSpace::Srgb)]
+    color_space: ColorSpace,
+
+    /// Palette file with the exact colors the icons are mapped onto, instead of a palette
+    /// chosen for the image: a GIMP ".gpl", an Adobe ".act", or a text file with one hex color per line
+    #[arg(long)]
+    palette: Option<String>,
+
+    /// Encoding of the icons in the ICO file: "png", "bmp" for the widest compatibility,
+    /// "auto" for PNG at 256 pixels and BMP for the smaller sizes, or "smallest" for
+    /// whichever takes fewer bytes
+    #[arg(short, long, default_value_t = IconEncoding::Auto)]
+    encoding: IconEncoding,
+}
+
+impl ImageArgs {
+    /// Builds the favicon options with the given sizes, reading the palette file when one is given.
+    fn favicon_options(&self, mut sizes: Vec<u32>) -> Result<FaviconOptions, CliError> {
+        let palette = match &self.palette {
+            Some(path) => Some(read_palette(path)?),
+            None => None,
+        };
+        sizes.sort_unstable();
+        sizes.dedup();
+        Ok(FaviconOptions {
+            sizes,
+            background: self.background,
+            fit: self.fit,
+            trim: self.trim.then_some(self.trim_tolerance),
+            padding: self.padding,
+            encoding: self.encoding,
+            // A fixed palette is always mapped onto, even without other color reduction
+            quantize: (self.colors > 0 || palette.is_some()).then_some(QuantizeOptions {
+                colors: self.colors as usize,
+                quantizer: self.quantizer,
+                sample_factor: self.sample_factor,
+                dither: self.dither,
+                color_space: self.color_space,
+                palette,
+            }),
+        })
+    }
+}
+
+/// Tasks on existing ICO files, instead of converting an image.
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// List the entries of an ICO file with their size, bit depth, encoding, palette size and bytes
+    Inspect {
+        /// The ICO or CUR file to inspect
+        file: String,
+
+        /// Print the entries as JSON instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+    /// Write each entry of an ICO file as a PNG file named after the file and the entry size
+    Extract {
+        /// The ICO or CUR file to extract
+        file: String,
+
+        /// The directory for the PNG files, created when missing
+        #[arg(default_value = ".")]
+        output_dir: String,
+    },
+    /// Add, replace or remove entries of an ICO file, keeping the other entries as they are
+    Edit {
+        /// The ICO file to edit
+        file: String,
+
+        /// The image rendered into the entries of the given sizes, replacing the entries of the same size,
+        /// "-" reads it from the standard input
+        #[arg(long, requires = "sizes")]
+        source: Option<String>,
+
+        /// Comma separated list of the sizes rendered from the source image, each between 1 and 256
+        #[arg(
+            short,
+            long,
+            value_delimiter = ',',
+            value_parser = clap::value_parser!(u32).range(1..=256),
+            requires = "source"
+        )]
+        sizes: Vec<u32>,
+
+        /// Comma separated list of the sizes whose entries are removed
+        #[arg(
+            short,
+            long,
+            value_delimiter = ',',
+            value_parser = clap::value_parser!(u32).range(1..=256)
+        )]
+        remove: Vec<u32>,
+
+        /// The file for the edited icon, instead of replacing the ICO file
+        #[arg(short, long)]
+        output: Option<String>,
+
+        #[command(flatten)]
+        image: ImageArgs,
+
+        /// Verbose mode gives more details about the edited entries
+        #[arg(short, long)]
+        verbose: bool,
+    },
+    /// Write the complete set of favicon files for a web site into a directory, and print the
+    /// HTML tags linking to them
+    Package {
+        /// The source image, preferably SVG which is also included as "favicon.svg"
+        source: String,
+
+        /// The directory for the files, created when missing
+        #[arg(default_value = ".")]
+        output_dir: String,
+
+        /// Comma separated list of the icon sizes in "favicon.ico", each between 1 and 256
+        #[arg(
+            short,
+            long,
+            value_delimiter = ',',
+            value_parser = clap::value_parser!(u32).range(1..=256),
+            default_values_t = PACKAGE_ICO_SIZES
+        )]
+        sizes: Vec<u32>,
+
+        /// URL path the files are served from, used in the manifest and the HTML tags
+        #[arg(long, default_value = "/")]
+        base_url: String,
+
+        /// Name of the site in the web app manifest
+        #[arg(long)]
+        name: Option<String>,
+
+        /// Color of the browser interface around the site, as a hex color [default: white]
+        #[arg(long, value_parser = parse_hex_color)]
+        theme_color: Option<image::Rgba<u8>>,
+
+        /// Color of the splash screen of the installed web app, as a hex color [default: white]
+        #[arg(long, value_parser = parse_hex_color)]
+        background_color: Option<image::Rgba<u8>>,
+
+        /// Derive the colors that are not given from the dominant and the edge colors of the source
+        #[arg(long)]
+        derive_colors: bool,
+
+        /// HTML file whose favicon tags are replaced with the ones of the package, can be repeated
+        #[arg(long, value_name = "FILE")]
+        html: Vec<String>,
+
+        #[command(flatten)]
+        image: ImageArgs,
+
+        /// Verbose mode gives more details about the written files
+        #[arg(short, long)]
+        verbose: bool,
+    },
+}
+
+// Exit codes, one for each class of failure.
+// Invalid arguments use the same code as the argument parsing errors reported by clap.
+/// The command line arguments are invalid.
+const EXIT_INVALID_ARGUMENTS: i32 = 2;
+/// The input file could not be read.
+const EXIT_READ_INPUT: i32 = 3;
+/// The input raster image could not be decoded.
+const EXIT_DECODE: i32 = 4;
+/// The input SVG could not be parsed.
+const EXIT_SVG_PARSE: i32 = 5;
+/// The output file could not be written.
+const EXIT_WRITE_OUTPUT: i32 = 6;
+/// The resized images could not be encoded to ICO.
+const EXIT_ENCODE: i32 = 7;
+
+/// Failures of the CLI, each mapped to its own exit code.
+#[derive(Debug)]
+pub enum CliError {
+    InvalidArguments(String),
+    ReadInput(io::Error),
+    Decode(chinenshichanaka::Error),
+    SvgParse(chinenshichanaka::Error),
+    WriteOutput(io::Error),
+    Encode(chinenshichanaka::Error),
+    /// Some of the inputs of a batch conversion failed, with the exit code of the first failure.
+    Batch {
+        failed: usize,
+        total: usize,
+        exit_code: i32,
+    },
+}
 
-    match args.output.ends_with(".ico") {
-        true => {
-            convert_paths(&args.input, &args.output, args.verbose);
+impl CliError {
+    /// Returns the process exit code matching the class of the failure.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CliError::InvalidArguments(_) => EXIT_INVALID_ARGUMENTS,
+            CliError::ReadInput(_) => EXIT_READ_INPUT,
+            CliError::Decode(_) => EXIT_DECODE,
+            CliError::SvgParse(_) => EXIT_SVG_PARSE,
+            CliError::WriteOutput(_) => EXIT_WRITE_OUTPUT,
+            CliError::Encode(_) => EXIT_ENCODE,
+            CliError::Batch { exit_code, .. } => *exit_code,
         }
-        false => {
-            eprintln!("The output file have to use the 'ico' suffix");
-            process::exit(1);
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::InvalidArguments(message) => write!(f, "{message}"),
+            CliError::ReadInput(err) => write!(f, "Error reading the input image. {err}"),
+            CliError::Decode(err) => write!(f, "Error decoding the input image. {err}"),
+            CliError::SvgParse(err) => write!(f, "Error rendering the input SVG. {err}"),
+            CliError::WriteOutput(err) => write!(f, "Error saving the output image. {err}"),
+            CliError::Encode(err) => write!(f, "Error encoding the output image. {err}"),
+            CliError::Batch { failed, total, .. } => write!(
+                f,
+                "Converted {} of {total} inputs, {failed} failed",
+                total - failed
+            ),
         }
     }
 }
 
-/// Converts an input image file to an ICO file, optionally printing verbose output.
+/// Entry point for the CLI tool. Parses arguments and runs the conversion process.
+fn main() {
+    let matches = Args::command().get_matches();
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    if let Err(err) = configure(&mut args, &matches).and_then(|()| run(args)) {
+        eprintln!("{err}");
+        process::exit(err.exit_code());
+    }
+}
+
+/// Fills in the options not given on the command line from the configuration file.
 ///
-/// # Arguments
-/// * `input` - Path to the input image file (SVG or raster).
-/// * `output` - Path to the output ICO file.
-/// * `verbosity` - Whether to print verbose output.
-pub fn convert_paths(input: &str, output: &str, verbosity: bool) {
-    // Read the content of the file into a byte vector
-    let input_buffer: Vec<u8> = match fs::read(input) {
-        Ok(buffer) => buffer,
-        Err(err) => {
-            eprintln!("Error reading the input image. {err}");
-            return;
+/// The `edit` and `package` subcommands take only the image options from it, such as the
+/// colors and the background, while `inspect` and `extract` do not use it.
+///
+/// # Errors
+/// Returns [`CliError::InvalidArguments`] if the configuration file is malformed,
+/// has invalid values, or does not have the chosen preset, and [`CliError::ReadInput`]
+/// if a configuration file given with `--config` cannot be read.
+fn configure(args: &mut Args, matches: &ArgMatches) -> Result<(), CliError> {
+    let verbose = match &args.command {
+        None => args.verbose,
+        Some(Command::Edit { verbose, .. } | Command::Package { verbose, .. }) => *verbose,
+        Some(Command::Inspect { .. } | Command::Extract { .. }) => return Ok(()),
+    };
+    let Some((path, mut settings)) = load_settings(args.config.as_deref())? else {
+        return match &args.preset {
+            Some(preset) => Err(CliError::InvalidArguments(format!(
+                "The preset '{preset}' needs a configuration file, but there is none"
+            ))),
+            None => Ok(()),
+        };
+    };
+    if let Some(name) = &args.preset {
+        let preset = settings.presets.remove(name).ok_or_else(|| {
+            let names: Vec<&str> = settings.presets.keys().map(String::as_str).collect();
+            CliError::InvalidArguments(format!(
+                "Configuration '{path}' has no preset '{name}', only '{}'",
+                names.join("', '")
+            ))
+        })?;
+        settings = settings.with_preset(preset);
+    }
+    if verbose {
+        message!("Using the configuration from '{path}'");
+    }
+
+    let invalid = |err: chinenshichanaka::Error| {
+        CliError::InvalidArguments(format!("Configuration '{path}': {err}"))
+    };
+    let out_of_range = |name: &str, value: i64, range: std::ops::RangeInclusive<i64>| match range
+        .contains(&value)
+    {
+        true => Ok(()),
+        false => Err(CliError::InvalidArguments(format!(
+            "Configuration '{path}': {name} {value} is not between {} and {}",
+            range.start(),
+            range.end()
+        ))),
+    };
+    for &size in settings.sizes.iter().flatten() {
+        out_of_range("size", size as i64, 1..=256)?;
+    }
+    if let Some(colors) = settings.colors {
+        out_of_range("colors", colors as i64, 0..=256)?;
+    }
+    if let Some(sample_factor) = settings.sample_factor {
+        out_of_range(
+            "sample-factor",
+            sample_factor as i64,
+            1..=MAX_SAMPLE_FACTOR as i64,
+        )?;
+    }
+
+    // Only the options left to their defaults are taken from the configuration
+    fn fill<T>(target: &mut T, given: bool, value: Option<T>) {
+        if let (false, Some(value)) = (given, value) {
+            *target = value;
+        }
+    }
+    let (image, matches) = match &mut args.command {
+        None => {
+            let given = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+            let inputs = settings.inputs.map(|inputs| match inputs {
+                OneOrMany::One(input) => vec![input],
+                OneOrMany::Many(inputs) => inputs,
+            });
+            // The last of several paths on the command line is the output as well
+            let output_given = given("output") || (given("paths") && args.paths.len() > 1);
+            fill(&mut args.paths, given("paths"), inputs);
+            fill(&mut args.output, output_given, settings.output.map(Some));
+            let format = settings.format.map(|format| format.parse()).transpose();
+            fill(
+                &mut args.format,
+                given("format"),
+                format.map_err(invalid)?.map(Some),
+            );
+            fill(&mut args.sizes, given("sizes"), settings.sizes);
+            fill(&mut args.jobs, given("jobs"), settings.jobs.map(Some));
+            fill(
+                &mut args.export_palette,
+                given("export_palette"),
+                settings.export_palette.map(Some),
+            );
+            (&mut args.image, matches)
         }
+        // The inputs, the output and the sizes of a conversion do not apply to the subcommands
+        Some(Command::Edit { image, .. } | Command::Package { image, .. }) => (
+            image,
+            matches.subcommand().map_or(matches, |(_, matches)| matches),
+        ),
+        Some(Command::Inspect { .. } | Command::Extract { .. }) => return Ok(()),
     };
+    let given = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+    let background = settings.background.map(|value| value.parse()).transpose();
+    fill(
+        &mut image.background,
+        given("background"),
+        background.map_err(invalid)?,
+    );
+    let fit = settings.fit.map(|value| value.parse()).transpose();
+    fill(&mut image.fit, given("fit"), fit.map_err(invalid)?);
+    fill(&mut image.trim, given("trim"), settings.trim);
+    fill(
+        &mut image.trim_tolerance,
+        given("trim_tolerance"),
+        settings.trim_tolerance,
+    );
+    let padding = settings.padding.as_deref().map(parse_padding).transpose();
+    fill(
+        &mut image.padding,
+        given("padding"),
+        padding.map_err(invalid)?,
+    );
+    fill(&mut image.colors, given("colors"), settings.colors);
+    let quantizer = settings.quantizer.map(|value| value.parse()).transpose();
+    fill(
+        &mut image.quantizer,
+        given("quantizer"),
+        quantizer.map_err(invalid)?,
+    );
+    fill(
+        &mut image.sample_factor,
+        given("sample_factor"),
+        settings.sample_factor,
+    );
+    let dither = settings.dither.map(|value| value.parse()).transpose();
+    fill(&mut image.dither, given("dither"), dither.map_err(invalid)?);
+    let color_space = settings.color_space.map(|value| value.parse()).transpose();
+    fill(
+        &mut image.color_space,
+        given("color_space"),
+        color_space.map_err(invalid)?,
+    );
+    fill(
+        &mut image.palette,
+        given("palette"),
+        settings.palette.map(Some),
+    );
+    let encoding = settings.encoding.map(|value| value.parse()).transpose();
+    fill(
+        &mut image.encoding,
+        given("encoding"),
+        encoding.map_err(invalid)?,
+    );
+    Ok(())
+}
 
-    let img = if input.ends_with(".svg") {
-        render_svg_to_image(&input_buffer)
+/// Reads the settings of a configuration file.
+///
+/// Without a path, `chinenshichanaka.toml` is used from the working directory, or else the
+/// `[package.metadata.chinenshichanaka]` table of `Cargo.toml`. A given file named
+/// `Cargo.toml` is read from that table as well.
+///
+/// # Returns
+/// The path of the configuration file with its settings, or `None` when there is none.
+///
+/// # Errors
+/// Returns [`CliError::InvalidArguments`] if the file is not a valid configuration, and
+/// [`CliError::ReadInput`] if a given file cannot be read.
+fn load_settings(path: Option<&str>) -> Result<Option<(String, Settings)>, CliError> {
+    let path = match path {
+        Some(path) => path,
+        None if Path::new(CONFIG_FILE).is_file() => CONFIG_FILE,
+        None if Path::new("Cargo.toml").is_file() => "Cargo.toml",
+        None => return Ok(None),
+    };
+    let text = fs::read_to_string(path).map_err(CliError::ReadInput)?;
+    let invalid =
+        |err: toml::de::Error| CliError::InvalidArguments(format!("Configuration '{path}': {err}"));
+
+    let is_manifest = Path::new(path).file_name() == Some("Cargo.toml".as_ref());
+    let settings = if is_manifest {
+        let manifest: toml::Table = toml::from_str(&text).map_err(invalid)?;
+        let table = manifest
+            .get("package")
+            .and_then(|package| package.get("metadata"))
+            .and_then(|metadata| metadata.get(env!("CARGO_PKG_NAME")));
+        match table {
+            Some(table) => table.clone().try_into().map_err(invalid)?,
+            None => return Ok(None),
+        }
     } else {
-        match image::load_from_memory(&input_buffer) {
-            Ok(img) => img,
-            Err(err) => {
-                eprintln!("Error decoding the input image. {err}");
-                return;
+        toml::from_str(&text).map_err(invalid)?
+    };
+    Ok(Some((path.to_string(), settings)))
+}
+
+/// Validates the parsed arguments and runs the conversion, or the given subcommand.
+fn run(args: Args) -> Result<(), CliError> {
+    match args.command {
+        Some(Command::Inspect { file, json }) => {
+            print!("{}", inspect_ico(&file, json)?);
+            return Ok(());
+        }
+        Some(Command::Extract { file, output_dir }) => {
+            for path in extract_ico(&file, &output_dir)? {
+                message!("Output saved to '{path}'");
             }
+            return Ok(());
         }
+        Some(Command::Edit {
+            file,
+            source,
+            sizes,
+            remove,
+            output,
+            image,
+            verbose,
+        }) => {
+            let options = image.favicon_options(sizes)?;
+            let output = output.as_deref().unwrap_or(&file);
+            return edit_ico(&file, output, source.as_deref(), &options, &remove, verbose);
+        }
+        Some(Command::Package {
+            source,
+            output_dir,
+            sizes,
+            base_url,
+            name,
+            theme_color,
+            background_color,
+            derive_colors,
+            html,
+            image,
+            verbose,
+        }) => {
+            let options = PackageOptions {
+                favicon: image.favicon_options(sizes)?,
+                base_url,
+                name,
+                theme_color,
+                background_color,
+                derive_colors,
+                manifest: None,
+            };
+            // Only the HTML tags go to stdout, so that they can be redirected into a file
+            MESSAGES_TO_STDERR.store(true, Ordering::Relaxed);
+            print!(
+                "{}",
+                package_paths(&source, &output_dir, &options, &html, verbose)?
+            );
+            return Ok(());
+        }
+        None => {}
+    }
+    if args.paths.is_empty() {
+        return Err(CliError::InvalidArguments(
+            "The input file is required, as an argument or in the configuration file".to_string(),
+        ));
+    }
+    // Without the output option, the last of several paths is the output
+    let (patterns, output, positional_output) = match (args.output, args.paths.split_last()) {
+        (Some(output), _) => (args.paths.as_slice(), output, false),
+        (None, Some((output, patterns))) if !patterns.is_empty() => {
+            (patterns, output.clone(), true)
+        }
+        (None, _) => (args.paths.as_slice(), DEFAULT_OUTPUT.to_string(), false),
     };
-
-    // The dimensions method returns the images width and height.
-    if verbosity {
-        println!("Original image dimensions {:?}", img.dimensions());
+    let inputs = expand_inputs(patterns)?;
+    if positional_output
+        && output != STDIO_PATH
+        && inputs
+            .iter()
+            .any(|input| resolved_path(input) == resolved_path(&output))
+    {
+        return Err(CliError::InvalidArguments(format!(
+            "The last path '{output}' is taken as the output, but it is also an input, give the output with --output"
+        )));
     }
 
-    // The color method returns the image's `ColorType`.
-    if verbosity {
-        println!("Original image color type {:?}", img.color());
+    let format = match (args.format, OutputFormat::from_path(&output)) {
+        (Some(format), _) => format,
+        (None, Some(format)) => format,
+        (None, None) if output == STDIO_PATH => {
+            return Err(CliError::InvalidArguments(
+                "Writing to the standard output needs the --format option".to_string(),
+            ))
+        }
+        (None, None) => {
+            return Err(CliError::InvalidArguments(
+                "The output file have to use the 'ico' or 'png' suffix".to_string(),
+            ))
+        }
+    };
+    if positional_output && is_existing_image(&output) {
+        return Err(CliError::InvalidArguments(format!(
+            "The last path '{output}' is an existing image, give the output with --output to overwrite it"
+        )));
+    }
+    if args.watch && inputs.iter().any(|input| input == STDIO_PATH) {
+        return Err(CliError::InvalidArguments(
+            "The standard input cannot be watched for changes".to_string(),
+        ));
+    }
+    let to_stdout = output == STDIO_PATH || args.export_palette.as_deref() == Some(STDIO_PATH);
+    MESSAGES_TO_STDERR.store(to_stdout, Ordering::Relaxed);
+
+    let options = args.image.favicon_options(args.sizes)?;
+    if args.export_palette.is_some() && options.quantize.is_none() {
+        return Err(CliError::InvalidArguments(
+            "Exporting the palette needs color reduction, but the colors are 0".to_string(),
+        ));
     }
 
-    let img: DynamicImage = resize_to_square(&img, 32);
+    let jobs: Vec<Job> = inputs
+        .into_iter()
+        .map(|input| Job {
+            output: output_path(&output, &input),
+            format,
+            palette: args
+                .export_palette
+                .as_ref()
+                .map(|template| output_path(template, &input)),
+            input,
+        })
+        .collect();
+    check_unique_outputs(&jobs)?;
+
+    let result = convert_jobs(&jobs, &options, args.jobs, args.verbose);
+    if !args.watch {
+        return result;
+    }
+    // A failing input may well be fixed by the next save
+    if let Err(err) = result {
+        eprintln!("{err}");
+    }
+    watch_jobs(&jobs, &options, args.verbose)
+}
 
-    // Reduce colors to 16
-    let img = reduce_colors(&img, 16);
+// Converts the inputs in parallel, reporting the failures and a summary when there are several
+fn convert_jobs(
+    jobs: &[Job],
+    options: &FaviconOptions,
+    workers: Option<usize>,
+    verbosity: bool,
+) -> Result<(), CliError> {
+    if let [job] = jobs {
+        return convert_job(job, options, verbosity);
+    }
 
-    // The dimensions method returns the images width and height.
-    if verbosity {
-        println!("Dimensions after resizing to square {:?}", img.dimensions());
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(workers.unwrap_or(0))
+        .build()
+        .map_err(|err| CliError::InvalidArguments(format!("Cannot start the workers. {err}")))?;
+    let results: Vec<Result<(), CliError>> = pool.install(|| {
+        jobs.par_iter()
+            .map(|job| convert_job(job, options, verbosity))
+            .collect()
+    });
+
+    let failures: Vec<(&Job, CliError)> = jobs
+        .iter()
+        .zip(results)
+        .filter_map(|(job, result)| result.err().map(|err| (job, err)))
+        .collect();
+    for (job, err) in &failures {
+        eprintln!("Failed to convert '{}'. {err}", job.input);
+    }
+    match failures.first() {
+        None => {
+            message!("Converted all {} inputs", jobs.len());
+            Ok(())
+        }
+        Some((_, err)) => Err(CliError::Batch {
+            failed: failures.len(),
+            total: jobs.len(),
+            exit_code: err.exit_code(),
+        }),
     }
+}
 
-    // The color method returns the image's `ColorType`.
+/// One input file of a conversion, with the paths of its outputs.
+#[derive(Debug)]
+struct Job {
+    input: String,
+    output: String,
+    format: OutputFormat,
+    palette: Option<String>,
+}
+
+// Converts the input of a job, and exports its palette when asked to
+fn convert_job(job: &Job, options: &FaviconOptions, verbosity: bool) -> Result<(), CliError> {
     if verbosity {
-        println!("Color type after color reduction {:?}", img.color());
+        message!("Converting '{}' to '{}'", job.input, job.output);
     }
+    let images = convert_images(&job.input, &job.output, options, job.format, verbosity)?;
 
-    // Call the convert function with the input buffer
-    let output_buffer: Vec<u8> = convert(img);
-
-    // Finally, save the output buffer to a new file
-    match fs::write(output, &output_buffer) {
-        Ok(_) => println!("Output saved to '{output}'"),
-        Err(err) => eprintln!("Error saving the output image. {err}"),
+    if let Some(path) = &job.palette {
+        export_palette(&images, path)?;
+        if verbosity {
+            message!("Palette saved to '{path}'");
+        }
     }
+    Ok(())
 }
 
-/// Calculates new dimensions for resizing an image to fit within a square.
+/// Converts the inputs again whenever they change, until the process is interrupted.
 ///
-/// # Arguments
-/// * `input_width` - Width of the input image.
-/// * `input_height` - Height of the input image.
-/// * `output_size` - Desired output size (width and height).
+/// The directories of the inputs are watched with the filesystem notifications of the
+/// operating system, since editors often replace the whole file when saving. Changes
+/// arriving within [`WATCH_DEBOUNCE`] of each other are handled as one.
 ///
-/// # Returns
-/// Tuple of new width and height.
-///
-/// # Examples
-/// ```
-/// # use chinenshichanaka::calculate_size;
-/// let (w, h) = chinenshichanaka::calculate_size(100, 150, 200);
-/// assert_eq!((w, h), (133, 200));
-/// ```
-fn calculate_size(input_width: u32, input_height: u32, output_size: u32) -> (u32, u32) {
-    // Calculate the scaling factor to fit the input image within a square of the desired size
-    let scale_factor = f64::min(
-        output_size as f64 / input_width as f64,
-        output_size as f64 / input_height as f64,
+/// # Errors
+/// Returns [`CliError::ReadInput`] if the directories cannot be watched.
+fn watch_jobs(jobs: &[Job], options: &FaviconOptions, verbosity: bool) -> Result<(), CliError> {
+    let watch_error = |err: notify::Error| CliError::ReadInput(io::Error::other(err));
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender).map_err(watch_error)?;
+
+    let inputs: Vec<(PathBuf, &Job)> = jobs
+        .iter()
+        .map(|job| (watched_path(&job.input), job))
+        .collect();
+    let directories: HashSet<&Path> = inputs
+        .iter()
+        .filter_map(|(path, _)| path.parent())
+        .collect();
+    for directory in directories {
+        watcher
+            .watch(directory, RecursiveMode::NonRecursive)
+            .map_err(watch_error)?;
+    }
+    message!(
+        "Watching {} for changes, press Ctrl-C to stop",
+        match jobs {
+            [job] => format!("'{}'", job.input),
+            _ => format!("{} inputs", jobs.len()),
+        }
     );
 
-    // Calculate the new dimensions after resizing
-    let new_width = (input_width as f64 * scale_factor) as u32;
-    let new_height = (input_height as f64 * scale_factor) as u32;
+    let mut changed = HashSet::new();
+    // The loop ends only if the watcher stops sending events
+    while let Ok(event) = receiver.recv() {
+        let mut event = Some(event);
+        while let Some(result) = event {
+            if let Ok(event) = result {
+                if is_content_change(&event.kind) {
+                    changed.extend(event.paths);
+                }
+            }
+            event = receiver.recv_timeout(WATCH_DEBOUNCE).ok();
+        }
+
+        for (_, job) in inputs.iter().filter(|(path, _)| changed.contains(path)) {
+            let started = Instant::now();
+            match convert_job(job, options, verbosity) {
+                Ok(()) => message!(
+                    "Rebuilt '{}' from '{}' in {} ms",
+                    job.output,
+                    job.input,
+                    started.elapsed().as_millis()
+                ),
+                Err(err) => eprintln!("Failed to rebuild '{}'. {err}", job.output),
+            }
+        }
+        changed.clear();
+    }
+    Ok(())
+}
+
+// The path of an input as reported in the events, which is absolute and may be
+// looked up even while an editor has removed the file for replacing it
+fn watched_path(input: &str) -> PathBuf {
+    let path = Path::new(input);
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    match (directory.canonicalize(), path.file_name()) {
+        (Ok(directory), Some(name)) => directory.join(name),
+        _ => path.to_path_buf(),
+    }
+}
+
+// Reading the input for the conversion causes access events, which must not trigger another one
+fn is_content_change(kind: &EventKind) -> bool {
+    matches!(
+        kind,
+        EventKind::Create(_)
+            | EventKind::Remove(_)
+            | EventKind::Modify(ModifyKind::Data(_) | ModifyKind::Name(_) | ModifyKind::Any)
+    )
+}
+
+// Several inputs must not overwrite each other's outputs, nor any of the inputs
+fn check_unique_outputs(jobs: &[Job]) -> Result<(), CliError> {
+    let inputs: HashSet<PathBuf> = jobs
+        .iter()
+        .filter(|job| job.input != STDIO_PATH)
+        .map(|job| resolved_path(&job.input))
+        .collect();
+    let mut seen = HashSet::new();
+    let outputs = jobs
+        .iter()
+        .flat_map(|job| std::iter::once(&job.output).chain(&job.palette));
+    for output in outputs {
+        let resolved = resolved_path(output);
+        if output != STDIO_PATH && inputs.contains(&resolved) {
+            return Err(CliError::InvalidArguments(format!(
+                "The output '{output}' would overwrite an input, use a template such as 'out/{{stem}}.ico'"
+            )));
+        }
+        if !seen.insert(resolved) {
+            return Err(CliError::InvalidArguments(format!(
+                "Several inputs would be saved to '{output}', use a template such as 'out/{{stem}}.ico'"
+            )));
+        }
+    }
+    Ok(())
+}
+
+// Any image other than an icon, which the tool may well have written in an earlier run
+fn is_existing_image(path: &str) -> bool {
+    Path::new(path).is_file()
+        && fs::read(path).is_ok_and(|data| {
+            detect_format(&data).is_ok_and(|format| format != InputFormat::Raster(ImageFormat::Ico))
+        })
+}
 
-    (new_width, new_height)
+// The same file can be written in many ways, such as "./a.png" and "a.png", so paths are
+// compared in their canonical form, through the parent directory for files not yet created
+fn resolved_path(path: &str) -> PathBuf {
+    let path = Path::new(path);
+    if let Ok(resolved) = fs::canonicalize(path) {
+        return resolved;
+    }
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    match (fs::canonicalize(parent), path.file_name()) {
+        (Ok(parent), Some(name)) => parent.join(name),
+        _ => path.to_path_buf(),
+    }
 }
 
-// Get the color of the top-left pixel
-/// Gets the color of the top-left pixel of an image.
+/// Expands the input arguments into input files.
 ///
-/// # Arguments
-/// * `input_image` - Reference to the input image.
+/// Directories are expanded to the files directly in them that have the suffix of a
+/// supported image format, and glob patterns such as `logos/*.svg` to the matching files,
+/// both in alphabetical order. Any other argument is taken as a file as is.
 ///
-/// # Returns
-/// The color of the top-left pixel as `Rgba<u8>`.
-fn get_top_left_color(input_image: &DynamicImage) -> Rgba<u8> {
-    input_image.get_pixel(0, 0)
+/// # Errors
+/// Returns [`CliError::InvalidArguments`] if a pattern is malformed or matches no files,
+/// and [`CliError::ReadInput`] if a directory cannot be read.
+pub fn expand_inputs(patterns: &[String]) -> Result<Vec<String>, CliError> {
+    let mut inputs = Vec::new();
+    for pattern in patterns {
+        let count = inputs.len();
+        let path = Path::new(pattern);
+        if path.is_dir() {
+            let mut files: Vec<String> = fs::read_dir(path)
+                .map_err(CliError::ReadInput)?
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.is_file() && has_image_suffix(path))
+                .map(|path| path.to_string_lossy().into_owned())
+                .collect();
+            files.sort();
+            inputs.extend(files);
+        } else if !path.exists() && pattern.contains(['*', '?', '[']) {
+            let paths = glob::glob(pattern).map_err(|err| {
+                CliError::InvalidArguments(format!("Invalid pattern '{pattern}': {err}"))
+            })?;
+            for path in paths {
+                let path = path.map_err(|err| CliError::ReadInput(err.into()))?;
+                if path.is_file() {
+                    inputs.push(path.to_string_lossy().into_owned());
+                }
+            }
+        } else {
+            inputs.push(pattern.clone());
+        }
+        if inputs.len() == count {
+            return Err(CliError::InvalidArguments(format!(
+                "No image files found in '{pattern}'"
+            )));
+        }
+    }
+    Ok(inputs)
+}
+
+// Files in directories are picked by the suffix, since reading them all would be slow
+fn has_image_suffix(path: &Path) -> bool {
+    let Some(extension) = path.extension().and_then(|extension| extension.to_str()) else {
+        return false;
+    };
+    matches!(extension.to_ascii_lowercase().as_str(), "svg" | "svgz")
+        || ImageFormat::from_extension(extension).is_some_and(|format| format.reading_enabled())
 }
 
-// Create a new square image with the desired output size and fill it with the background color
-/// Creates a new square image of the given size, filled with the specified background color.
+/// Fills in the output template of an input file.
 ///
-/// # Arguments
-/// * `output_size` - Size of the square image (width and height).
-/// * `background_color` - Color to fill the image.
+/// The `{stem}` placeholder is replaced with the name of the input file without its suffix,
+/// and `{dir}` with the directory of the input file.
+pub fn output_path(template: &str, input: &str) -> String {
+    let path = Path::new(input);
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy())
+        .unwrap_or_default();
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_string_lossy(),
+        _ => ".".into(),
+    };
+    template.replace("{stem}", &stem).replace("{dir}", &dir)
+}
+
+/// Reads the colors of a palette file, in the format given by its suffix.
 ///
-/// # Returns
-/// A new `DynamicImage` filled with the background color.
-fn create_square_image(output_size: u32, background_color: Rgba<u8>) -> DynamicImage {
-    let mut square_image = DynamicImage::new_rgb8(output_size, output_size);
-    imageops::overlay(
-        &mut square_image,
-        &DynamicImage::ImageRgb8(image::RgbImage::from_pixel(1, 1, background_color.to_rgb())),
-        0,
-        0,
-    );
-    square_image
+/// # Errors
+/// Returns [`CliError::ReadInput`] if the file cannot be read, and
+/// [`CliError::InvalidArguments`] if it is not a valid palette.
+pub fn read_palette(path: &str) -> Result<Vec<image::Rgba<u8>>, CliError> {
+    let data = read_input(path)?;
+    parse_palette(&data, PaletteFormat::from_path(path))
+        .map_err(|err| CliError::InvalidArguments(format!("Palette '{path}': {err}")))
 }
 
-// Resize the input image using Lanczos3 filter for high-quality results
-/// Resizes an image to the specified dimensions using the Lanczos3 filter.
+/// Writes the colors used in the icons of a conversion to a palette file,
+/// in the format given by its suffix.
 ///
-/// # Arguments
-/// * `input_image` - Reference to the input image.
-/// * `new_width` - Desired width.
-/// * `new_height` - Desired height.
+/// Each icon size is reduced separately, so the palette has the colors of all of them.
 ///
-/// # Returns
-/// A new `DynamicImage` with the resized dimensions.
-fn resize_image(input_image: &DynamicImage, new_width: u32, new_height: u32) -> DynamicImage {
-    input_image.resize_exact(new_width, new_height, imageops::FilterType::Lanczos3)
+/// # Errors
+/// Returns a [`CliError`] describing the step that failed.
+pub fn export_palette(images: &[DynamicImage], output: &str) -> Result<(), CliError> {
+    let data = write_palette(&used_colors(images), PaletteFormat::from_path(output))
+        .map_err(CliError::Encode)?;
+    write_output(output, &data)
 }
 
-// Paste the resized image onto the square image at the specified position
-/// Pastes a resized image onto a square image at the specified position.
+/// Reads an input file, or the standard input for "-".
 ///
-/// # Arguments
-/// * `square_image` - Mutable reference to the destination image.
-/// * `resized_image` - Reference to the image to paste.
-/// * `paste_x` - X coordinate for pasting.
-/// * `paste_y` - Y coordinate for pasting.
-fn paste_resized_image(
-    square_image: &mut DynamicImage,
-    resized_image: &DynamicImage,
-    paste_x: u32,
-    paste_y: u32,
-) {
-    imageops::overlay(square_image, resized_image, paste_x as i64, paste_y as i64);
-}
-
-// Resize input image to a square with the specified output size
-/// Resizes an image to a square of the specified size, centering the original image.
+/// The standard input is read once and kept, so that it can be used again.
 ///
-/// # Arguments
-/// * `input_image` - Reference to the input image.
-/// * `output_size` - Desired size for the square image.
+/// # Errors
+/// Returns [`CliError::ReadInput`] if the input cannot be read.
+pub fn read_input(path: &str) -> Result<Vec<u8>, CliError> {
+    static STDIN: OnceLock<Vec<u8>> = OnceLock::new();
+    if path != STDIO_PATH {
+        return fs::read(path).map_err(CliError::ReadInput);
+    }
+    if let Some(data) = STDIN.get() {
+        return Ok(data.clone());
+    }
+    let mut data = Vec::new();
+    io::stdin()
+        .read_to_end(&mut data)
+        .map_err(CliError::ReadInput)?;
+    Ok(STDIN.get_or_init(|| data).clone())
+}
+
+/// Writes an output file, or the standard output for "-".
 ///
-/// # Returns
-/// A new `DynamicImage` resized and centered in a square.
-fn resize_to_square(input_image: &DynamicImage, output_size: u32) -> DynamicImage {
-    let (input_width, input_height) = input_image.dimensions();
-    let (new_width, new_height) = calculate_size(input_width, input_height, output_size);
-    let top_left_color = get_top_left_color(input_image);
-    let mut square_image = create_square_image(output_size, top_left_color);
-    let paste_x = (output_size - new_width) / 2;
-    let paste_y = (output_size - new_height) / 2;
-    let resized_image = resize_image(input_image, new_width, new_height);
-    paste_resized_image(&mut square_image, &resized_image, paste_x, paste_y);
-    square_image
+/// # Errors
+/// Returns [`CliError::WriteOutput`] if the output cannot be written.
+pub fn write_output(path: &str, data: &[u8]) -> Result<(), CliError> {
+    if path != STDIO_PATH {
+        return fs::write(path, data).map_err(CliError::WriteOutput);
+    }
+    let mut stdout = io::stdout().lock();
+    stdout
+        .write_all(data)
+        .and_then(|_| stdout.flush())
+        .map_err(CliError::WriteOutput)
 }
 
-// Tests
-#[cfg(test)]
-mod tests {
+/// Reads the entries of an ICO or CUR file.
+///
+/// # Errors
+/// Returns [`CliError::ReadInput`] if the file cannot be read, and
+/// [`CliError::Decode`] if it is not a valid ICO file.
+pub fn read_ico_file(path: &str) -> Result<Vec<IconEntry>, CliError> {
+    let data = read_input(path)?;
+    read_ico(&data).map_err(CliError::Decode)
+}
 
-    use super::*;
-    use assert_cmd::Command;
-    use image::Rgb;
-    use image::{imageops, DynamicImage, GenericImageView, Pixel, Rgba};
-    use std::io::Cursor;
-    use tempfile::NamedTempFile;
+/// Describes the entries of an ICO file, as a table or as JSON.
+///
+/// # Arguments
+/// * `path` - Path to the ICO or CUR file.
+/// * `json` - Whether to describe the entries as JSON instead of a table.
+///
+/// # Errors
+/// Returns a [`CliError`] if the file cannot be read or is not a valid ICO file.
+pub fn inspect_ico(path: &str, json: bool) -> Result<String, CliError> {
+    let entries = read_ico_file(path)?;
+    let encoding = |entry: &IconEntry| if entry.is_png() { "png" } else { "bmp" };
+
+    if json {
+        let entries: Vec<_> = entries
+            .iter()
+            .map(|entry| {
+                serde_json::json!({
+                    "width": entry.width,
+                    "height": entry.height,
+                    "bit_depth": entry.bit_depth(),
+                    "encoding": encoding(entry),
+                    "palette_size": entry.palette_size(),
+                    "bytes": entry.data.len(),
+                })
+            })
+            .collect();
+        let report = serde_json::json!({ "file": path, "entries": entries });
+        return Ok(format!("{report:#}\n"));
+    }
 
-    // Helper function to create an image with specified dimensions and color
-    fn create_test_image(width: u32, height: u32, color: Rgba<u8>) -> DynamicImage {
-        let mut img: DynamicImage = DynamicImage::new_rgba8(width, height);
-        imageops::overlay(
-            &mut img,
-            &DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(1, 1, color.to_rgba())),
-            0,
-            0,
+    let count = match entries.len() {
+        1 => "1 entry".to_string(),
+        count => format!("{count} entries"),
+    };
+    let mut report = format!("{path}: {count}\n");
+    report += "  #  Size       Bits  Encoding  Palette  Bytes\n";
+    for (index, entry) in entries.iter().enumerate() {
+        report += &format!(
+            "{:>3}  {:<9}  {:>4}  {:<8}  {:>7}  {:>5}\n",
+            index + 1,
+            format!("{}x{}", entry.width, entry.height),
+            entry.bit_depth(),
+            encoding(entry),
+            entry.palette_size(),
+            entry.data.len()
         );
-        img
     }
+    Ok(report)
+}
 
-    // Helper function to create a temporary image file
-    fn create_temp_image_file(extension: &str, color: Rgba<u8>) -> (NamedTempFile, String) {
-        let temp_file = NamedTempFile::new().expect("Failed to create temp input file");
-        let file_path = temp_file.path().to_str().unwrap().to_owned() + extension;
-        let input_image = create_test_image(100, 150, color);
-        input_image
-            .save(&file_path)
-            .expect("Failed to save input image");
-        (temp_file, file_path)
-    }
+/// Writes each entry of an ICO file as a PNG file into a directory.
+///
+/// The files are named after the ICO file and the entry size, such as `favicon-32x32.png`,
+/// with a counter added for repeated sizes. PNG entries are copied as they are, while
+/// BMP entries are decoded and encoded as PNG.
+///
+/// # Arguments
+/// * `path` - Path to the ICO or CUR file.
+/// * `output_dir` - Directory for the PNG files, created when missing.
+///
+/// # Returns
+/// The paths of the written PNG files, in the order of the entries.
+///
+/// # Errors
+/// Returns a [`CliError`] describing the step that failed.
+pub fn extract_ico(path: &str, output_dir: &str) -> Result<Vec<String>, CliError> {
+    let entries = read_ico_file(path)?;
+    let stem = Path::new(path)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("icon");
+    fs::create_dir_all(output_dir).map_err(CliError::WriteOutput)?;
+
+    let mut paths: Vec<String> = Vec::with_capacity(entries.len());
+    for entry in &entries {
+        let name = format!("{stem}-{}x{}", entry.width, entry.height);
+        let mut file_path = Path::new(output_dir).join(format!("{name}.png"));
+        let mut counter = 1;
+        while paths.iter().any(|path| Path::new(path) == file_path) {
+            counter += 1;
+            file_path = Path::new(output_dir).join(format!("{name}-{counter}.png"));
+        }
 
-    // Helper function to create a temporary directory and output file path
-    fn create_temp_output_file(extension: &str) -> (tempfile::TempDir, String) {
-        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
-        let output_path = temp_dir.path().to_str().unwrap().to_owned() + extension;
-        (temp_dir, output_path)
+        let png = if entry.is_png() {
+            entry.data.clone()
+        } else {
+            let img = entry.decode().map_err(CliError::Decode)?;
+            IconEntry::png(&img).map_err(CliError::Encode)?.data
+        };
+        fs::write(&file_path, png).map_err(CliError::WriteOutput)?;
+        paths.push(file_path.to_string_lossy().into_owned());
     }
+    Ok(paths)
+}
 
-    // Verifies that a valid image buffer is correctly converted into an ICO format.
-    #[test]
-    fn test_convert_with_valid_input() {
-        // Create a test image
-        let input_image: DynamicImage = create_square_image(32, Rgba([255, 0, 0, 255]));
-        let input_image: DynamicImage = reduce_colors(&input_image, 32);
-        // Call the convert function with the test image
-        let output_buffer: Vec<u8> = convert(input_image);
-
-        let guess: image::ImageFormat =
-            image::guess_format(&output_buffer).expect("Failed to guess output image format");
-        assert_eq!(guess, image::ImageFormat::Ico);
+/// Edits the entries of an ICO file, keeping the untouched entries byte for byte.
+///
+/// The entries of the given sizes are first removed, after which the entries rendered
+/// from the source image replace the entries of the same size or are added.
+///
+/// # Arguments
+/// * `file` - Path to the ICO file to edit.
+/// * `output` - Path for the edited ICO file, which may be the same as `file`.
+/// * `source` - Path to the image rendered into new entries, at the sizes of `options`,
+///   or "-" for the standard input.
+/// * `options` - Sizes and colors of the new entries.
+/// * `remove` - Sizes of the entries to remove.
+/// * `verbosity` - Whether to print verbose output.
+///
+/// # Errors
+/// Returns [`CliError::InvalidArguments`] if there is nothing to edit, a size is both
+/// added and removed, or no entries would be left, and otherwise a [`CliError`]
+/// describing the step that failed.
+pub fn edit_ico(
+    file: &str,
+    output: &str,
+    source: Option<&str>,
+    options: &FaviconOptions,
+    remove: &[u32],
+    verbosity: bool,
+) -> Result<(), CliError> {
+    if source.is_none() && remove.is_empty() {
+        return Err(CliError::InvalidArguments(
+            "Nothing to edit, give a source image with sizes or sizes to remove".to_string(),
+        ));
+    }
+    if let Some(size) = options.sizes.iter().find(|size| remove.contains(size)) {
+        return Err(CliError::InvalidArguments(format!(
+            "The size {size} cannot be both added and removed"
+        )));
+    }
 
-        // Open the output image from the byte buffer
-        let reader = image::ImageReader::new(Cursor::new(&output_buffer))
-            .with_guessed_format()
-            .expect("Cursor io never fails");
-        assert_eq!(reader.format(), Some(image::ImageFormat::Ico));
+    let mut entries = read_ico_file(file)?;
+    let removed = remove_entries(&mut entries, remove);
+    if verbosity {
+        message!("Removed {removed} entries of the sizes {remove:?}");
+    }
 
-        // Perform assertions on the output image
-        let dimensions: (u32, u32) = reader
-            .into_dimensions()
-            .expect("Failed to get output image dimensions");
-        assert_eq!(dimensions, (32, 32));
+    if let Some(source) = source {
+        let input_buffer = read_input(source)?;
+        let new_entries = favicon_entries(&input_buffer, options).map_err(conversion_error)?;
+        if verbosity {
+            message!("Rendered the sizes {:?} from '{source}'", options.sizes);
+        }
+        merge_entries(&mut entries, new_entries);
     }
 
-    // Ensures that invalid input (e.g., an empty buffer) results in no output.
-    #[test]
-    fn test_convert_with_invalid_input() {
-        // Call the convert function with an invalid image
-        let invalid_image = DynamicImage::new_rgb8(0, 0); // Empty image
-        let result = std::panic::catch_unwind(|| convert(invalid_image));
+    if entries.is_empty() {
+        return Err(CliError::InvalidArguments(
+            "No entries would be left in the icon".to_string(),
+        ));
+    }
+    let output_buffer = write_ico(&entries).map_err(CliError::Encode)?;
+    fs::write(output, &output_buffer).map_err(CliError::WriteOutput)?;
+    message!("Output saved to '{output}'");
+    Ok(())
+}
 
-        // Ensure the function panics due to invalid input
-        assert!(result.is_err());
+// Maps the errors of a whole conversion to the failing step
+fn conversion_error(err: chinenshichanaka::Error) -> CliError {
+    match err {
+        chinenshichanaka::Error::SvgParse(_) => CliError::SvgParse(err),
+        chinenshichanaka::Error::Decode(_) | chinenshichanaka::Error::UnsupportedFormat => {
+            CliError::Decode(err)
+        }
+        _ => CliError::Encode(err),
     }
+}
 
-    // Validates the logic for calculating new dimensions.
-    #[test]
-    fn test_calculate_size() {
+/// Writes the favicon package of a source image file into a directory.
+///
+/// A `site.webmanifest` already in the directory is merged with the generated one,
+/// keeping its other fields. The favicon tags of the HTML files are replaced with the
+/// ones linking to the package.
+///
+/// # Arguments
+/// * `source` - Path to the source image file, or "-" for the standard input.
+/// * `output_dir` - Directory for the files, created when missing.
+/// * `options` - Options of the images, the manifest and the HTML tags.
+/// * `html_paths` - HTML files to patch in place.
+/// * `verbosity` - Whether to print verbose output.
+///
+/// # Returns
+/// The HTML tags linking to the files, for the `<head>` of the pages.
+///
+/// # Errors
+/// Returns a [`CliError`] describing the step that failed.
+pub fn package_paths(
+    source: &str,
+    output_dir: &str,
+    options: &PackageOptions,
+    html_paths: &[String],
+    verbosity: bool,
+) -> Result<String, CliError> {
+    let input_buffer = read_input(source)?;
+    let manifest_path = Path::new(output_dir).join("site.webmanifest");
+    let mut options = options.clone();
+    if manifest_path.is_file() {
+        options.manifest = Some(fs::read(&manifest_path).map_err(CliError::ReadInput)?);
+        if verbosity {
+            message!("Merging into the existing '{}'", manifest_path.display());
+        }
+    }
+    let files = favicon_package(&input_buffer, &options).map_err(|err| match err {
+        chinenshichanaka::Error::InvalidManifest(_) => CliError::InvalidArguments(format!(
+            "Cannot merge into '{}'. {err}",
+            manifest_path.display()
+        )),
+        _ => conversion_error(err),
+    })?;
+    if verbosity {
+        message!(
+            "Packaging '{source}' with {} in favicon.ico",
+            options
+                .favicon
+                .sizes
+                .iter()
+                .map(|size| format!("{size}x{size}"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+
+    // All the HTML files are patched before writing anything, so that a failure leaves no
+    // partial result behind
+    let snippet = html_snippet(&files, &options);
+    let mut patched_html = Vec::new();
+    for path in html_paths {
+        let html = fs::read_to_string(path).map_err(CliError::ReadInput)?;
+        let patched = patch_html(&html, &snippet)
+            .map_err(|err| CliError::InvalidArguments(format!("Cannot patch '{path}'. {err}")))?;
+        patched_html.push((path, html, patched));
+    }
+
+    fs::create_dir_all(output_dir).map_err(CliError::WriteOutput)?;
+    for file in &files {
+        let path = Path::new(output_dir).join(&file.name);
+        fs::write(&path, &file.data).map_err(CliError::WriteOutput)?;
+        message!("Output saved to '{}'", path.display());
+    }
+    for (path, html, patched) in patched_html {
+        if patched == html {
+            message!("The favicon tags of '{path}' are up to date");
+        } else {
+            fs::write(path, patched).map_err(CliError::WriteOutput)?;
+            message!("Favicon tags updated in '{path}'");
+        }
+    }
+    Ok(snippet)
+}
+
+/// Converts an input image file to an ICO file, optionally printing verbose output.
+///
+/// Runs [`convert_paths_with`] with the ICO output format.
+///
+/// # Errors
+/// Returns a [`CliError`] describing the step that failed, in which case no output is written.
+pub fn convert_paths(
+    input: &str,
+    output: &str,
+    options: &FaviconOptions,
+    verbosity: bool,
+) -> Result<(), CliError> {
+    convert_paths_with(input, output, options, OutputFormat::Ico, verbosity)
+}
+
+/// Converts an input image file to an ICO or PNG file, optionally printing verbose output.
+///
+/// # Arguments
+/// * `input` - Path to the input image file (SVG or raster).
+/// * `output` - Path to the output file, or "-" for the standard output.
+/// * `options` - Sizes and colors of the icons to include in the output.
+/// * `format` - Format of the output, where PNG has only the largest icon size.
+/// * `verbosity` - Whether to print verbose output.
+///
+/// # Errors
+/// Returns a [`CliError`] describing the step that failed, in which case no output is written.
+pub fn convert_paths_with(
+    input: &str,
+    output: &str,
+    options: &FaviconOptions,
+    format: OutputFormat,
+    verbosity: bool,
+) -> Result<(), CliError> {
+    convert_images(input, output, options, format, verbosity).map(|_| ())
+}
+
+// Converts like `convert_paths_with`, returning the icons saved in the output
+fn convert_images(
+    input: &str,
+    output: &str,
+    options: &FaviconOptions,
+    format: OutputFormat,
+    verbosity: bool,
+) -> Result<Vec<DynamicImage>, CliError> {
+    // Read the content of the file, or the standard input, into a byte vector
+    let input_buffer: Vec<u8> = read_input(input)?;
+
+    let input_format = detect_format(&input_buffer).map_err(CliError::Decode)?;
+    if verbosity {
+        message!("Detected input format {input_format:?}");
+    }
+
+    // SVG is parsed once, rendered for reference here and later at each icon size
+    let document = match input_format {
+        InputFormat::Raster(_) => None,
+        InputFormat::Svg | InputFormat::Svgz => {
+            Some(SvgDocument::parse(&input_buffer).map_err(conversion_error)?)
+        }
+    };
+    let img = match &document {
+        Some(document) => Cow::Borrowed(document.reference()),
+        None => Cow::Owned(decode_image(&input_buffer).map_err(conversion_error)?),
+    };
+
+    // The dimensions method returns the images width and height.
+    if verbosity && document.is_some() {
+        message!("SVG reference rendering dimensions {:?}", img.dimensions());
+    } else if verbosity {
+        message!("Original image dimensions {:?}", img.dimensions());
+    }
+
+    // The color method returns the image's `ColorType`.
+    if verbosity {
+        message!("Original image color type {:?}", img.color());
+    }
+
+    if verbosity {
+        let color = background_color(&img, options.background);
+        message!(
+            "Background color {} from '{}'",
+            format_hex_color(color),
+            options.background
+        );
+    }
+
+    if let (true, Some(tolerance)) = (verbosity, options.trim) {
+        match content_bounds(&img, tolerance) {
+            Some(bounds) => message!("Content bounds for trimming {bounds:?}"),
+            None => message!("No content found for trimming"),
+        }
+    }
+
+    if verbosity {
+        match &options.quantize {
+            Some(QuantizeOptions {
+                palette: Some(palette),
+                dither,
+                color_space,
+                ..
+            }) => message!(
+                "Mapping onto the {} palette colors with '{dither}' dithering and {color_space} distances",
+                palette.len()
+            ),
+            Some(quantize) => message!(
+                "Reducing to {} colors with the '{}' quantizer, '{}' dithering and {} distances",
+                quantize.colors, quantize.quantizer, quantize.dither, quantize.color_space
+            ),
+            None => message!("Keeping true color without color reduction"),
+        }
+    }
+
+    // Each size is rendered separately from the source image for the best quality
+    let images = match &document {
+        Some(document) => favicon_images_from_svg_document(document, options),
+        None => favicon_images(&img, options),
+    }
+    .map_err(conversion_error)?;
+
+    for img in &images {
+        // The dimensions method returns the images width and height.
+        if verbosity {
+            message!("Dimensions after resizing to square {:?}", img.dimensions());
+        }
+
+        // The color method returns the image's `ColorType`.
+        if verbosity {
+            message!("Color type after color reduction {:?}", img.color());
+        }
+    }
+
+    // Call the convert function with all the resized images
+    let (output_buffer, images) = match format {
+        OutputFormat::Ico => {
+            if verbosity {
+                message!("Encoding the icons with the '{}' policy", options.encoding);
+            }
+            let data = convert_frames_with(&images, options.encoding).map_err(CliError::Encode)?;
+            (data, images)
+        }
+        OutputFormat::Png => {
+            let largest = images
+                .into_iter()
+                .max_by_key(|img| img.width())
+                .ok_or(chinenshichanaka::Error::InvalidSize {
+                    width: 0,
+                    height: 0,
+                })
+                .map_err(CliError::Encode)?;
+            let data = IconEntry::png(&largest.to_rgba8()).map_err(CliError::Encode)?;
+            (data.data, vec![largest])
+        }
+    };
+
+    // Finally, save the output buffer to a new file
+    write_output(output, &output_buffer)?;
+    match output {
+        STDIO_PATH => message!("Output written to the standard output"),
+        _ => message!("Output saved to '{output}'"),
+    }
+    Ok(images)
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use assert_cmd::Command;
+    use chinenshichanaka::{
+        add_padding, calculate_crop, calculate_size, choose_palette, convert, convert_frames,
+        convert_frames_with, create_square_image, decode_image, detect_format, favicon_entries,
+        favicon_from_bytes, favicon_images_from_svg, fit_to_square, get_edge_color,
+        get_top_left_color, merge_entries, parse_palette, paste_resized_image, quantize,
+        quantize_with, read_ico, reduce_colors, remove_entries, render_svg_to_image, resize_image,
+        resize_to_square, smart_crop, supported_input_formats, trim, web_manifest, write_ico,
+        write_palette, CropRect, Error, FaviconOptions, FixedPalette, IconEncoding, IconEntry,
+        InputFormat, MedianCutQuantizer, NeuQuantQuantizer, OctreeQuantizer, OutputFormat,
+        PaletteFormat, PaletteQuantizer, Quantizer, MAX_PADDING, SVG_REFERENCE_SIZE,
+    };
+    use image::Rgb;
+    use image::{imageops, DynamicImage, GenericImageView, Pixel, Rgba};
+    use std::io::Cursor;
+    use tempfile::NamedTempFile;
+
+    // Helper function to create an image with specified dimensions and color
+    fn create_test_image(width: u32, height: u32, color: Rgba<u8>) -> DynamicImage {
+        let mut img: DynamicImage = DynamicImage::new_rgba8(width, height);
+        imageops::overlay(
+            &mut img,
+            &DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(1, 1, color.to_rgba())),
+            0,
+            0,
+        );
+        img
+    }
+
+    // Helper function to create a temporary image file
+    fn create_temp_image_file(extension: &str, color: Rgba<u8>) -> (NamedTempFile, String) {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp input file");
+        let file_path = temp_file.path().to_str().unwrap().to_owned() + extension;
+        let input_image = create_test_image(100, 150, color);
+        input_image
+            .save(&file_path)
+            .expect("Failed to save input image");
+        (temp_file, file_path)
+    }
+
+    // Helper function to create conversion options with only the given sizes
+    fn options_with_sizes(sizes: &[u32]) -> FaviconOptions {
+        FaviconOptions {
+            sizes: sizes.to_vec(),
+            ..Default::default()
+        }
+    }
+
+    // Helper function to create a temporary directory and output file path
+    fn create_temp_output_file(extension: &str) -> (tempfile::TempDir, String) {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let output_path = temp_dir.path().to_str().unwrap().to_owned() + extension;
+        (temp_dir, output_path)
+    }
+
+    // Verifies that a valid image buffer is correctly converted into an ICO format.
+    #[test]
+    fn test_convert_with_valid_input() {
+        // Create a test image
+        let input_image: DynamicImage = create_square_image(32, Rgba([255, 0, 0, 255]));
+        let input_image: DynamicImage =
+            reduce_colors(&input_image, 32).expect("Failed to reduce colors");
+        // Call the convert function with the test image
+        let output_buffer: Vec<u8> = convert(input_image).expect("Failed to convert image");
+
+        let guess: image::ImageFormat =
+            image::guess_format(&output_buffer).expect("Failed to guess output image format");
+        assert_eq!(guess, image::ImageFormat::Ico);
+
+        // Open the output image from the byte buffer
+        let reader = image::ImageReader::new(Cursor::new(&output_buffer))
+            .with_guessed_format()
+            .expect("Cursor io never fails");
+        assert_eq!(reader.format(), Some(image::ImageFormat::Ico));
+
+        // Perform assertions on the output image
+        let dimensions: (u32, u32) = reader
+            .into_dimensions()
+            .expect("Failed to get output image dimensions");
+        assert_eq!(dimensions, (32, 32));
+    }
+
+    // Ensures that invalid input (e.g., an empty buffer) results in no output.
+    #[test]
+    fn test_convert_with_invalid_input() {
+        // Call the convert function with an invalid image
+        let invalid_image = DynamicImage::new_rgb8(0, 0); // Empty image
+        let result = convert(invalid_image);
+
+        // Ensure the function fails due to invalid input
+        assert!(matches!(
+            result,
+            Err(Error::InvalidSize {
+                width: 0,
+                height: 0
+            })
+        ));
+    }
+
+    // Validates the logic for calculating new dimensions.
+    #[test]
+    fn test_calculate_size() {
         assert_eq!(calculate_size(100, 150, 200), (133, 200));
         assert_eq!(calculate_size(400, 600, 200), (133, 200));
         assert_eq!(calculate_size(300, 300, 200), (200, 200));
@@ -348,7 +1738,7 @@ mod tests {
     fn test_resize_to_square() {
         let input_image: DynamicImage =
             DynamicImage::ImageRgb8(image::RgbImage::from_pixel(1, 1, Rgb([255, 0, 0])));
-        let result: DynamicImage = resize_to_square(&input_image, 200);
+        let result: DynamicImage = resize_to_square(&input_image, 200, Rgba([0, 0, 0, 0]));
         assert_eq!(result.dimensions(), (200, 200));
         assert_eq!(result.get_pixel(50, 50), Rgba([255, 0, 0, 255]));
     }
@@ -356,7 +1746,8 @@ mod tests {
     #[test]
     fn test_reduce_colors() {
         let input_image: DynamicImage = create_test_image(100, 100, Rgba([255, 0, 0, 255]));
-        let reduced_image: DynamicImage = reduce_colors(&input_image, 16);
+        let reduced_image: DynamicImage =
+            reduce_colors(&input_image, 16).expect("Failed to reduce colors");
         assert_eq!(reduced_image.dimensions(), (100, 100));
 
         // Check that the number of unique colors is reduced
@@ -370,7 +1761,8 @@ mod tests {
     #[test]
     fn test_reduce_colors_with_more_colors() {
         let input_image: DynamicImage = create_test_image(100, 100, Rgba([0, 255, 0, 255]));
-        let reduced_image: DynamicImage = reduce_colors(&input_image, 256);
+        let reduced_image: DynamicImage =
+            reduce_colors(&input_image, 256).expect("Failed to reduce colors");
         assert_eq!(reduced_image.dimensions(), (100, 100));
 
         // Check that the number of unique colors is reduced
@@ -450,10 +1842,41 @@ mod tests {
             .arg(&output_path)
             .arg("--verbose")
             .assert()
-            .failure();
+            .code(EXIT_INVALID_ARGUMENTS);
+
+        let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+        assert!(stderr.contains("The output file have to use the 'ico' or 'png' suffix"));
+    }
+
+    #[test]
+    fn test_main_with_missing_input() {
+        let (_temp_dir, output_path) = create_temp_output_file("/output.ico");
+
+        let assert = Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .arg("non_existent.png")
+            .arg(&output_path)
+            .assert()
+            .code(EXIT_READ_INPUT);
 
         let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
-        assert!(stderr.contains("The output file have to use the 'ico' suffix"));
+        assert!(stderr.contains("Error reading the input image."));
+        assert!(!std::path::Path::new(&output_path).exists());
+    }
+
+    #[test]
+    fn test_main_with_corrupted_svg() {
+        let temp_input = NamedTempFile::new().expect("Failed to create temp input file");
+        let input_path = temp_input.path().to_str().unwrap().to_owned() + ".svg";
+        fs::write(&input_path, "<svg").expect("Failed to write corrupted SVG");
+        let (_temp_dir, output_path) = create_temp_output_file("/output.ico");
+
+        Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .arg(&input_path)
+            .arg(&output_path)
+            .assert()
+            .code(EXIT_SVG_PARSE);
     }
 
     #[test]
@@ -461,7 +1884,10 @@ mod tests {
         let (_, output_path) = create_temp_output_file("/output.ico");
         let input_path = "invalid.png".to_string();
 
-        convert_paths(&input_path, &output_path, true);
+        let result = convert_paths(&input_path, &output_path, &options_with_sizes(&[32]), true);
+        let err = result.expect_err("Conversion should fail");
+        assert!(matches!(err, CliError::ReadInput(_)));
+        assert_eq!(err.exit_code(), EXIT_READ_INPUT);
 
         assert!(!std::path::Path::new(&output_path).exists());
     }
@@ -478,7 +1904,8 @@ mod tests {
         let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
         let output_path = temp_dir.path().to_str().unwrap().to_owned() + "/output.ico";
 
-        convert_paths(&input_path, &output_path, true);
+        convert_paths(&input_path, &output_path, &options_with_sizes(&[32]), true)
+            .expect("Failed to convert paths");
 
         assert!(std::path::Path::new(&output_path).exists());
         let output_content = fs::read(output_path).expect("Failed to read output file");
@@ -511,7 +1938,10 @@ mod tests {
         let (_, output_path) = create_temp_output_file("/output.ico");
         let input_path = "non_existent.png".to_string();
 
-        convert_paths(&input_path, &output_path, true);
+        let result = convert_paths(&input_path, &output_path, &options_with_sizes(&[32]), true);
+        let err = result.expect_err("Conversion should fail");
+        assert!(matches!(err, CliError::ReadInput(_)));
+        assert_eq!(err.exit_code(), EXIT_READ_INPUT);
 
         assert!(!std::path::Path::new(&output_path).exists());
     }
@@ -527,11 +1957,11 @@ mod tests {
         let input_buffer = svg_content.as_bytes();
 
         // Render SVG to image
-        let input_image = render_svg_to_image(input_buffer);
-        let input_image = reduce_colors(&input_image, 16);
+        let input_image = render_svg_to_image(input_buffer, 32).expect("Failed to render SVG");
+        let input_image = reduce_colors(&input_image, 16).expect("Failed to reduce colors");
 
         // Call the convert function with the rendered image
-        let output_buffer: Vec<u8> = convert(input_image);
+        let output_buffer: Vec<u8> = convert(input_image).expect("Failed to convert image");
 
         let guess: image::ImageFormat =
             image::guess_format(&output_buffer).expect("Failed to guess output image format");
@@ -564,7 +1994,8 @@ mod tests {
         let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
         let output_path = temp_dir.path().to_str().unwrap().to_owned() + "/output.ico";
 
-        convert_paths(&input_path, &output_path, true);
+        convert_paths(&input_path, &output_path, &options_with_sizes(&[32]), true)
+            .expect("Failed to convert paths");
 
         assert!(std::path::Path::new(&output_path).exists());
         let output_content = fs::read(output_path).expect("Failed to read output file");
@@ -637,11 +2068,13 @@ mod tests {
         let input_buffer = large_svg_content.as_bytes();
 
         // Render SVG to image
-        let input_image = render_svg_to_image(input_buffer);
-        let input_image = reduce_colors(&input_image, 16);
+        let input_image = render_svg_to_image(input_buffer, 32).expect("Failed to render SVG");
+        // The document is scaled down instead of clipped to its top-left corner
+        assert_eq!(input_image.get_pixel(31, 31), Rgba([255, 0, 0, 255]));
+        let input_image = reduce_colors(&input_image, 16).expect("Failed to reduce colors");
 
         // Call the convert function with the large SVG content
-        let output_buffer: Vec<u8> = convert(input_image);
+        let output_buffer: Vec<u8> = convert(input_image).expect("Failed to convert image");
 
         let guess: image::ImageFormat =
             image::guess_format(&output_buffer).expect("Failed to guess output image format");
@@ -671,11 +2104,11 @@ mod tests {
         let input_buffer = transparent_svg_content.as_bytes();
 
         // Render SVG to image
-        let input_image = render_svg_to_image(input_buffer);
-        let input_image = reduce_colors(&input_image, 32);
+        let input_image = render_svg_to_image(input_buffer, 32).expect("Failed to render SVG");
+        let input_image = reduce_colors(&input_image, 32).expect("Failed to reduce colors");
 
         // Call the convert function with the transparent SVG content
-        let output_buffer: Vec<u8> = convert(input_image);
+        let output_buffer: Vec<u8> = convert(input_image).expect("Failed to convert image");
 
         let guess: image::ImageFormat =
             image::guess_format(&output_buffer).expect("Failed to guess output image format");
@@ -695,27 +2128,27 @@ mod tests {
     }
 
     #[test]
-    #[should_panic(expected = "Failed to parse SVG")]
     fn test_render_svg_to_image_with_invalid_svg() {
         // Invalid SVG content that should cause parsing to fail
         let invalid_svg = b"<svg><invalid></svg>";
-        render_svg_to_image(invalid_svg);
+        let result = render_svg_to_image(invalid_svg, 32);
+        assert!(matches!(result, Err(Error::SvgParse(_))));
     }
 
     #[test]
-    #[should_panic(expected = "Failed to parse SVG")]
     fn test_render_svg_to_image_with_malformed_svg() {
         // Malformed SVG content
         let malformed_svg = b"not an svg at all";
-        render_svg_to_image(malformed_svg);
+        let result = render_svg_to_image(malformed_svg, 32);
+        assert!(matches!(result, Err(Error::SvgParse(_))));
     }
 
     #[test]
-    #[should_panic(expected = "Failed to parse SVG")]
     fn test_render_svg_to_image_with_empty_data() {
         // Empty data should fail to parse
         let empty_data = b"";
-        render_svg_to_image(empty_data);
+        let result = render_svg_to_image(empty_data, 32);
+        assert!(matches!(result, Err(Error::SvgParse(_))));
     }
 
     #[test]
@@ -732,7 +2165,15 @@ mod tests {
         let invalid_output_path = "/root/nonexistent/output.ico".to_string();
 
         // This should handle the write error gracefully
-        convert_paths(&input_path, &invalid_output_path, true);
+        let result = convert_paths(
+            &input_path,
+            &invalid_output_path,
+            &options_with_sizes(&[32]),
+            true,
+        );
+        let err = result.expect_err("Conversion should fail");
+        assert!(matches!(err, CliError::WriteOutput(_)));
+        assert_eq!(err.exit_code(), EXIT_WRITE_OUTPUT);
 
         // The file should not exist
         assert!(!std::path::Path::new(&invalid_output_path).exists());
@@ -751,7 +2192,10 @@ mod tests {
         let output_path = temp_dir.path().to_str().unwrap().to_owned() + "/output.ico";
 
         // This should handle the decode error gracefully
-        convert_paths(&input_path, &output_path, true);
+        let result = convert_paths(&input_path, &output_path, &options_with_sizes(&[32]), true);
+        let err = result.expect_err("Conversion should fail");
+        assert!(matches!(err, CliError::Decode(_)));
+        assert_eq!(err.exit_code(), EXIT_DECODE);
 
         // The output file should not exist since conversion failed
         assert!(!std::path::Path::new(&output_path).exists());
@@ -770,26 +2214,25 @@ mod tests {
         let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
         let output_path = temp_dir.path().to_str().unwrap().to_owned() + "/output.ico";
 
-        // This should panic due to the expect() in render_svg_to_image
-        let result = std::panic::catch_unwind(|| {
-            convert_paths(&input_path, &output_path, true);
-        });
+        // This should handle the SVG parse error gracefully
+        let result = convert_paths(&input_path, &output_path, &options_with_sizes(&[32]), true);
+        let err = result.expect_err("Conversion should fail");
+        assert!(matches!(err, CliError::SvgParse(_)));
+        assert_eq!(err.exit_code(), EXIT_SVG_PARSE);
 
-        // Ensure the function panics due to invalid SVG
-        assert!(result.is_err());
+        // The output file should not exist since conversion failed
+        assert!(!std::path::Path::new(&output_path).exists());
     }
 
     #[test]
-    #[should_panic(expected = "Failed to convert image to RGB8")]
     fn test_convert_with_unsupported_image_format() {
-        // Create an image that might not convert to RGB8 properly
-        // This is a bit tricky since most DynamicImage variants can convert to RGB8
-        // But we can create a scenario where the conversion might fail
+        // Only RGB8 and RGBA8 images are accepted
         let img = DynamicImage::new_luma8(32, 32);
-
-        // For this test, we need to modify the convert function to potentially fail
-        // Since as_rgb8() rarely fails, this test documents the potential failure point
-        convert(img);
+        let result = convert(img);
+        assert!(matches!(
+            result,
+            Err(Error::UnsupportedColorType(image::ColorType::L8))
+        ));
     }
 
     #[test]
@@ -797,14 +2240,9 @@ mod tests {
         // Test edge case with zero colors requested
         let input_image = create_test_image(10, 10, Rgba([255, 0, 0, 255]));
 
-        // This should handle the edge case gracefully or panic predictably
-        let result = std::panic::catch_unwind(|| {
-            reduce_colors(&input_image, 0);
-        });
-
-        // This documents that requesting 0 colors may cause issues
-        // The behavior depends on the NeuQuant implementation
-        assert!(result.is_ok() || result.is_err());
+        // Requesting 0 colors is reported as an error instead of a panic
+        let result = reduce_colors(&input_image, 0);
+        assert!(matches!(result, Err(Error::InvalidColorCount(0))));
     }
 
     #[test]
@@ -813,7 +2251,7 @@ mod tests {
         let input_image = create_test_image(2, 2, Rgba([255, 0, 0, 255]));
 
         // Request more colors than pixels exist
-        let reduced_image = reduce_colors(&input_image, 1000);
+        let reduced_image = reduce_colors(&input_image, 1000).expect("Failed to reduce colors");
 
         // Should still work, but actual unique colors will be limited by input
         assert_eq!(reduced_image.dimensions(), (2, 2));
@@ -844,7 +2282,7 @@ mod tests {
     #[test]
     fn test_resize_to_square_with_zero_output_size() {
         let input_image = create_test_image(100, 100, Rgba([255, 0, 0, 255]));
-        let result = resize_to_square(&input_image, 0);
+        let result = resize_to_square(&input_image, 0, Rgba([0, 0, 0, 0]));
         assert_eq!(result.dimensions(), (0, 0));
     }
 
@@ -852,7 +2290,7 @@ mod tests {
     fn test_resize_to_square_with_very_large_output_size() {
         let input_image = create_test_image(10, 10, Rgba([255, 0, 0, 255]));
         // Test with a large but reasonable output size (1000x1000 instead of 10000x10000)
-        let result = resize_to_square(&input_image, 1000);
+        let result = resize_to_square(&input_image, 1000, Rgba([0, 0, 0, 0]));
         assert_eq!(result.dimensions(), (1000, 1000));
     }
 
@@ -866,8 +2304,10 @@ mod tests {
         "#;
 
         // Should still render to 32x32 regardless of source size
-        let result = render_svg_to_image(large_svg.as_bytes());
+        let result = render_svg_to_image(large_svg.as_bytes(), 32).expect("Failed to render SVG");
         assert_eq!(result.dimensions(), (32, 32));
+        // The whole document is scaled, so the bottom-right corner is red as well
+        assert_eq!(result.get_pixel(31, 31), Rgba([255, 0, 0, 255]));
     }
 
     #[test]
@@ -886,9 +2326,2924 @@ mod tests {
         let invalid_output_path = "/root/nonexistent/output.ico".to_string();
 
         // This should handle the write error gracefully
-        convert_paths(&input_path, &invalid_output_path, true);
+        let result = convert_paths(
+            &input_path,
+            &invalid_output_path,
+            &options_with_sizes(&[32]),
+            true,
+        );
+        let err = result.expect_err("Conversion should fail");
+        assert!(matches!(err, CliError::WriteOutput(_)));
+        assert_eq!(err.exit_code(), EXIT_WRITE_OUTPUT);
 
         // The file should not exist
         assert!(!std::path::Path::new(&invalid_output_path).exists());
     }
+
+    #[test]
+    fn test_convert_frames_with_multiple_sizes() {
+        let images: Vec<DynamicImage> = [16, 32, 256]
+            .iter()
+            .map(|&size| reduce_colors(&create_square_image(size, Rgba([255, 0, 0, 255])), 16))
+            .collect::<Result<_, _>>()
+            .expect("Failed to reduce colors");
+        let output_buffer: Vec<u8> = convert_frames(&images).expect("Failed to convert images");
+
+        let guess: image::ImageFormat =
+            image::guess_format(&output_buffer).expect("Failed to guess output image format");
+        assert_eq!(guess, image::ImageFormat::Ico);
+
+        // The number of images is stored in the ICO header
+        assert_eq!(u16::from_le_bytes([output_buffer[4], output_buffer[5]]), 3);
+
+        // The largest entry is the one decoded by default
+        let dimensions: (u32, u32) = image::ImageReader::new(Cursor::new(&output_buffer))
+            .with_guessed_format()
+            .expect("Cursor io never fails")
+            .into_dimensions()
+            .expect("Failed to get output image dimensions");
+        assert_eq!(dimensions, (256, 256));
+    }
+
+    #[test]
+    fn test_convert_frames_with_too_large_image() {
+        let images = vec![DynamicImage::new_rgb8(257, 257)];
+        let result = convert_frames(&images);
+        assert!(matches!(
+            result,
+            Err(Error::InvalidSize {
+                width: 257,
+                height: 257
+            })
+        ));
+    }
+
+    #[test]
+    fn test_convert_frames_with_no_images() {
+        let result = convert_frames(&[]);
+        assert!(matches!(result, Err(Error::InvalidSize { .. })));
+    }
+
+    #[test]
+    fn test_convert_paths_with_multiple_sizes() {
+        let (_, input_path) = create_temp_image_file(".png", Rgba([255, 0, 0, 255]));
+        let (_temp_dir, output_path) = create_temp_output_file("/output.ico");
+
+        convert_paths(
+            &input_path,
+            &output_path,
+            &options_with_sizes(&[16, 32, 48]),
+            false,
+        )
+        .expect("Failed to convert paths");
+
+        let output_content = fs::read(output_path).expect("Failed to read output file");
+        assert_eq!(
+            u16::from_le_bytes([output_content[4], output_content[5]]),
+            3
+        );
+    }
+
+    #[test]
+    fn test_main_with_sizes_option() {
+        let (_, input_path) = create_temp_image_file(".png", Rgba([255, 0, 0, 255]));
+        let (_temp_dir, output_path) = create_temp_output_file("/output.ico");
+
+        Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .arg(&input_path)
+            .arg(&output_path)
+            .arg("--sizes")
+            .arg("48,16,32,16")
+            .assert()
+            .success();
+
+        // Duplicates are removed and the entries are sorted by size
+        let output_content = fs::read(output_path).expect("Failed to read output file");
+        assert_eq!(
+            u16::from_le_bytes([output_content[4], output_content[5]]),
+            3
+        );
+        assert_eq!(output_content[6], 16);
+        assert_eq!(output_content[6 + 16], 32);
+        assert_eq!(output_content[6 + 32], 48);
+    }
+
+    #[test]
+    fn test_main_with_invalid_size() {
+        let (_, input_path) = create_temp_image_file(".png", Rgba([255, 0, 0, 255]));
+        let (_temp_dir, output_path) = create_temp_output_file("/output.ico");
+
+        Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .arg(&input_path)
+            .arg(&output_path)
+            .arg("--sizes")
+            .arg("32,512")
+            .assert()
+            .failure();
+
+        assert!(!std::path::Path::new(&output_path).exists());
+    }
+
+    #[test]
+    fn test_reduce_colors_keeps_transparency() {
+        // Left half is opaque red, right half fully transparent
+        let input_image = image::RgbaImage::from_fn(32, 32, |x, _| {
+            if x < 16 {
+                Rgba([255, 0, 0, 255])
+            } else {
+                Rgba([0, 0, 0, 0])
+            }
+        });
+        let reduced_image = reduce_colors(&DynamicImage::ImageRgba8(input_image), 16)
+            .expect("Failed to reduce colors");
+
+        assert_eq!(reduced_image.color(), image::ColorType::Rgba8);
+        assert_eq!(reduced_image.get_pixel(31, 0), Rgba([0, 0, 0, 0]));
+        assert!(reduced_image.get_pixel(0, 0)[3] > 250);
+    }
+
+    #[test]
+    fn test_render_svg_to_image_with_semi_transparent_fill() {
+        let svg = r#"
+        <svg width="32" height="32" xmlns="http://www.w3.org/2000/svg">
+            <rect width="32" height="32" style="fill:rgb(0,0,255);fill-opacity:0.5;"/>
+        </svg>
+        "#;
+        let pixel = render_svg_to_image(svg.as_bytes(), 32)
+            .expect("Failed to render SVG")
+            .get_pixel(16, 16);
+
+        // The color is not darkened by premultiplied alpha
+        assert_eq!(pixel[2], 255);
+        assert!(pixel[3] > 100 && pixel[3] < 160);
+    }
+
+    #[test]
+    fn test_convert_keeps_transparency() {
+        let mut input_image = image::RgbaImage::from_pixel(32, 32, Rgba([0, 0, 0, 0]));
+        input_image.put_pixel(16, 16, Rgba([0, 255, 0, 255]));
+        let output_buffer: Vec<u8> =
+            convert(DynamicImage::ImageRgba8(input_image)).expect("Failed to convert image");
+
+        let output_image = image::load_from_memory(&output_buffer).expect("Failed to decode ICO");
+        assert_eq!(output_image.color(), image::ColorType::Rgba8);
+        assert_eq!(output_image.get_pixel(0, 0), Rgba([0, 0, 0, 0]));
+        assert_eq!(output_image.get_pixel(16, 16), Rgba([0, 255, 0, 255]));
+    }
+
+    #[test]
+    fn test_convert_paths_with_transparent_svg() {
+        let temp_input = NamedTempFile::new().expect("Failed to create temp input file");
+        let input_path = temp_input.path().to_str().unwrap().to_owned() + ".svg";
+        let svg_content = r#"
+        <svg width="32" height="32" xmlns="http://www.w3.org/2000/svg">
+            <circle cx="16" cy="16" r="8" style="fill:rgb(0,0,255);"/>
+        </svg>
+        "#;
+        fs::write(&input_path, svg_content).expect("Failed to write SVG content to file");
+        let (_temp_dir, output_path) = create_temp_output_file("/output.ico");
+
+        convert_paths(&input_path, &output_path, &options_with_sizes(&[32]), false)
+            .expect("Failed to convert paths");
+
+        let output_content = fs::read(output_path).expect("Failed to read output file");
+        let output_image = image::load_from_memory(&output_content).expect("Failed to decode ICO");
+        assert_eq!(output_image.get_pixel(16, 16), Rgba([0, 0, 255, 255]));
+        assert_eq!(output_image.get_pixel(31, 31)[3], 0);
+    }
+
+    #[test]
+    fn test_favicon_from_bytes_matches_convert_paths() {
+        let (_, input_path) = create_temp_image_file(".png", Rgba([255, 0, 0, 255]));
+        let (_temp_dir, output_path) = create_temp_output_file("/output.ico");
+        let options = options_with_sizes(&[16, 32]);
+
+        convert_paths(&input_path, &output_path, &options, false).expect("Failed to convert paths");
+
+        let input_buffer = fs::read(&input_path).expect("Failed to read input file");
+        let output_buffer =
+            favicon_from_bytes(&input_buffer, &options).expect("Failed to create favicon");
+        let output_content = fs::read(output_path).expect("Failed to read output file");
+        assert_eq!(output_buffer, output_content);
+    }
+
+    #[test]
+    fn test_decode_image_with_invalid_raster() {
+        // PNG signature followed by garbage
+        let mut input_buffer = vec![0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+        input_buffer.extend_from_slice(b"not an image");
+        let result = decode_image(&input_buffer);
+        assert!(matches!(result, Err(Error::Decode(_))));
+    }
+
+    #[test]
+    fn test_favicon_images_with_invalid_size() {
+        let input_image = create_test_image(10, 10, Rgba([255, 0, 0, 255]));
+        let result = favicon_images(&input_image, &options_with_sizes(&[16, 300]));
+        assert!(matches!(
+            result,
+            Err(Error::InvalidSize {
+                width: 300,
+                height: 300
+            })
+        ));
+    }
+    #[test]
+    fn test_favicon_images_with_empty_image() {
+        for (width, height) in [(0, 0), (0, 16), (16, 0)] {
+            let input_image = DynamicImage::new_rgba8(width, height);
+            let result = favicon_images(&input_image, &FaviconOptions::default());
+            assert!(
+                matches!(result, Err(Error::InvalidSize { width: w, height: h }) if (w, h) == (width, height)),
+                "{result:?}"
+            );
+            assert!(matches!(
+                convert(input_image),
+                Err(Error::InvalidSize { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_create_square_image_fills_whole_canvas() {
+        let square_image: DynamicImage = create_square_image(10, Rgba([0, 255, 0, 255]));
+        assert!(square_image
+            .pixels()
+            .all(|(_, _, pixel)| pixel == Rgba([0, 255, 0, 255])));
+    }
+
+    #[test]
+    fn test_resize_to_square_fills_letterbox_with_background() {
+        let input_image: DynamicImage =
+            DynamicImage::ImageRgb8(image::RgbImage::from_pixel(100, 50, Rgb([255, 0, 0])));
+        let result = resize_to_square(&input_image, 100, Rgba([255, 255, 255, 255]));
+        assert_eq!(result.get_pixel(50, 5), Rgba([255, 255, 255, 255]));
+        assert_eq!(result.get_pixel(99, 94), Rgba([255, 255, 255, 255]));
+        assert_eq!(result.get_pixel(50, 50), Rgba([255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_background_from_str() {
+        assert_eq!(
+            "transparent".parse::<Background>().unwrap(),
+            Background::Transparent
+        );
+        assert_eq!(
+            "Top-Left".parse::<Background>().unwrap(),
+            Background::TopLeft
+        );
+        assert_eq!("edge".parse::<Background>().unwrap(), Background::Edge);
+        assert_eq!(
+            "#123456".parse::<Background>().unwrap(),
+            Background::Color(Rgba([0x12, 0x34, 0x56, 255]))
+        );
+        assert!(matches!(
+            "#12345".parse::<Background>(),
+            Err(Error::InvalidColor(_))
+        ));
+        assert!(matches!(
+            "blue".parse::<Background>(),
+            Err(Error::InvalidColor(_))
+        ));
+    }
+
+    #[test]
+    fn test_get_edge_color() {
+        // A white frame around a red center
+        let input_image = DynamicImage::ImageRgba8(image::RgbaImage::from_fn(10, 10, |x, y| {
+            if x == 0 || y == 0 || x == 9 || y == 9 {
+                Rgba([255, 255, 255, 255])
+            } else {
+                Rgba([255, 0, 0, 255])
+            }
+        }));
+        assert_eq!(get_edge_color(&input_image), Rgba([255, 255, 255, 255]));
+        assert_eq!(get_top_left_color(&input_image), Rgba([255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn test_main_with_background_option() {
+        let (_, input_path) = create_temp_image_file(".png", Rgba([255, 0, 0, 255]));
+        let (_temp_dir, output_path) = create_temp_output_file("/output.ico");
+
+        let assert = Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .arg(&input_path)
+            .arg(&output_path)
+            .arg("--sizes")
+            .arg("32")
+            .arg("--background")
+            .arg("#0000ff")
+            .arg("--verbose")
+            .assert()
+            .success();
+
+        let stdout = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+        assert!(stdout.contains("Background color #0000ff"));
+
+        // The input is taller than wide, so the left edge is background
+        let output_content = fs::read(output_path).expect("Failed to read output file");
+        let output_image = image::load_from_memory(&output_content).expect("Failed to decode ICO");
+        assert_eq!(output_image.get_pixel(0, 16), Rgba([0, 0, 255, 255]));
+    }
+
+    #[test]
+    fn test_main_with_invalid_background() {
+        let (_, input_path) = create_temp_image_file(".png", Rgba([255, 0, 0, 255]));
+        let (_temp_dir, output_path) = create_temp_output_file("/output.ico");
+
+        Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .arg(&input_path)
+            .arg(&output_path)
+            .arg("--background")
+            .arg("not-a-color")
+            .assert()
+            .code(EXIT_INVALID_ARGUMENTS);
+    }
+    #[test]
+    fn test_calculate_crop() {
+        assert_eq!(
+            calculate_crop(300, 100),
+            CropRect {
+                x: 100,
+                y: 0,
+                width: 100,
+                height: 100
+            }
+        );
+        assert_eq!(
+            calculate_crop(100, 301),
+            CropRect {
+                x: 0,
+                y: 100,
+                width: 100,
+                height: 100
+            }
+        );
+        assert_eq!(calculate_crop(50, 50).width, 50);
+    }
+
+    #[test]
+    fn test_smart_crop_finds_detail() {
+        // A tall image with a checkerboard at the top and flat color elsewhere
+        let input_image = DynamicImage::ImageRgba8(image::RgbaImage::from_fn(10, 40, |x, y| {
+            if y < 10 && (x + y) % 2 == 0 {
+                Rgba([0, 0, 0, 255])
+            } else {
+                Rgba([255, 255, 255, 255])
+            }
+        }));
+        let crop = smart_crop(&input_image);
+        assert_eq!(crop.y, 0);
+        assert_eq!((crop.width, crop.height), (10, 10));
+    }
+
+    #[test]
+    fn test_smart_crop_with_uniform_image() {
+        let input_image = create_test_image(300, 100, Rgba([255, 0, 0, 255]));
+        // The single colored pixel at the top-left corner is the only detail
+        assert_eq!(smart_crop(&input_image).x, 0);
+
+        let uniform_image =
+            DynamicImage::ImageRgb8(image::RgbImage::from_pixel(300, 100, Rgb([1, 2, 3])));
+        assert_eq!(smart_crop(&uniform_image), calculate_crop(300, 100));
+    }
+
+    #[test]
+    fn test_fit_to_square_modes() {
+        // Left half red, right half blue
+        let input_image = DynamicImage::ImageRgba8(image::RgbaImage::from_fn(200, 100, |x, _| {
+            if x < 100 {
+                Rgba([255, 0, 0, 255])
+            } else {
+                Rgba([0, 0, 255, 255])
+            }
+        }));
+        let background = Rgba([0, 255, 0, 255]);
+
+        let contain = fit_to_square(&input_image, 20, background, Fit::Contain);
+        assert_eq!(contain.get_pixel(10, 0), background);
+
+        let cover = fit_to_square(&input_image, 20, background, Fit::Cover);
+        assert_eq!(cover.dimensions(), (20, 20));
+        assert_eq!(cover.get_pixel(2, 0), Rgba([255, 0, 0, 255]));
+        assert_eq!(cover.get_pixel(17, 19), Rgba([0, 0, 255, 255]));
+
+        let stretch = fit_to_square(&input_image, 20, background, Fit::Stretch);
+        assert_eq!(stretch.dimensions(), (20, 20));
+        assert_eq!(stretch.get_pixel(2, 0), Rgba([255, 0, 0, 255]));
+        assert_eq!(stretch.get_pixel(17, 19), Rgba([0, 0, 255, 255]));
+    }
+
+    #[test]
+    fn test_fit_from_str() {
+        assert_eq!("cover".parse::<Fit>().unwrap(), Fit::Cover);
+        assert_eq!("SMART".parse::<Fit>().unwrap(), Fit::Smart);
+        assert!(matches!(
+            "fill".parse::<Fit>(),
+            Err(Error::InvalidOption { name: "fit", .. })
+        ));
+    }
+
+    #[test]
+    fn test_main_with_fit_option() {
+        let (_, input_path) = create_temp_image_file(".png", Rgba([255, 0, 0, 255]));
+        let (_temp_dir, output_path) = create_temp_output_file("/output.ico");
+
+        Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .arg(&input_path)
+            .arg(&output_path)
+            .arg("--fit")
+            .arg("cover")
+            .arg("--sizes")
+            .arg("32")
+            .assert()
+            .success();
+
+        let output_content = fs::read(output_path).expect("Failed to read output file");
+        let dimensions = image::load_from_memory(&output_content)
+            .expect("Failed to decode ICO")
+            .dimensions();
+        assert_eq!(dimensions, (32, 32));
+    }
+    #[test]
+    fn test_trim_by_alpha() {
+        let mut input_image = image::RgbaImage::new(20, 20);
+        for x in 5..10 {
+            input_image.put_pixel(x, 8, Rgba([255, 0, 0, 255]));
+        }
+        // Nearly transparent noise is within the tolerance
+        input_image.put_pixel(18, 18, Rgba([255, 255, 255, 4]));
+        let trimmed = trim(&DynamicImage::ImageRgba8(input_image), 16);
+        assert_eq!(trimmed.dimensions(), (5, 1));
+    }
+
+    #[test]
+    fn test_trim_by_border_color() {
+        let input_image = DynamicImage::ImageRgb8(image::RgbImage::from_fn(30, 20, |x, y| {
+            if (10..20).contains(&x) && (5..15).contains(&y) {
+                Rgb([0, 0, 255])
+            } else if x % 2 == 0 {
+                // Slightly uneven white margin
+                Rgb([250, 250, 250])
+            } else {
+                Rgb([255, 255, 255])
+            }
+        }));
+        assert_eq!(trim(&input_image, 16).dimensions(), (10, 10));
+        // Without tolerance the uneven margin counts as content
+        assert_eq!(trim(&input_image, 0).dimensions(), (29, 20));
+    }
+
+    #[test]
+    fn test_trim_without_content() {
+        let input_image = create_test_image(10, 10, Rgba([0, 0, 0, 0]));
+        assert_eq!(trim(&input_image, 16).dimensions(), (10, 10));
+    }
+
+    #[test]
+    fn test_add_padding() {
+        let input_image =
+            DynamicImage::ImageRgb8(image::RgbImage::from_pixel(10, 5, Rgb([255, 0, 0])));
+        let padded = add_padding(&input_image, 0.2, Rgba([0, 0, 255, 255])).unwrap();
+        assert_eq!(padded.dimensions(), (14, 9));
+        assert_eq!(padded.get_pixel(0, 0), Rgba([0, 0, 255, 255]));
+        assert_eq!(padded.get_pixel(2, 2), Rgba([255, 0, 0, 255]));
+
+        // The padded size has to fit in u32
+        assert!(matches!(
+            add_padding(&input_image, 1e9, Rgba([0, 0, 0, 0])),
+            Err(Error::InvalidSize { .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_padding() {
+        assert_eq!(parse_padding("10%").unwrap(), 0.1);
+        assert_eq!(parse_padding("0").unwrap(), 0.0);
+        assert_eq!(parse_padding("100%").unwrap(), MAX_PADDING);
+        assert!(parse_padding("-5%").is_err());
+        assert!(parse_padding("ten").is_err());
+        assert!(matches!(
+            parse_padding("1000000%"),
+            Err(Error::InvalidOption {
+                name: "padding",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_main_with_too_large_padding() {
+        let (_, input_path) = create_temp_image_file(".png", Rgba([255, 0, 0, 255]));
+        let (_temp_dir, output_path) = create_temp_output_file("/favicon.ico");
+        Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .args([&input_path, &output_path, "--padding", "1000000%"])
+            .assert()
+            .code(EXIT_INVALID_ARGUMENTS);
+        assert!(!Path::new(&output_path).exists());
+    }
+
+    #[test]
+    fn test_favicon_images_with_trim_and_padding() {
+        // A small red mark in a large white margin
+        let input_image = DynamicImage::ImageRgb8(image::RgbImage::from_fn(100, 100, |x, y| {
+            if (40..60).contains(&x) && (40..60).contains(&y) {
+                Rgb([255, 0, 0])
+            } else {
+                Rgb([255, 255, 255])
+            }
+        }));
+        let options = FaviconOptions {
+            sizes: vec![32],
+            quantize: Some(QuantizeOptions {
+                colors: 256,
+                ..Default::default()
+            }),
+            trim: Some(16),
+            ..Default::default()
+        };
+        let images = favicon_images(&input_image, &options).expect("Failed to create images");
+        // The mark fills the whole icon
+        assert!(images[0].get_pixel(1, 1)[1] < 64);
+
+        let options = FaviconOptions {
+            padding: 0.25,
+            ..options
+        };
+        let images = favicon_images(&input_image, &options).expect("Failed to create images");
+        // The padding is filled with the white background
+        assert!(images[0].get_pixel(1, 1)[1] > 192);
+        assert!(images[0].get_pixel(16, 16)[1] < 64);
+    }
+
+    #[test]
+    fn test_main_with_trim_option() {
+        let (_, input_path) = create_temp_image_file(".png", Rgba([255, 0, 0, 255]));
+        let (_temp_dir, output_path) = create_temp_output_file("/output.ico");
+
+        let assert = Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .arg(&input_path)
+            .arg(&output_path)
+            .arg("--trim")
+            .arg("--padding")
+            .arg("10%")
+            .arg("--verbose")
+            .assert()
+            .success();
+
+        let stdout = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+        assert!(stdout.contains("Content bounds for trimming"));
+    }
+    #[test]
+    fn test_render_svg_to_image_with_view_box() {
+        // A wide document where only the viewBox defines the size
+        let svg = r#"
+        <svg viewBox="0 0 400 100" xmlns="http://www.w3.org/2000/svg">
+            <rect width="200" height="100" style="fill:rgb(255,0,0);"/>
+            <rect x="200" width="200" height="100" style="fill:rgb(0,0,255);"/>
+        </svg>
+        "#;
+        let result = render_svg_to_image(svg.as_bytes(), 64).expect("Failed to render SVG");
+        assert_eq!(result.dimensions(), (64, 16));
+        assert_eq!(result.get_pixel(1, 8), Rgba([255, 0, 0, 255]));
+        assert_eq!(result.get_pixel(62, 8), Rgba([0, 0, 255, 255]));
+    }
+
+    #[test]
+    fn test_render_svg_to_image_with_zero_size() {
+        let svg = br#"<svg width="10" height="10" xmlns="http://www.w3.org/2000/svg"/>"#;
+        let result = render_svg_to_image(svg, 0);
+        assert!(matches!(result, Err(Error::InvalidSize { .. })));
+    }
+
+    #[test]
+    fn test_favicon_images_from_svg_renders_each_size() {
+        // A thin line that would be lost when downscaling a single large rendering
+        let svg = r#"
+        <svg width="1024" height="1024" xmlns="http://www.w3.org/2000/svg">
+            <rect width="1024" height="1024" style="fill:rgb(255,255,255);"/>
+            <rect x="0" y="512" width="1024" height="64" style="fill:rgb(0,0,0);"/>
+        </svg>
+        "#;
+        let options = FaviconOptions {
+            sizes: vec![16, 256],
+            quantize: Some(QuantizeOptions {
+                colors: 256,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let images = favicon_images_from_svg(svg.as_bytes(), &options)
+            .expect("Failed to create images from SVG");
+        assert_eq!(images[0].dimensions(), (16, 16));
+        assert_eq!(images[1].dimensions(), (256, 256));
+        assert!(images[0].get_pixel(8, 8)[0] < 64);
+        assert!(images[1].get_pixel(128, 140)[0] < 64);
+        assert!(images[1].get_pixel(128, 100)[0] > 192);
+    }
+
+    #[test]
+    fn test_favicon_images_from_svg_document() {
+        let document = SvgDocument::parse(SQUARE_SVG.as_bytes()).expect("Failed to parse SVG");
+        assert_eq!(
+            document.reference(),
+            &render_svg_to_image(SQUARE_SVG.as_bytes(), SVG_REFERENCE_SIZE).unwrap()
+        );
+        let options = options_with_sizes(&[16, 48]);
+        assert_eq!(
+            favicon_images_from_svg_document(&document, &options).unwrap(),
+            favicon_images_from_svg(SQUARE_SVG.as_bytes(), &options).unwrap()
+        );
+        assert!(matches!(
+            SvgDocument::parse(b"<svg"),
+            Err(Error::SvgParse(_))
+        ));
+    }
+
+    #[test]
+    fn test_favicon_images_from_svg_with_trim() {
+        // A small square in the middle of a large transparent document
+        let svg = r#"
+        <svg width="1000" height="1000" xmlns="http://www.w3.org/2000/svg">
+            <rect x="450" y="450" width="100" height="100" style="fill:rgb(255,0,0);"/>
+        </svg>
+        "#;
+        let options = FaviconOptions {
+            sizes: vec![32],
+            trim: Some(16),
+            ..Default::default()
+        };
+        let images = favicon_images_from_svg(svg.as_bytes(), &options)
+            .expect("Failed to create images from SVG");
+        // The square fills the icon, apart from the anti-aliased outermost pixels
+        assert!(images[0].get_pixel(2, 2)[3] > 200);
+        assert!(images[0].get_pixel(29, 29)[3] > 200);
+    }
+    // Helper function to compress data with gzip, as used by SVGZ files
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        use flate2::write::GzEncoder;
+        use std::io::Write;
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).expect("Failed to compress data");
+        encoder.finish().expect("Failed to finish compression")
+    }
+
+    #[test]
+    fn test_detect_format() {
+        let mut png = Vec::new();
+        create_test_image(4, 4, Rgba([255, 0, 0, 255]))
+            .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+            .expect("Failed to encode PNG");
+        assert_eq!(
+            detect_format(&png).unwrap(),
+            InputFormat::Raster(image::ImageFormat::Png)
+        );
+
+        let svg = "\u{feff}\n  <!-- logo -->\n<svg xmlns=\"http://www.w3.org/2000/svg\"/>";
+        assert_eq!(detect_format(svg.as_bytes()).unwrap(), InputFormat::Svg);
+        assert_eq!(
+            detect_format(&gzip(svg.as_bytes())).unwrap(),
+            InputFormat::Svgz
+        );
+        assert!(matches!(
+            detect_format(&gzip(b"plain text in a .gz archive")),
+            Err(Error::UnsupportedFormat)
+        ));
+        assert!(matches!(
+            detect_format(&[0x1F, 0x8B, 0x08, 0x00]),
+            Err(Error::UnsupportedFormat)
+        ));
+
+        assert!(matches!(
+            detect_format(b"not an image"),
+            Err(Error::UnsupportedFormat)
+        ));
+        assert!(matches!(
+            detect_format(b"<html><body></body></html>"),
+            Err(Error::UnsupportedFormat)
+        ));
+    }
+
+    #[test]
+    fn test_unsupported_format_lists_formats() {
+        let message = Error::UnsupportedFormat.to_string();
+        for format in supported_input_formats() {
+            assert!(message.contains(&format));
+        }
+    }
+
+    #[test]
+    fn test_decode_image_with_svgz() {
+        let svg = r#"<svg width="10" height="10" xmlns="http://www.w3.org/2000/svg"><rect width="10" height="10" style="fill:rgb(0,255,0);"/></svg>"#;
+        let img = decode_image(&gzip(svg.as_bytes())).expect("Failed to decode SVGZ");
+        assert_eq!(img.get_pixel(5, 5), Rgba([0, 255, 0, 255]));
+    }
+
+    #[test]
+    fn test_convert_paths_with_svg_without_suffix() {
+        let temp_input = NamedTempFile::new().expect("Failed to create temp input file");
+        let input_path = temp_input.path().to_str().unwrap().to_owned() + ".SVG";
+        let svg_content = r#"<svg width="100" height="100" xmlns="http://www.w3.org/2000/svg"><rect width="100" height="100" style="fill:rgb(0,0,255);"/></svg>"#;
+        fs::write(&input_path, svg_content).expect("Failed to write SVG content to file");
+        let (_temp_dir, output_path) = create_temp_output_file("/output.ico");
+
+        convert_paths(&input_path, &output_path, &options_with_sizes(&[32]), false)
+            .expect("Failed to convert paths");
+
+        let output_content = fs::read(output_path).expect("Failed to read output file");
+        let output_image = image::load_from_memory(&output_content).expect("Failed to decode ICO");
+        assert_eq!(output_image.get_pixel(16, 16), Rgba([0, 0, 255, 255]));
+    }
+
+    #[test]
+    fn test_main_with_svgz_input() {
+        let temp_input = NamedTempFile::new().expect("Failed to create temp input file");
+        let input_path = temp_input.path().to_str().unwrap().to_owned() + ".svgz";
+        let svg_content = r#"<svg width="100" height="100" xmlns="http://www.w3.org/2000/svg"><rect width="100" height="100" style="fill:rgb(0,0,255);"/></svg>"#;
+        fs::write(&input_path, gzip(svg_content.as_bytes())).expect("Failed to write SVGZ");
+        let (_temp_dir, output_path) = create_temp_output_file("/output.ico");
+
+        Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .arg(&input_path)
+            .arg(&output_path)
+            .assert()
+            .success();
+
+        assert!(std::path::Path::new(&output_path).exists());
+    }
+
+    #[test]
+    fn test_main_with_gzip_input_that_is_not_svg() {
+        let (temp_dir, input_path) = create_temp_output_file("/notes.txt.gz");
+        fs::write(&input_path, gzip(b"meeting notes")).expect("Failed to write input");
+        let output_path = temp_dir.path().join("output.ico");
+
+        Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .arg(&input_path)
+            .arg(&output_path)
+            .assert()
+            .code(EXIT_DECODE);
+        assert!(!output_path.exists());
+    }
+
+    #[test]
+    fn test_main_with_unsupported_format() {
+        let temp_input = NamedTempFile::new().expect("Failed to create temp input file");
+        let input_path = temp_input.path().to_str().unwrap().to_owned();
+        fs::write(&input_path, b"plain text").expect("Failed to write input");
+        let (_temp_dir, output_path) = create_temp_output_file("/output.ico");
+
+        let assert = Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .arg(&input_path)
+            .arg(&output_path)
+            .assert()
+            .code(EXIT_DECODE);
+
+        let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+        assert!(stderr.contains("this build can decode SVG, SVGZ,"));
+    }
+
+    // Helper function to create a horizontal gray gradient, which bands when reduced
+    fn create_gradient_image(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageRgba8(image::RgbaImage::from_fn(width, height, |x, _| {
+            let level = (x * 255 / (width - 1)) as u8;
+            Rgba([level, level, level, 255])
+        }))
+    }
+
+    #[test]
+    fn test_quantize_with_dithering() {
+        let input_image = create_gradient_image(64, 16);
+        for dither in [Dither::None, Dither::FloydSteinberg, Dither::Ordered] {
+            let options = QuantizeOptions {
+                colors: 4,
+                dither,
+                ..Default::default()
+            };
+            let reduced_image = quantize(&input_image, &options).expect("Failed to quantize");
+            let unique_colors: std::collections::HashSet<_> =
+                reduced_image.to_rgba8().pixels().copied().collect();
+            assert!(unique_colors.len() <= 4);
+        }
+    }
+
+    #[test]
+    fn test_quantize_dithering_mixes_colors_across_bands() {
+        let input_image = create_gradient_image(64, 16);
+        let count_columns_with_mixed_colors = |dither| {
+            let options = QuantizeOptions {
+                colors: 2,
+                dither,
+                ..Default::default()
+            };
+            let reduced_image = quantize(&input_image, &options)
+                .expect("Failed to quantize")
+                .to_rgba8();
+            (0..64)
+                .filter(|&x| (1..16).any(|y| reduced_image[(x, y)] != reduced_image[(x, 0)]))
+                .count()
+        };
+        // Without dithering each column maps to a single color
+        assert_eq!(count_columns_with_mixed_colors(Dither::None), 0);
+        assert!(count_columns_with_mixed_colors(Dither::FloydSteinberg) > 0);
+        assert!(count_columns_with_mixed_colors(Dither::Ordered) > 0);
+    }
+
+    #[test]
+    fn test_quantize_dithering_keeps_transparency() {
+        let mut input_image = create_gradient_image(32, 32).to_rgba8();
+        for y in 0..16 {
+            for x in 0..32 {
+                input_image.put_pixel(x, y, Rgba([0, 0, 0, 0]));
+            }
+        }
+        let options = QuantizeOptions {
+            colors: 4,
+            dither: Dither::FloydSteinberg,
+            ..Default::default()
+        };
+        let reduced_image = quantize(&DynamicImage::ImageRgba8(input_image), &options)
+            .expect("Failed to quantize")
+            .to_rgba8();
+        assert!((0..16).all(|y| reduced_image[(5, y)] == Rgba([0, 0, 0, 0])));
+        assert_eq!(reduced_image[(5, 20)][3], 255);
+    }
+
+    #[test]
+    fn test_quantize_with_invalid_sample_factor() {
+        let input_image = create_gradient_image(8, 8);
+        for sample_factor in [0, 31] {
+            let options = QuantizeOptions {
+                sample_factor,
+                ..Default::default()
+            };
+            let result = quantize(&input_image, &options);
+            assert!(matches!(
+                result,
+                Err(Error::InvalidOption {
+                    name: "sample factor",
+                    ..
+                })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_dither_from_str() {
+        assert_eq!("none".parse::<Dither>().unwrap(), Dither::None);
+        assert_eq!("FS".parse::<Dither>().unwrap(), Dither::FloydSteinberg);
+        assert_eq!(
+            "floyd-steinberg".parse::<Dither>().unwrap(),
+            Dither::FloydSteinberg
+        );
+        assert_eq!("bayer".parse::<Dither>().unwrap(), Dither::Ordered);
+        assert!(matches!(
+            "noise".parse::<Dither>(),
+            Err(Error::InvalidOption { name: "dither", .. })
+        ));
+    }
+
+    #[test]
+    fn test_favicon_images_with_true_color() {
+        let input_image = create_gradient_image(64, 64);
+        let options = FaviconOptions {
+            quantize: None,
+            ..options_with_sizes(&[64])
+        };
+        let images = favicon_images(&input_image, &options).expect("Failed to create images");
+        // The gradient is kept intact without reducing its colors
+        assert_eq!(images[0], DynamicImage::ImageRgba8(input_image.to_rgba8()));
+    }
+
+    #[test]
+    fn test_main_with_color_options() {
+        let (_temp_file, input_path) = create_temp_image_file(".png", Rgba([255, 0, 0, 255]));
+        let (_temp_dir, output_path) = create_temp_output_file("/output.ico");
+
+        Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .args(["--colors", "0", "--sizes", "16", &input_path, &output_path])
+            .assert()
+            .success();
+        Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .args([
+                "--colors",
+                "8",
+                "--sample-factor",
+                "10",
+                "--dither",
+                "ordered",
+            ])
+            .args([&input_path, &output_path])
+            .assert()
+            .success();
+        Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .args(["--colors", "257", &input_path, &output_path])
+            .assert()
+            .code(EXIT_INVALID_ARGUMENTS);
+    }
+
+    // Helper function to create an image with many colors and some transparency
+    fn create_colorful_image(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageRgba8(image::RgbaImage::from_fn(width, height, |x, y| {
+            let alpha = if x < 4 { 0 } else { 255 };
+            Rgba([(x * 7) as u8, (y * 11) as u8, ((x + y) * 3) as u8, alpha])
+        }))
+    }
+
+    #[test]
+    fn test_quantizers_reduce_colors() {
+        let input_image = create_colorful_image(48, 48);
+        for quantizer in [Quantizer::NeuQuant, Quantizer::MedianCut, Quantizer::Octree] {
+            for colors in [1, 2, 16, 256] {
+                let options = QuantizeOptions {
+                    colors,
+                    quantizer,
+                    ..Default::default()
+                };
+                let reduced_image = quantize(&input_image, &options)
+                    .expect("Failed to quantize")
+                    .to_rgba8();
+                let unique_colors: std::collections::HashSet<_> =
+                    reduced_image.pixels().copied().collect();
+                // The transparent pixels are kept apart from the palette colors
+                assert!(unique_colors.len() <= colors + 1, "{quantizer} {colors}");
+                assert_eq!(reduced_image[(0, 0)], Rgba([0, 0, 0, 0]));
+            }
+        }
+    }
+
+    #[test]
+    fn test_quantizers_are_deterministic() {
+        let input_image = create_colorful_image(64, 64);
+        for quantizer in [Quantizer::NeuQuant, Quantizer::MedianCut, Quantizer::Octree] {
+            let options = QuantizeOptions {
+                colors: 16,
+                quantizer,
+                dither: Dither::FloydSteinberg,
+                ..Default::default()
+            };
+            let first = quantize(&input_image, &options).expect("Failed to quantize");
+            let second = quantize(&input_image, &options).expect("Failed to quantize");
+            assert_eq!(first, second, "{quantizer}");
+        }
+    }
+
+    #[test]
+    fn test_quantizers_keep_few_colors_exact() {
+        let colors = [
+            Rgba([255, 0, 0, 255]),
+            Rgba([0, 128, 0, 255]),
+            Rgba([0, 0, 255, 128]),
+        ];
+        let input_image = image::RgbaImage::from_fn(30, 30, |x, _| colors[(x / 10) as usize]);
+        let quantizers: [&dyn PaletteQuantizer; 3] = [
+            &NeuQuantQuantizer::default(),
+            &MedianCutQuantizer,
+            &OctreeQuantizer,
+        ];
+        for quantizer in quantizers {
+            let palette = quantizer.palette(&input_image, 16, ColorSpace::Srgb);
+            for color in colors {
+                assert!(palette.contains(&color), "{palette:?}");
+            }
+        }
+        for color_space in [ColorSpace::Srgb, ColorSpace::Lab, ColorSpace::Oklab] {
+            for quantizer in [
+                &MedianCutQuantizer as &dyn PaletteQuantizer,
+                &OctreeQuantizer,
+            ] {
+                let palette = quantizer.palette(&input_image, 16, color_space);
+                assert_eq!(palette.len(), 3, "{color_space}");
+                for color in colors {
+                    assert!(palette.contains(&color), "{color_space}: {palette:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_quantizers_in_perceptual_color_space() {
+        // The dark grays span a narrower sRGB range than the light ones, but a wider lightness range
+        let input_image = image::RgbaImage::from_fn(222, 1, |x, _| {
+            let value = if x < 111 { x * 100 / 110 } else { x + 34 } as u8;
+            Rgba([value, value, value, 255])
+        });
+        let dark_colors = |color_space| {
+            let palette = MedianCutQuantizer.palette(&input_image, 3, color_space);
+            assert_eq!(palette.len(), 3);
+            palette.iter().filter(|color| color[0] < 128).count()
+        };
+        assert_eq!(dark_colors(ColorSpace::Srgb), 1);
+        assert_eq!(dark_colors(ColorSpace::Lab), 2);
+        assert_eq!(dark_colors(ColorSpace::Oklab), 2);
+
+        let input_image = create_colorful_image(32, 32).to_rgba8();
+        let srgb = OctreeQuantizer.palette(&input_image, 8, ColorSpace::Srgb);
+        for color_space in [ColorSpace::Lab, ColorSpace::Oklab] {
+            let palette = OctreeQuantizer.palette(&input_image, 8, color_space);
+            assert!(!palette.is_empty() && palette.len() <= 8);
+            assert_ne!(palette, srgb, "{color_space}");
+        }
+    }
+
+    #[test]
+    fn test_quantizers_with_transparent_image() {
+        let input_image = image::RgbaImage::new(8, 8);
+        assert!(MedianCutQuantizer
+            .palette(&input_image, 4, ColorSpace::Srgb)
+            .is_empty());
+        assert!(OctreeQuantizer
+            .palette(&input_image, 4, ColorSpace::Lab)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_quantizer_from_str() {
+        assert_eq!(
+            "NeuQuant".parse::<Quantizer>().unwrap(),
+            Quantizer::NeuQuant
+        );
+        assert_eq!(
+            "median-cut".parse::<Quantizer>().unwrap(),
+            Quantizer::MedianCut
+        );
+        assert_eq!("octree".parse::<Quantizer>().unwrap(), Quantizer::Octree);
+        assert!(matches!(
+            "wu".parse::<Quantizer>(),
+            Err(Error::InvalidOption {
+                name: "quantizer",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_main_with_quantizer() {
+        let (_temp_file, input_path) = create_temp_image_file(".png", Rgba([255, 0, 0, 255]));
+        let (_temp_dir, output_path) = create_temp_output_file("/output.ico");
+
+        for quantizer in ["median-cut", "octree"] {
+            Command::cargo_bin(env!("CARGO_PKG_NAME"))
+                .expect("Binary not found")
+                .args(["--quantizer", quantizer, &input_path, &output_path])
+                .assert()
+                .success();
+        }
+        Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .args(["--quantizer", "wu", &input_path, &output_path])
+            .assert()
+            .code(EXIT_INVALID_ARGUMENTS);
+    }
+
+    // A palette quantizer that always picks black and white
+    struct BlackAndWhite;
+
+    impl PaletteQuantizer for BlackAndWhite {
+        fn palette(
+            &self,
+            _img: &image::RgbaImage,
+            _colors: usize,
+            _color_space: ColorSpace,
+        ) -> Vec<Rgba<u8>> {
+            vec![Rgba([0, 0, 0, 255]), Rgba([255, 255, 255, 255])]
+        }
+    }
+
+    #[test]
+    fn test_quantize_with_perceptual_color_space() {
+        // Mid gray in sRGB values is darker than the perceptual middle of black and white
+        let input_image = create_square_image(4, Rgba([120, 120, 120, 255]));
+        let nearest = |color_space| {
+            quantize_with(&input_image, &BlackAndWhite, 2, Dither::None, color_space)
+                .expect("Failed to quantize")
+                .get_pixel(0, 0)
+        };
+        assert_eq!(nearest(ColorSpace::Srgb), Rgba([0, 0, 0, 255]));
+        assert_eq!(nearest(ColorSpace::Lab), Rgba([255, 255, 255, 255]));
+        assert_eq!(nearest(ColorSpace::Oklab), Rgba([255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn test_color_space_coordinates() {
+        let close = |a: [f32; 4], b: [f32; 4]| a.iter().zip(b).all(|(a, b)| (a - b).abs() < 0.01);
+        assert!(close(
+            ColorSpace::Lab.coordinates([0.0, 0.0, 0.0, 0.0]),
+            [0.0, 0.0, 0.0, 0.0]
+        ));
+        // Reference values of pure red
+        assert!(close(
+            ColorSpace::Lab.coordinates([255.0, 0.0, 0.0, 255.0]),
+            [53.24, 80.09, 67.20, 100.0]
+        ));
+        assert!(close(
+            ColorSpace::Oklab.coordinates([255.0, 0.0, 0.0, 255.0]),
+            [0.628, 0.2249, 0.1258, 1.0]
+        ));
+        assert!(close(
+            ColorSpace::Oklab.coordinates([255.0, 255.0, 255.0, 255.0]),
+            [1.0, 0.0, 0.0, 1.0]
+        ));
+        assert_eq!(
+            ColorSpace::Srgb.coordinates([1.0, 2.0, 3.0, 4.0]),
+            [1.0, 2.0, 3.0, 4.0]
+        );
+    }
+
+    #[test]
+    fn test_color_space_from_str() {
+        assert_eq!("sRGB".parse::<ColorSpace>().unwrap(), ColorSpace::Srgb);
+        assert_eq!("cielab".parse::<ColorSpace>().unwrap(), ColorSpace::Lab);
+        assert_eq!("OKLab".parse::<ColorSpace>().unwrap(), ColorSpace::Oklab);
+        assert!(matches!(
+            "hsv".parse::<ColorSpace>(),
+            Err(Error::InvalidOption {
+                name: "color space",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_main_with_color_space() {
+        let (_temp_file, input_path) = create_temp_image_file(".png", Rgba([255, 0, 0, 255]));
+        let (_temp_dir, output_path) = create_temp_output_file("/output.ico");
+
+        Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .args(["--color-space", "oklab", &input_path, &output_path])
+            .assert()
+            .success();
+    }
+
+    #[test]
+    fn test_parse_palette_formats() {
+        let expected = vec![Rgba([255, 136, 0, 255]), Rgba([0, 51, 102, 255])];
+
+        let gpl = "GIMP Palette\nName: Brand\nColumns: 2\n# Approved colors\n255 136 0\tOrange\n\n  0  51 102 Navy blue\n";
+        assert_eq!(
+            parse_palette(gpl.as_bytes(), PaletteFormat::Gpl).unwrap(),
+            expected
+        );
+
+        let hex = "; Approved colors\n#ff8800\n003366 ; navy\n";
+        assert_eq!(
+            parse_palette(hex.as_bytes(), PaletteFormat::Hex).unwrap(),
+            expected
+        );
+        assert_eq!(
+            parse_palette(b"#f80, #ff880080", PaletteFormat::Hex).unwrap(),
+            vec![Rgba([255, 136, 0, 255]), Rgba([255, 136, 0, 128])]
+        );
+
+        let mut act = vec![0; 768];
+        act[..6].copy_from_slice(&[255, 136, 0, 0, 51, 102]);
+        assert_eq!(parse_palette(&act, PaletteFormat::Act).unwrap().len(), 256);
+        // The count and transparent index limit the colors
+        let mut act_with_count = act.clone();
+        act_with_count.extend_from_slice(&[0, 3, 0, 2]);
+        assert_eq!(
+            parse_palette(&act_with_count, PaletteFormat::Act).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_parse_palette_errors() {
+        assert!(matches!(
+            parse_palette(b"255 0 0\n", PaletteFormat::Gpl),
+            Err(Error::InvalidPalette(_))
+        ));
+        assert!(matches!(
+            parse_palette(b"GIMP Palette\n255 0\n", PaletteFormat::Gpl),
+            Err(Error::InvalidPalette(_))
+        ));
+        assert!(matches!(
+            parse_palette(b"GIMP Palette\n300 0 0\n", PaletteFormat::Gpl),
+            Err(Error::InvalidPalette(_))
+        ));
+        assert!(matches!(
+            parse_palette(b"#ff8800\norange\n", PaletteFormat::Hex),
+            Err(Error::InvalidColor(_))
+        ));
+        assert!(matches!(
+            parse_palette(b"; nothing here\n", PaletteFormat::Hex),
+            Err(Error::InvalidPalette(_))
+        ));
+        assert!(matches!(
+            parse_palette(&[0; 100], PaletteFormat::Act),
+            Err(Error::InvalidPalette(_))
+        ));
+    }
+
+    #[test]
+    fn test_write_palette_round_trip() {
+        let colors = vec![
+            Rgba([255, 136, 0, 255]),
+            Rgba([0, 51, 102, 255]),
+            Rgba([1, 2, 3, 255]),
+        ];
+        for format in [PaletteFormat::Gpl, PaletteFormat::Act, PaletteFormat::Hex] {
+            let data = write_palette(&colors, format).expect("Failed to write palette");
+            assert_eq!(parse_palette(&data, format).unwrap(), colors, "{format:?}");
+        }
+        assert!(matches!(
+            write_palette(&vec![Rgba([0, 0, 0, 255]); 257], PaletteFormat::Act),
+            Err(Error::InvalidPalette(_))
+        ));
+    }
+
+    #[test]
+    fn test_palette_format_from_path() {
+        assert_eq!(PaletteFormat::from_path("brand.gpl"), PaletteFormat::Gpl);
+        assert_eq!(
+            PaletteFormat::from_path("dir/brand.ACT"),
+            PaletteFormat::Act
+        );
+        assert_eq!(PaletteFormat::from_path("brand.hex"), PaletteFormat::Hex);
+        assert_eq!(PaletteFormat::from_path("brand"), PaletteFormat::Hex);
+    }
+
+    #[test]
+    fn test_quantize_with_fixed_palette() {
+        let brand = vec![Rgba([255, 136, 0, 255]), Rgba([0, 51, 102, 255])];
+        let input_image = create_colorful_image(32, 32);
+        let options = QuantizeOptions {
+            palette: Some(brand.clone()),
+            dither: Dither::FloydSteinberg,
+            ..Default::default()
+        };
+        let reduced_image = quantize(&input_image, &options)
+            .expect("Failed to quantize")
+            .to_rgba8();
+        for pixel in reduced_image.pixels() {
+            assert!(pixel[3] == 0 || brand.contains(pixel), "{pixel:?}");
+        }
+        assert_eq!(choose_palette(&input_image, &options).unwrap(), brand);
+
+        let empty = QuantizeOptions {
+            palette: Some(Vec::new()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            quantize(&input_image, &empty),
+            Err(Error::InvalidPalette(_))
+        ));
+    }
+
+    #[test]
+    fn test_choose_palette() {
+        let input_image = create_colorful_image(32, 32);
+        let options = QuantizeOptions {
+            colors: 8,
+            quantizer: Quantizer::Octree,
+            ..Default::default()
+        };
+        let palette = choose_palette(&input_image, &options).expect("Failed to choose palette");
+        assert!(!palette.is_empty() && palette.len() <= 8);
+        // Quantizing maps onto the same palette
+        let reduced_image = quantize(&input_image, &options).unwrap().to_rgba8();
+        for pixel in reduced_image.pixels() {
+            assert!(pixel[3] == 0 || palette.contains(pixel));
+        }
+        let fixed = FixedPalette(palette.clone());
+        assert_eq!(
+            fixed.palette(&input_image.to_rgba8(), 1, ColorSpace::Srgb),
+            palette
+        );
+    }
+
+    #[test]
+    fn test_main_with_palette_files() {
+        let (_temp_file, input_path) = create_temp_image_file(".png", Rgba([250, 10, 10, 255]));
+        let (temp_dir, output_path) = create_temp_output_file("/output.ico");
+        let palette_path = temp_dir.path().join("brand.gpl");
+        fs::write(
+            &palette_path,
+            "GIMP Palette\nName: Brand\n#\n255   0   0\tRed\n  0   0 255\tBlue\n",
+        )
+        .expect("Failed to write palette");
+        let export_path = temp_dir.path().join("chosen.hex");
+
+        Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .arg("--palette")
+            .arg(&palette_path)
+            .arg("--export-palette")
+            .arg(&export_path)
+            .args(["--sizes", "32", &input_path, &output_path])
+            .assert()
+            .success();
+
+        let output_content = fs::read(&output_path).expect("Failed to read output file");
+        let output_image = image::load_from_memory(&output_content).expect("Failed to decode ICO");
+        assert_eq!(output_image.get_pixel(0, 0), Rgba([255, 0, 0, 255]));
+        // Only the colors of the fixed palette that ended up in the icon
+        assert_eq!(
+            fs::read_to_string(&export_path).expect("Failed to read palette"),
+            "#ff0000\n"
+        );
+    }
+
+    #[test]
+    fn test_main_with_export_palette_matches_icon() {
+        let (temp_dir, input_path) = create_temp_output_file("/colorful.png");
+        create_colorful_image(64, 64).save(&input_path).unwrap();
+        let output_path = temp_dir.path().join("favicon.ico");
+        let export_path = temp_dir.path().join("icon.hex");
+
+        Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .args([&input_path, "--colors", "8", "--sizes", "16,48"])
+            .arg("--output")
+            .arg(&output_path)
+            .arg("--export-palette")
+            .arg(&export_path)
+            .assert()
+            .success();
+
+        let entries = read_ico(&fs::read(&output_path).unwrap()).unwrap();
+        let mut icon_colors: HashSet<Rgba<u8>> = HashSet::new();
+        for entry in &entries {
+            icon_colors.extend(
+                entry
+                    .decode()
+                    .unwrap()
+                    .pixels()
+                    .filter(|pixel| pixel[3] > 0),
+            );
+        }
+        let exported = parse_palette(&fs::read(&export_path).unwrap(), PaletteFormat::Hex).unwrap();
+        assert_eq!(exported.len(), icon_colors.len());
+        assert_eq!(exported.into_iter().collect::<HashSet<_>>(), icon_colors);
+    }
+
+    #[test]
+    fn test_main_with_invalid_palette() {
+        let (_temp_file, input_path) = create_temp_image_file(".png", Rgba([250, 10, 10, 255]));
+        let (temp_dir, output_path) = create_temp_output_file("/output.ico");
+        let palette_path = temp_dir.path().join("brand.txt");
+        fs::write(&palette_path, "orange\n").expect("Failed to write palette");
+
+        Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .arg("--palette")
+            .arg(&palette_path)
+            .args([&input_path, &output_path])
+            .assert()
+            .code(EXIT_INVALID_ARGUMENTS);
+
+        Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .arg("--palette")
+            .arg(temp_dir.path().join("missing.gpl"))
+            .args([&input_path, &output_path])
+            .assert()
+            .code(EXIT_READ_INPUT);
+    }
+
+    // Helper function to create an image with the given number of opaque colors
+    // and a fully transparent first column
+    fn create_indexed_image(size: u32, colors: u32) -> image::RgbaImage {
+        image::RgbaImage::from_fn(size, size, |x, y| {
+            if x == 0 {
+                Rgba([0, 0, 0, 0])
+            } else {
+                let index = (x * size + y) % colors;
+                Rgba([index as u8, (index >> 8) as u8 * 100, 64, 255])
+            }
+        })
+    }
+
+    // Helper function to read the directory entry fields of an ICO file
+    fn ico_directory(ico_bytes: &[u8]) -> Vec<(u8, u8, u8, u16, u32, u32)> {
+        let count = u16::from_le_bytes([ico_bytes[4], ico_bytes[5]]) as usize;
+        (0..count)
+            .map(|index| {
+                let entry = &ico_bytes[6 + index * 16..6 + (index + 1) * 16];
+                (
+                    entry[0],
+                    entry[1],
+                    entry[2],
+                    u16::from_le_bytes([entry[6], entry[7]]),
+                    u32::from_le_bytes([entry[8], entry[9], entry[10], entry[11]]),
+                    u32::from_le_bytes([entry[12], entry[13], entry[14], entry[15]]),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_icon_entry_bmp_bit_depths() {
+        for (size, colors, bits_per_pixel, color_count) in [
+            // The transparent column needs black in addition to the colors
+            (16, 1, 1, 2),
+            (16, 2, 4, 16),
+            (13, 3, 4, 16),
+            (32, 15, 4, 16),
+            (32, 16, 8, 0),
+            (33, 17, 8, 0),
+            (48, 255, 8, 0),
+        ] {
+            let input_image = create_indexed_image(size, colors);
+            let entry = IconEntry::bmp(&input_image).expect("Failed to encode BMP");
+            assert_eq!(entry.bits_per_pixel, bits_per_pixel, "{colors} colors");
+            assert_eq!(entry.color_count, color_count, "{colors} colors");
+
+            // The DIB holds the header, the full color table, the bitmap and the AND mask
+            let row = |bits: u32| (size * bits).div_ceil(32) as usize * 4;
+            let expected_length = 40
+                + (1 << bits_per_pixel) * 4
+                + row(bits_per_pixel as u32) * size as usize
+                + row(1) * size as usize;
+            assert_eq!(entry.data.len(), expected_length);
+
+            let ico_bytes = write_ico(&[entry]).expect("Failed to write ICO");
+            let output_image = image::load_from_memory(&ico_bytes)
+                .expect("Failed to decode ICO")
+                .to_rgba8();
+            for (x, y, pixel) in input_image.enumerate_pixels() {
+                if pixel[3] == 0 {
+                    assert_eq!(output_image[(x, y)][3], 0);
+                } else {
+                    assert_eq!(output_image[(x, y)], *pixel);
+                }
+            }
+        }
+    }
+
+    // Helper function to read the color table entries that the XOR bitmap of an indexed BMP
+    // entry uses for the fully transparent pixels
+    fn transparent_table_colors(entry: &IconEntry, img: &image::RgbaImage) -> HashSet<[u8; 4]> {
+        let bits = entry.bits_per_pixel as usize;
+        let table = &entry.data[40..40 + (4 << bits)];
+        let bitmap = &entry.data[40 + (4 << bits)..];
+        let row_length = (img.width() as usize * bits).div_ceil(32) * 4;
+        img.enumerate_pixels()
+            .filter(|(_, _, pixel)| pixel[3] == 0)
+            .map(|(x, y, _)| {
+                let row = (img.height() - 1 - y) as usize * row_length;
+                let bit = x as usize * bits;
+                let byte = bitmap[row + bit / 8];
+                let index = (byte >> (8 - bits - bit % 8)) as usize & ((1 << bits) - 1);
+                table[index * 4..index * 4 + 4].try_into().unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_icon_entry_bmp_transparent_pixels_are_black() {
+        // Palettes that would fill the color table, with and without black in them
+        let with_black = image::RgbaImage::from_fn(16, 16, |x, y| match (x + y) % 3 {
+            0 => Rgba([0, 0, 0, 0]),
+            1 => Rgba([0, 0, 0, 255]),
+            _ => Rgba([255, 0, 0, 255]),
+        });
+        for (input_image, bits_per_pixel) in [
+            (create_indexed_image(16, 2), 4),
+            (create_indexed_image(16, 16), 8),
+            (with_black, 1),
+        ] {
+            let entry = IconEntry::bmp(&input_image).expect("Failed to encode BMP");
+            assert_eq!(entry.bits_per_pixel, bits_per_pixel);
+            // The table stores blue, green, red and a reserved byte
+            assert_eq!(
+                transparent_table_colors(&entry, &input_image),
+                HashSet::from([[0, 0, 0, 0]])
+            );
+        }
+
+        // Without room for black, the bitmap keeps true color
+        let input_image = create_indexed_image(48, 256);
+        assert_eq!(IconEntry::bmp(&input_image).unwrap().bits_per_pixel, 32);
+    }
+
+    #[test]
+    fn test_icon_entry_bmp_with_true_color() {
+        // Partial transparency and too many colors need 32 bits per pixel
+        let translucent = image::RgbaImage::from_pixel(8, 8, Rgba([255, 0, 0, 128]));
+        let many_colors = create_indexed_image(32, 300);
+        for input_image in [translucent, many_colors] {
+            let entry = IconEntry::bmp(&input_image).expect("Failed to encode BMP");
+            assert_eq!((entry.bits_per_pixel, entry.color_count), (32, 0));
+            let ico_bytes = write_ico(&[entry]).expect("Failed to write ICO");
+            let output_image = image::load_from_memory(&ico_bytes)
+                .expect("Failed to decode ICO")
+                .to_rgba8();
+            for (x, y, pixel) in input_image.enumerate_pixels() {
+                if pixel[3] > 0 {
+                    assert_eq!(output_image[(x, y)], *pixel);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_icon_entry_with_invalid_size() {
+        assert!(matches!(
+            IconEntry::bmp(&image::RgbaImage::new(257, 16)),
+            Err(Error::InvalidSize { .. })
+        ));
+        assert!(matches!(
+            IconEntry::png(&image::RgbaImage::new(0, 0)),
+            Err(Error::InvalidSize { .. })
+        ));
+        assert!(matches!(write_ico(&[]), Err(Error::InvalidSize { .. })));
+    }
+
+    #[test]
+    fn test_convert_frames_with_indexed_entries() {
+        let images = vec![
+            DynamicImage::ImageRgba8(create_indexed_image(16, 1)),
+            DynamicImage::ImageRgba8(create_indexed_image(32, 15)),
+            DynamicImage::ImageRgba8(create_indexed_image(48, 200)),
+            DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+                256,
+                256,
+                Rgba([255, 0, 0, 128]),
+            )),
+        ];
+        let ico_bytes = convert_frames(&images).expect("Failed to convert frames");
+        let directory = ico_directory(&ico_bytes);
+        let fields: Vec<_> = directory
+            .iter()
+            .map(|&(width, height, colors, bits, _, _)| (width, height, colors, bits))
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                (16, 16, 2, 1),
+                (32, 32, 16, 4),
+                (48, 48, 0, 8),
+                // Partial transparency is kept in a PNG entry
+                (0, 0, 0, 32)
+            ]
+        );
+        let (_, _, _, _, length, offset) = directory[3];
+        assert!(ico_bytes[offset as usize..].starts_with(b"\x89PNG"));
+        assert_eq!(offset as usize + length as usize, ico_bytes.len());
+    }
+
+    #[test]
+    fn test_favicon_from_bytes_with_indexed_entries() {
+        let svg = br#"<svg width="32" height="32" xmlns="http://www.w3.org/2000/svg"><rect width="32" height="32" style="fill:rgb(255,0,0);"/><rect width="16" height="16" style="fill:rgb(0,0,255);"/></svg>"#;
+        let options = FaviconOptions {
+            quantize: Some(QuantizeOptions {
+                colors: 16,
+                quantizer: Quantizer::MedianCut,
+                ..Default::default()
+            }),
+            ..options_with_sizes(&[16, 32])
+        };
+        let ico_bytes = favicon_from_bytes(svg, &options).expect("Failed to create favicon");
+        for (_, _, colors, bits, _, _) in ico_directory(&ico_bytes) {
+            assert_eq!((colors, bits), (2, 1));
+        }
+        let output_image = image::load_from_memory(&ico_bytes).expect("Failed to decode ICO");
+        assert_eq!(output_image.get_pixel(31, 31), Rgba([255, 0, 0, 255]));
+        assert_eq!(output_image.get_pixel(0, 0), Rgba([0, 0, 255, 255]));
+    }
+
+    #[test]
+    fn test_icon_entry_encode_policies() {
+        let small = create_indexed_image(32, 16);
+        let large = create_indexed_image(256, 16);
+        let is_png = |img: &image::RgbaImage, encoding| {
+            IconEntry::encode(img, encoding)
+                .expect("Failed to encode")
+                .is_png()
+        };
+        assert!(is_png(&small, IconEncoding::Png));
+        assert!(!is_png(&large, IconEncoding::Bmp));
+        assert!(!is_png(&small, IconEncoding::Auto));
+        assert!(is_png(&large, IconEncoding::Auto));
+
+        // A uniform image compresses well as PNG, while noise is smaller as indexed BMP
+        let uniform = image::RgbaImage::from_pixel(64, 64, Rgba([255, 0, 0, 255]));
+        assert!(is_png(&uniform, IconEncoding::Smallest));
+        let noise = image::RgbaImage::from_fn(64, 64, |x, y| {
+            let index = (x * 7919 + y * 104729) % 16;
+            Rgba([(index * 16) as u8, (index * 5) as u8, 0, 255])
+        });
+        let smallest = IconEntry::encode(&noise, IconEncoding::Smallest).unwrap();
+        let png = IconEntry::png(&noise).unwrap();
+        let bmp = IconEntry::bmp(&noise).unwrap();
+        assert_eq!(smallest.data.len(), png.data.len().min(bmp.data.len()));
+    }
+
+    #[test]
+    fn test_convert_frames_with_encoding() {
+        let images = vec![
+            DynamicImage::ImageRgba8(create_indexed_image(16, 4)),
+            DynamicImage::ImageRgba8(create_indexed_image(256, 4)),
+        ];
+        let entry_is_png = |ico_bytes: &[u8]| -> Vec<bool> {
+            ico_directory(ico_bytes)
+                .iter()
+                .map(|&(_, _, _, _, _, offset)| {
+                    ico_bytes[offset as usize..].starts_with(b"\x89PNG")
+                })
+                .collect()
+        };
+        for (encoding, expected) in [
+            (IconEncoding::Png, vec![true, true]),
+            (IconEncoding::Bmp, vec![false, false]),
+            (IconEncoding::Auto, vec![false, true]),
+        ] {
+            let ico_bytes =
+                convert_frames_with(&images, encoding).expect("Failed to convert frames");
+            assert_eq!(entry_is_png(&ico_bytes), expected, "{encoding}");
+            // Every entry decodes back to the same size
+            let output_image = image::load_from_memory(&ico_bytes).expect("Failed to decode");
+            assert_eq!(output_image.dimensions(), (256, 256));
+        }
+    }
+
+    #[test]
+    fn test_icon_encoding_from_str() {
+        assert_eq!("PNG".parse::<IconEncoding>().unwrap(), IconEncoding::Png);
+        assert_eq!(
+            "smallest".parse::<IconEncoding>().unwrap(),
+            IconEncoding::Smallest
+        );
+        assert!(matches!(
+            "gif".parse::<IconEncoding>(),
+            Err(Error::InvalidOption {
+                name: "encoding",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_main_with_encoding() {
+        let (_temp_file, input_path) = create_temp_image_file(".png", Rgba([255, 0, 0, 255]));
+        let (_temp_dir, output_path) = create_temp_output_file("/output.ico");
+
+        Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .args([
+                "--encoding",
+                "bmp",
+                "--sizes",
+                "16,256",
+                &input_path,
+                &output_path,
+            ])
+            .assert()
+            .success();
+
+        let output_content = fs::read(&output_path).expect("Failed to read output file");
+        for (_, _, _, _, _, offset) in ico_directory(&output_content) {
+            assert!(!output_content[offset as usize..].starts_with(b"\x89PNG"));
+        }
+    }
+
+    #[test]
+    fn test_read_ico_round_trip() {
+        let entries = vec![
+            IconEntry::bmp(&create_indexed_image(16, 3)).unwrap(),
+            IconEntry::bmp(&create_colorful_image(32, 32).to_rgba8()).unwrap(),
+            IconEntry::png(&create_colorful_image(256, 256).to_rgba8()).unwrap(),
+        ];
+        let ico_bytes = write_ico(&entries).unwrap();
+        let read = read_ico(&ico_bytes).expect("Failed to read ICO");
+        assert_eq!(read, entries);
+        assert_eq!((read[2].width, read[2].height), (256, 256));
+    }
+
+    #[test]
+    fn test_icon_entry_bit_depth_and_palette_size() {
+        let indexed = IconEntry::bmp(&create_indexed_image(16, 3)).unwrap();
+        assert_eq!((indexed.bit_depth(), indexed.palette_size()), (4, 16));
+        let translucent = image::RgbaImage::from_pixel(16, 16, Rgba([255, 136, 0, 128]));
+        let true_color = IconEntry::bmp(&translucent).unwrap();
+        assert_eq!((true_color.bit_depth(), true_color.palette_size()), (32, 0));
+        let png = IconEntry::png(&create_colorful_image(16, 16).to_rgba8()).unwrap();
+        assert_eq!((png.bit_depth(), png.palette_size()), (32, 0));
+
+        // An indexed PNG with a palette of two colors
+        let mut png_bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png_bytes, 2, 1);
+        encoder.set_color(png::ColorType::Indexed);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_palette(vec![255, 0, 0, 0, 0, 255]);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&[0, 1]).unwrap();
+        writer.finish().unwrap();
+        let indexed_png = IconEntry {
+            width: 2,
+            height: 1,
+            color_count: 2,
+            bits_per_pixel: 8,
+            data: png_bytes,
+        };
+        assert_eq!(
+            (indexed_png.bit_depth(), indexed_png.palette_size()),
+            (8, 2)
+        );
+
+        // A chunk length far beyond the data, before the palette is reached
+        let mut broken_png = indexed_png.clone();
+        broken_png.data[8..12].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert_eq!(broken_png.palette_size(), 0);
+    }
+
+    #[test]
+    fn test_icon_entry_decode() {
+        let img = create_indexed_image(16, 5);
+        assert_eq!(IconEntry::bmp(&img).unwrap().decode().unwrap(), img);
+        let img = create_colorful_image(48, 48).to_rgba8();
+        assert_eq!(IconEntry::png(&img).unwrap().decode().unwrap(), img);
+    }
+
+    #[test]
+    fn test_read_ico_with_cursor() {
+        let entry = IconEntry::bmp(&create_indexed_image(32, 200)).unwrap();
+        let mut cur_bytes = write_ico(std::slice::from_ref(&entry)).unwrap();
+        // A cursor stores the hotspot in place of the planes and bits per pixel
+        cur_bytes[2] = 2;
+        cur_bytes[10..14].copy_from_slice(&[5, 0, 7, 0]);
+        let read = read_ico(&cur_bytes).expect("Failed to read CUR");
+        assert_eq!(read[0].bits_per_pixel, 8);
+        assert_eq!(read[0].data, entry.data);
+    }
+
+    #[test]
+    fn test_read_ico_errors() {
+        let ico_bytes =
+            write_ico(&[IconEntry::bmp(&create_indexed_image(16, 2)).unwrap()]).unwrap();
+        let mut not_icon = ico_bytes.clone();
+        not_icon[2] = 3;
+        let mut no_entries = ico_bytes.clone();
+        no_entries[4] = 0;
+        let mut outside = ico_bytes.clone();
+        outside[14..18].copy_from_slice(&u32::MAX.to_le_bytes());
+        for data in [
+            &ico_bytes[..4],
+            &not_icon[..],
+            &no_entries[..],
+            &ico_bytes[..10],
+            &ico_bytes[..ico_bytes.len() - 1],
+            &outside[..],
+        ] {
+            assert!(matches!(read_ico(data), Err(Error::InvalidIco(_))));
+        }
+    }
+
+    #[test]
+    fn test_main_inspect_and_extract() {
+        let (temp_dir, output_path) = create_temp_output_file("/favicon.ico");
+        let images = vec![
+            DynamicImage::ImageRgba8(create_indexed_image(16, 1)),
+            create_colorful_image(32, 32),
+            create_colorful_image(256, 256),
+        ];
+        fs::write(&output_path, convert_frames(&images).unwrap()).unwrap();
+
+        let assert = Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .args(["inspect", &output_path])
+            .assert()
+            .success();
+        let stdout = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+        assert!(stdout.contains("3 entries"));
+        assert!(stdout.contains("16x16"));
+        assert!(stdout.contains("256x256"));
+
+        let assert = Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .args(["inspect", "--json", &output_path])
+            .assert()
+            .success();
+        let report: serde_json::Value =
+            serde_json::from_slice(&assert.get_output().stdout).unwrap();
+        let entries = report["entries"].as_array().unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0]["bit_depth"], 1);
+        assert_eq!(entries[0]["palette_size"], 2);
+        assert_eq!(entries[0]["encoding"], "bmp");
+        assert_eq!(entries[2]["width"], 256);
+        assert_eq!(entries[2]["encoding"], "png");
+
+        let extract_dir = temp_dir.path().join("extracted");
+        Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .arg("extract")
+            .arg(&output_path)
+            .arg(&extract_dir)
+            .assert()
+            .success();
+        for (size, original) in [(16, &images[0]), (32, &images[1]), (256, &images[2])] {
+            let png = image::open(extract_dir.join(format!("favicon-{size}x{size}.png")))
+                .expect("Failed to open extracted PNG")
+                .to_rgba8();
+            // The color of transparent BMP pixels comes from the color table
+            for (pixel, original) in png.pixels().zip(original.to_rgba8().pixels()) {
+                assert_eq!(pixel[3], original[3]);
+                if original[3] > 0 {
+                    assert_eq!(pixel, original);
+                }
+            }
+        }
+
+        Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .args(["inspect", "Cargo.toml"])
+            .assert()
+            .code(EXIT_DECODE);
+    }
+
+    #[test]
+    fn test_extract_ico_with_repeated_sizes() {
+        let (temp_dir, output_path) = create_temp_output_file("/icon.ico");
+        let img = create_colorful_image(16, 16).to_rgba8();
+        let entries = vec![IconEntry::bmp(&img).unwrap(), IconEntry::png(&img).unwrap()];
+        fs::write(&output_path, write_ico(&entries).unwrap()).unwrap();
+        let output_dir = temp_dir.path().to_str().unwrap();
+        let paths = extract_ico(&output_path, output_dir).expect("Failed to extract");
+        assert!(paths[0].ends_with("icon-16x16.png"));
+        assert!(paths[1].ends_with("icon-16x16-2.png"));
+        // PNG entries are copied without encoding them again
+        assert_eq!(fs::read(&paths[1]).unwrap(), entries[1].data);
+    }
+
+    // A square SVG document with a red circle on a transparent background
+    const SQUARE_SVG: &str = r##"<svg width="64" height="64" xmlns="http://www.w3.org/2000/svg"><circle cx="32" cy="32" r="24" fill="#ff0000"/></svg>"##;
+
+    #[test]
+    fn test_merge_entries_replaces_and_adds_by_size() {
+        let pixel_art = IconEntry::bmp(&create_indexed_image(16, 4)).unwrap();
+        let mut entries = vec![
+            pixel_art.clone(),
+            IconEntry::bmp(&create_indexed_image(48, 4)).unwrap(),
+        ];
+        let generated = vec![
+            IconEntry::png(&create_colorful_image(256, 256).to_rgba8()).unwrap(),
+            IconEntry::png(&create_colorful_image(48, 48).to_rgba8()).unwrap(),
+            IconEntry::png(&create_colorful_image(32, 32).to_rgba8()).unwrap(),
+        ];
+        merge_entries(&mut entries, generated.clone());
+        let sizes: Vec<u32> = entries.iter().map(|entry| entry.width).collect();
+        assert_eq!(sizes, vec![16, 32, 48, 256]);
+        assert_eq!(entries[0], pixel_art);
+        assert_eq!(entries[1], generated[2]);
+        assert_eq!(entries[2], generated[1]);
+        assert_eq!(entries[3], generated[0]);
+    }
+
+    #[test]
+    fn test_remove_entries() {
+        let mut entries: Vec<IconEntry> = [16, 24, 32, 24]
+            .iter()
+            .map(|&size| IconEntry::bmp(&create_indexed_image(size, 2)).unwrap())
+            .collect();
+        assert_eq!(remove_entries(&mut entries, &[24, 64]), 2);
+        assert_eq!(remove_entries(&mut entries, &[]), 0);
+        let sizes: Vec<u32> = entries.iter().map(|entry| entry.width).collect();
+        assert_eq!(sizes, vec![16, 32]);
+    }
+
+    #[test]
+    fn test_favicon_entries_matches_favicon_from_bytes() {
+        let options = FaviconOptions {
+            sizes: vec![16, 32, 256],
+            ..Default::default()
+        };
+        let entries = favicon_entries(SQUARE_SVG.as_bytes(), &options).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(
+            write_ico(&entries).unwrap(),
+            favicon_from_bytes(SQUARE_SVG.as_bytes(), &options).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_main_edit_keeps_untouched_entries() {
+        let (temp_dir, ico_path) = create_temp_output_file("/favicon.ico");
+        let pixel_art = IconEntry::bmp(&create_indexed_image(16, 4)).unwrap();
+        let entries = vec![
+            pixel_art.clone(),
+            IconEntry::bmp(&create_indexed_image(24, 4)).unwrap(),
+            IconEntry::bmp(&create_indexed_image(32, 4)).unwrap(),
+        ];
+        fs::write(&ico_path, write_ico(&entries).unwrap()).unwrap();
+        let source_path = temp_dir.path().join("logo.svg");
+        fs::write(&source_path, SQUARE_SVG).unwrap();
+
+        Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .args(["edit", &ico_path, "--source"])
+            .arg(&source_path)
+            .args(["--sizes", "32,256", "--remove", "24", "--colors", "0"])
+            .assert()
+            .success();
+        let edited = read_ico(&fs::read(&ico_path).unwrap()).unwrap();
+        let sizes: Vec<u32> = edited.iter().map(|entry| entry.width).collect();
+        assert_eq!(sizes, vec![16, 32, 256]);
+        assert_eq!(edited[0], pixel_art);
+        assert_ne!(edited[1], entries[2]);
+        assert!(edited[2].is_png());
+
+        // Writing elsewhere leaves the original file as it was
+        let output_path = temp_dir.path().join("edited.ico");
+        let original = fs::read(&ico_path).unwrap();
+        Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .args(["edit", &ico_path, "--remove", "256", "--output"])
+            .arg(&output_path)
+            .assert()
+            .success();
+        assert_eq!(fs::read(&ico_path).unwrap(), original);
+        assert_eq!(
+            read_ico(&fs::read(&output_path).unwrap()).unwrap(),
+            edited[..2]
+        );
+
+        // The source image can come from the standard input
+        Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .args(["edit", &ico_path, "--source", "-", "--sizes", "48"])
+            .write_stdin(SQUARE_SVG)
+            .assert()
+            .success();
+        assert_eq!(entry_sizes(Path::new(&ico_path)), vec![16, 32, 48, 256]);
+    }
+
+    #[test]
+    fn test_main_edit_with_invalid_arguments() {
+        let (temp_dir, ico_path) = create_temp_output_file("/favicon.ico");
+        fs::write(
+            &ico_path,
+            write_ico(&[IconEntry::bmp(&create_indexed_image(16, 2)).unwrap()]).unwrap(),
+        )
+        .unwrap();
+        let source_path = temp_dir.path().join("logo.svg");
+        fs::write(&source_path, SQUARE_SVG).unwrap();
+
+        for args in [
+            vec!["--remove", "16"],
+            vec!["--sizes", "16"],
+            vec!["--sizes", "16", "--remove", "16", "--source"],
+            vec![],
+        ] {
+            let mut command = Command::cargo_bin(env!("CARGO_PKG_NAME")).expect("Binary not found");
+            command.args(["edit", &ico_path]).args(&args);
+            if args.last() == Some(&"--source") {
+                command.arg(&source_path);
+            }
+            command.assert().code(EXIT_INVALID_ARGUMENTS);
+        }
+    }
+
+    #[test]
+    fn test_output_path() {
+        assert_eq!(
+            output_path("out/{stem}.ico", "logos/acme.svg"),
+            "out/acme.ico"
+        );
+        assert_eq!(
+            output_path("{dir}/{stem}.ico", "logos/acme.svg"),
+            "logos/acme.ico"
+        );
+        assert_eq!(
+            output_path("{dir}/{stem}.ico", "acme.tar.png"),
+            "./acme.tar.ico"
+        );
+        assert_eq!(output_path("favicon.ico", "logos/acme.svg"), "favicon.ico");
+    }
+
+    #[test]
+    fn test_expand_inputs() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let dir = temp_dir.path().to_str().unwrap().to_owned();
+        for name in ["b.svg", "a.PNG", "c.svgz", "notes.txt"] {
+            fs::write(temp_dir.path().join(name), "").unwrap();
+        }
+        fs::create_dir(temp_dir.path().join("nested.png")).unwrap();
+
+        let inputs = expand_inputs(std::slice::from_ref(&dir)).unwrap();
+        let names: Vec<&str> = inputs
+            .iter()
+            .map(|input| input.rsplit(['/', '\\']).next().unwrap())
+            .collect();
+        assert_eq!(names, vec!["a.PNG", "b.svg", "c.svgz"]);
+
+        let inputs = expand_inputs(&[format!("{dir}/*.svg*"), "missing.png".to_string()]).unwrap();
+        assert_eq!(inputs.len(), 3);
+        assert!(inputs[0].ends_with("b.svg"));
+        assert!(inputs[1].ends_with("c.svgz"));
+        assert_eq!(inputs[2], "missing.png");
+
+        for pattern in [format!("{dir}/*.gif"), format!("{dir}/[")] {
+            assert!(matches!(
+                expand_inputs(&[pattern]),
+                Err(CliError::InvalidArguments(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_main_with_batch_conversion() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let logos = temp_dir.path().join("logos");
+        fs::create_dir(&logos).unwrap();
+        for name in ["acme", "globex", "initech"] {
+            fs::write(logos.join(format!("{name}.svg")), SQUARE_SVG).unwrap();
+        }
+        let template = temp_dir.path().join("out").join("{stem}.ico");
+        fs::create_dir(temp_dir.path().join("out")).unwrap();
+
+        let assert = Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .arg(&logos)
+            .arg(&template)
+            .args(["--sizes", "16,32", "--jobs", "2"])
+            .assert()
+            .success();
+        let stdout = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+        assert!(stdout.contains("Converted all 3 inputs"));
+        for name in ["acme", "globex", "initech"] {
+            let ico_bytes = fs::read(temp_dir.path().join("out").join(format!("{name}.ico")))
+                .expect("Failed to read output file");
+            assert_eq!(read_ico(&ico_bytes).unwrap().len(), 2);
+        }
+
+        // A failing input does not stop the others, but fails the whole run
+        fs::write(logos.join("broken.png"), "not a png").unwrap();
+        let assert = Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .arg(logos.join("*.*"))
+            .arg("--output")
+            .arg(&template)
+            .args(["--sizes", "16"])
+            .assert()
+            .code(EXIT_DECODE);
+        let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+        assert!(stderr.contains("broken.png"));
+        assert!(stderr.contains("Converted 3 of 4 inputs, 1 failed"));
+    }
+
+    #[test]
+    fn test_main_with_batch_conversion_to_one_output() {
+        let (_first_file, first_path) = create_temp_image_file(".png", Rgba([255, 0, 0, 255]));
+        let (_second_file, second_path) = create_temp_image_file(".png", Rgba([0, 0, 255, 255]));
+        let (_temp_dir, output_path) = create_temp_output_file("/favicon.ico");
+
+        let assert = Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .args([&first_path, &second_path, &output_path])
+            .assert()
+            .code(EXIT_INVALID_ARGUMENTS);
+        let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+        assert!(stderr.contains("Several inputs would be saved to"));
+        assert!(!Path::new(&output_path).exists());
+    }
+
+    #[test]
+    fn test_main_with_output_overwriting_an_input() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let icons = temp_dir.path().join("icons");
+        fs::create_dir(&icons).unwrap();
+        let ico_path = icons.join("favicon.ico");
+        fs::write(
+            &ico_path,
+            favicon_from_bytes(SQUARE_SVG.as_bytes(), &options_with_sizes(&[16])).unwrap(),
+        )
+        .unwrap();
+        fs::write(icons.join("logo.svg"), SQUARE_SVG).unwrap();
+        let original = fs::read(&ico_path).unwrap();
+
+        // The last path is one of the files of the input directory
+        let assert = Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .arg(&icons)
+            .arg(&ico_path)
+            .assert()
+            .code(EXIT_INVALID_ARGUMENTS);
+        let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+        assert!(stderr.contains("it is also an input, give the output with --output"));
+        assert_eq!(fs::read(&ico_path).unwrap(), original);
+
+        // A template that expands to the input itself
+        let assert = Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .arg(&icons)
+            .arg("--output")
+            .arg(icons.join("{stem}.ico"))
+            .assert()
+            .code(EXIT_INVALID_ARGUMENTS);
+        let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+        assert!(stderr.contains("would overwrite an input"));
+        assert_eq!(fs::read(&ico_path).unwrap(), original);
+        assert!(!icons.join("logo.ico").exists());
+    }
+
+    #[test]
+    fn test_main_with_image_as_last_path() {
+        let (_first_file, first_path) = create_temp_image_file(".png", Rgba([255, 0, 0, 255]));
+        let (_second_file, second_path) = create_temp_image_file(".png", Rgba([0, 0, 255, 255]));
+        let original = fs::read(&second_path).unwrap();
+
+        // Such as a shell glob matching two images
+        let assert = Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .args([&first_path, &second_path])
+            .assert()
+            .code(EXIT_INVALID_ARGUMENTS);
+        let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+        assert!(stderr.contains("is an existing image"));
+        assert_eq!(fs::read(&second_path).unwrap(), original);
+
+        let assert = Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .args([&first_path, &second_path, "--format", "png"])
+            .assert()
+            .code(EXIT_INVALID_ARGUMENTS);
+        let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+        assert!(stderr.contains("is an existing image"));
+        assert_eq!(fs::read(&second_path).unwrap(), original);
+
+        // Given with the option, the PNG output is written as asked
+        Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .args([&first_path, "--output", &second_path])
+            .assert()
+            .success();
+        let png = image::load_from_memory(&fs::read(&second_path).unwrap()).unwrap();
+        assert_eq!(png.dimensions(), (256, 256));
+    }
+
+    #[test]
+    fn test_main_with_new_png_as_last_path() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let input_path = temp_dir.path().join("logo.svg");
+        let output_path = temp_dir.path().join("icon.png");
+        fs::write(&input_path, SQUARE_SVG).unwrap();
+
+        Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .arg(&input_path)
+            .arg(&output_path)
+            .args(["--sizes", "16,32"])
+            .assert()
+            .success();
+        let png = image::load_from_memory(&fs::read(&output_path).unwrap()).unwrap();
+        assert_eq!(png.dimensions(), (32, 32));
+    }
+
+    #[test]
+    fn test_main_with_watch() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let input_path = temp_dir.path().join("logo.svg");
+        let output_path = temp_dir.path().join("favicon.ico");
+        fs::write(&input_path, SQUARE_SVG).unwrap();
+
+        let mut child = process::Command::new(assert_cmd::cargo::cargo_bin(env!("CARGO_PKG_NAME")))
+            .arg(&input_path)
+            .arg(&output_path)
+            .args(["--sizes", "16", "--colors", "0", "--watch"])
+            .stdout(process::Stdio::piped())
+            .spawn()
+            .expect("Failed to start the watch mode");
+        let stdout = io::BufReader::new(child.stdout.take().unwrap());
+        let mut lines = io::BufRead::lines(stdout).map_while(Result::ok);
+        assert!(lines.any(|line| line.starts_with("Watching")));
+        let first = fs::read(&output_path).expect("Failed to read output file");
+
+        // Several quick saves result in a single rebuild
+        let blue_svg = SQUARE_SVG.replace("#ff0000", "#0000ff");
+        fs::write(&input_path, &blue_svg).unwrap();
+        fs::write(&input_path, blue_svg + "\n").unwrap();
+        let rebuilt = lines.find(|line| line.starts_with("Rebuilt"));
+        child.kill().expect("Failed to stop the watch mode");
+        child.wait().unwrap();
+
+        assert!(rebuilt.unwrap().contains("favicon.ico"));
+        let second = fs::read(&output_path).expect("Failed to read output file");
+        assert_ne!(first, second);
+        let icon = read_ico(&second).unwrap()[0].decode().unwrap();
+        assert_eq!(icon.get_pixel(8, 8), &Rgba([0, 0, 255, 255]));
+    }
+
+    #[test]
+    fn test_output_format() {
+        assert_eq!("ICO".parse::<OutputFormat>().unwrap(), OutputFormat::Ico);
+        assert_eq!("png".parse::<OutputFormat>().unwrap(), OutputFormat::Png);
+        assert!(matches!(
+            "bmp".parse::<OutputFormat>(),
+            Err(Error::InvalidOption { .. })
+        ));
+        assert_eq!(
+            OutputFormat::from_path("out/logo.png"),
+            Some(OutputFormat::Png)
+        );
+        assert_eq!(
+            OutputFormat::from_path("out/{stem}.ico"),
+            Some(OutputFormat::Ico)
+        );
+        assert_eq!(OutputFormat::from_path("-"), None);
+        assert_eq!(OutputFormat::Png.to_string(), "png");
+    }
+
+    #[test]
+    fn test_main_with_stdin_and_stdout() {
+        let assert = Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .args(["-", "-", "--format", "ico", "--sizes", "16,32", "--verbose"])
+            .write_stdin(SQUARE_SVG)
+            .assert()
+            .success();
+        let output = assert.get_output();
+        let entries = read_ico(&output.stdout).expect("Failed to read ICO from stdout");
+        assert_eq!(entries.len(), 2);
+        let stderr = String::from_utf8(output.stderr.clone()).unwrap();
+        assert!(stderr.contains("Detected input format Svg"));
+        assert!(stderr.contains("Output written to the standard output"));
+
+        let assert = Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .args(["-", "-", "--format", "png", "--sizes", "16,48"])
+            .write_stdin(SQUARE_SVG)
+            .assert()
+            .success();
+        let png = image::load_from_memory_with_format(
+            &assert.get_output().stdout,
+            image::ImageFormat::Png,
+        )
+        .expect("Failed to read PNG from stdout");
+        assert_eq!(png.dimensions(), (48, 48));
+
+        // The ICO file from stdout can be inspected from stdin
+        let assert = Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .args(["inspect", "-"])
+            .write_stdin(output.stdout.clone())
+            .assert()
+            .success();
+        let stdout = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+        assert!(stdout.contains("2 entries"));
+    }
+
+    #[test]
+    fn test_main_with_stdout_without_format() {
+        let assert = Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .args(["-", "-"])
+            .write_stdin(SQUARE_SVG)
+            .assert()
+            .code(EXIT_INVALID_ARGUMENTS);
+        assert!(assert.get_output().stdout.is_empty());
+        let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+        assert!(stderr.contains("needs the --format option"));
+    }
+
+    // Helper function to create a project directory with a logo and a configuration file
+    fn create_configured_project(file_name: &str, config: &str) -> tempfile::TempDir {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        fs::write(temp_dir.path().join("logo.svg"), SQUARE_SVG).unwrap();
+        fs::write(temp_dir.path().join(file_name), config).unwrap();
+        temp_dir
+    }
+
+    // Helper function to list the entry sizes of an ICO file
+    fn entry_sizes(path: &Path) -> Vec<u32> {
+        let ico_bytes = fs::read(path).expect("Failed to read output file");
+        read_ico(&ico_bytes)
+            .unwrap()
+            .iter()
+            .map(|entry| entry.width)
+            .collect()
+    }
+
+    const PROJECT_CONFIG: &str = r#"
+input = "logo.svg"
+output = "favicon.ico"
+sizes = [16, 32]
+colors = 8
+
+[presets.app]
+output = "app.ico"
+sizes = [48, 256]
+encoding = "png"
+"#;
+
+    #[test]
+    fn test_main_with_configuration_file() {
+        let project = create_configured_project("chinenshichanaka.toml", PROJECT_CONFIG);
+        Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .current_dir(project.path())
+            .assert()
+            .success();
+        assert_eq!(
+            entry_sizes(&project.path().join("favicon.ico")),
+            vec![16, 32]
+        );
+
+        Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .current_dir(project.path())
+            .args(["--preset", "app"])
+            .assert()
+            .success();
+        let app_path = project.path().join("app.ico");
+        assert_eq!(entry_sizes(&app_path), vec![48, 256]);
+        let entries = read_ico(&fs::read(&app_path).unwrap()).unwrap();
+        assert!(entries.iter().all(IconEntry::is_png));
+
+        // Command line options override the preset and the top-level values
+        Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .current_dir(project.path())
+            .args(["--preset", "app", "--sizes", "24", "--encoding", "bmp"])
+            .args(["logo.svg", "custom.ico"])
+            .assert()
+            .success();
+        let custom_path = project.path().join("custom.ico");
+        assert_eq!(entry_sizes(&custom_path), vec![24]);
+        assert!(!read_ico(&fs::read(&custom_path).unwrap()).unwrap()[0].is_png());
+    }
+
+    #[test]
+    fn test_main_subcommands_with_configuration_file() {
+        let project = create_configured_project("settings.toml", PROJECT_CONFIG);
+        let ico_path = project.path().join("favicon.ico");
+        let entries = vec![IconEntry::bmp(&create_indexed_image(16, 4)).unwrap()];
+        fs::write(&ico_path, write_ico(&entries).unwrap()).unwrap();
+
+        // Only the image options of the preset apply, not its sizes or output
+        Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .current_dir(project.path())
+            .args([
+                "edit",
+                "favicon.ico",
+                "--source",
+                "logo.svg",
+                "--sizes",
+                "32",
+            ])
+            .args(["--config", "settings.toml", "--preset", "app"])
+            .assert()
+            .success();
+        let edited = read_ico(&fs::read(&ico_path).unwrap()).unwrap();
+        assert_eq!(entry_sizes(&ico_path), vec![16, 32]);
+        assert!(!edited[0].is_png() && edited[1].is_png());
+
+        let package_dir = project.path().join("site");
+        Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .current_dir(project.path())
+            .args(["package", "logo.svg", "site", "--config", "settings.toml"])
+            .args(["--preset", "app"])
+            .assert()
+            .success();
+        let entries = read_ico(&fs::read(package_dir.join("favicon.ico")).unwrap()).unwrap();
+        assert_eq!(entries.len(), PACKAGE_ICO_SIZES.len());
+        assert!(entries.iter().all(IconEntry::is_png));
+
+        let assert = Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .current_dir(project.path())
+            .args(["package", "logo.svg", "site", "--config", "settings.toml"])
+            .args(["--preset", "web"])
+            .assert()
+            .code(EXIT_INVALID_ARGUMENTS);
+        let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+        assert!(stderr.contains("no preset 'web'"));
+    }
+
+    #[test]
+    fn test_main_with_cargo_metadata() {
+        let manifest = r#"
+[package]
+name = "website"
+version = "0.1.0"
+
+[package.metadata.chinenshichanaka]
+inputs = ["logo.svg"]
+output = "{stem}.ico"
+sizes = [64]
+"#;
+        let project = create_configured_project("Cargo.toml", manifest);
+        Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .current_dir(project.path())
+            .assert()
+            .success();
+        assert_eq!(entry_sizes(&project.path().join("logo.ico")), vec![64]);
+    }
+
+    #[test]
+    fn test_main_with_invalid_configuration() {
+        for config in [
+            "colour = 8",
+            "colors = 300",
+            "fit = \"squash\"",
+            "sizes = [0]",
+        ] {
+            let project = create_configured_project("settings.toml", config);
+            let assert = Command::cargo_bin(env!("CARGO_PKG_NAME"))
+                .expect("Binary not found")
+                .current_dir(project.path())
+                .args(["--config", "settings.toml", "logo.svg"])
+                .assert()
+                .code(EXIT_INVALID_ARGUMENTS);
+            let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+            assert!(stderr.contains("Configuration 'settings.toml'"), "{config}");
+        }
+
+        let project = create_configured_project("chinenshichanaka.toml", PROJECT_CONFIG);
+        let assert = Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .current_dir(project.path())
+            .args(["--preset", "web"])
+            .assert()
+            .code(EXIT_INVALID_ARGUMENTS);
+        let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+        assert!(stderr.contains("no preset 'web'"));
+    }
+
+    // Helper function to list the names and PNG sizes of the files in a package
+    fn package_contents(files: &[chinenshichanaka::PackageFile]) -> Vec<(String, Option<u32>)> {
+        files
+            .iter()
+            .map(|file| {
+                let size = file
+                    .name
+                    .ends_with(".png")
+                    .then(|| image::load_from_memory(&file.data).unwrap().width());
+                (file.name.clone(), size)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_favicon_package_from_svg() {
+        let files = favicon_package(SQUARE_SVG.as_bytes(), &PackageOptions::default()).unwrap();
+        assert_eq!(
+            package_contents(&files),
+            vec![
+                ("favicon.ico".to_string(), None),
+                ("favicon.svg".to_string(), None),
+                ("apple-touch-icon.png".to_string(), Some(180)),
+                ("android-chrome-192x192.png".to_string(), Some(192)),
+                ("android-chrome-512x512.png".to_string(), Some(512)),
+                ("site.webmanifest".to_string(), None),
+            ]
+        );
+        assert_eq!(files[1].data, SQUARE_SVG.as_bytes());
+        let entries = read_ico(&files[0].data).unwrap();
+        let sizes: Vec<u32> = entries.iter().map(|entry| entry.width).collect();
+        assert_eq!(sizes, PACKAGE_ICO_SIZES);
+    }
+
+    #[test]
+    fn test_favicon_package_from_raster() {
+        let mut png = Vec::new();
+        create_colorful_image(64, 64)
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        let options = PackageOptions {
+            favicon: options_with_sizes(&[32]),
+            ..Default::default()
+        };
+        let files = favicon_package(&png, &options).unwrap();
+        let names: Vec<String> = package_contents(&files)
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert!(!names.contains(&"favicon.svg".to_string()));
+        assert_eq!(names.len(), 5);
+
+        // The PNG images keep the colors of the source
+        let apple = image::load_from_memory(&files[1].data).unwrap().to_rgba8();
+        let colors: HashSet<Rgba<u8>> = apple.pixels().copied().collect();
+        assert!(colors.len() > 16);
+    }
+
+    #[test]
+    fn test_favicon_package_manifest() {
+        let options = PackageOptions {
+            base_url: "/static".to_string(),
+            name: Some("Okapi".to_string()),
+            theme_color: Some(Rgba([255, 136, 0, 255])),
+            ..Default::default()
+        };
+        let files = favicon_package(SQUARE_SVG.as_bytes(), &options).unwrap();
+        let manifest: serde_json::Value =
+            serde_json::from_slice(&files.last().unwrap().data).unwrap();
+        assert_eq!(manifest["name"], "Okapi");
+        assert_eq!(manifest["theme_color"], "#ff8800");
+        assert_eq!(manifest["background_color"], "#ffffff");
+        assert_eq!(manifest["display"], "standalone");
+        let icons: Vec<(&str, &str, &str, &str)> = manifest["icons"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|icon| {
+                (
+                    icon["src"].as_str().unwrap(),
+                    icon["sizes"].as_str().unwrap(),
+                    icon["type"].as_str().unwrap(),
+                    icon["purpose"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            icons,
+            vec![
+                ("/static/favicon.svg", "any", "image/svg+xml", "any"),
+                (
+                    "/static/android-chrome-192x192.png",
+                    "192x192",
+                    "image/png",
+                    "any"
+                ),
+                (
+                    "/static/android-chrome-512x512.png",
+                    "512x512",
+                    "image/png",
+                    "any"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_web_manifest_merges_into_existing() {
+        let existing = r##"{
+  "name": "Dojo",
+  "start_url": "/app/",
+  "icons": [
+    {"src": "/old/android-chrome-192x192.png", "sizes": "192x192"},
+    {"src": "/mask.png", "sizes": "512x512", "purpose": "maskable"}
+  ],
+  "theme_color": "#000000",
+  "lang": "fi"
+}"##;
+        let options = PackageOptions {
+            background_color: Some(Rgba([0, 0, 255, 255])),
+            manifest: Some(existing.as_bytes().to_vec()),
+            ..Default::default()
+        };
+        let files = favicon_package(SQUARE_SVG.as_bytes(), &options).unwrap();
+        let manifest: serde_json::Value =
+            serde_json::from_slice(&files.last().unwrap().data).unwrap();
+        let keys: Vec<&String> = manifest.as_object().unwrap().keys().collect();
+        assert_eq!(
+            keys,
+            [
+                "name",
+                "start_url",
+                "icons",
+                "theme_color",
+                "lang",
+                "short_name",
+                "background_color",
+                "display"
+            ]
+        );
+        assert_eq!(manifest["name"], "Dojo");
+        assert_eq!(manifest["short_name"], "Dojo");
+        assert_eq!(manifest["theme_color"], "#000000");
+        assert_eq!(manifest["background_color"], "#0000ff");
+        let sources: Vec<&str> = manifest["icons"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|icon| icon["src"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            sources,
+            [
+                "/favicon.svg",
+                "/android-chrome-192x192.png",
+                "/android-chrome-512x512.png",
+                "/mask.png"
+            ]
+        );
+        assert!(html_snippet(&files, &options).contains(r##"content="#000000""##));
+
+        // The given name replaces the old one
+        let renamed = PackageOptions {
+            name: Some("Okapi".to_string()),
+            ..options
+        };
+        let manifest: serde_json::Value =
+            serde_json::from_str(&web_manifest(&files, &renamed).unwrap()).unwrap();
+        assert_eq!(manifest["name"], "Okapi");
+        assert_eq!(manifest["short_name"], "Okapi");
+    }
+
+    #[test]
+    fn test_web_manifest_with_invalid_existing() {
+        for existing in ["[]", "{\"name\": ", "okapi"] {
+            let options = PackageOptions {
+                manifest: Some(existing.as_bytes().to_vec()),
+                ..Default::default()
+            };
+            assert!(matches!(
+                web_manifest(&[], &options),
+                Err(Error::InvalidManifest(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_favicon_package_derive_colors() {
+        // An orange mark on a blue background
+        let svg = r##"<svg width="64" height="64" xmlns="http://www.w3.org/2000/svg">
+<rect width="64" height="64" fill="#0000ff"/><rect x="8" y="8" width="48" height="48" fill="#ff8800"/>
+</svg>"##;
+        let options = PackageOptions {
+            derive_colors: true,
+            ..Default::default()
+        };
+        let files = favicon_package(svg.as_bytes(), &options).unwrap();
+        let manifest: serde_json::Value =
+            serde_json::from_slice(&files.last().unwrap().data).unwrap();
+        assert_eq!(manifest["theme_color"], "#ff8800");
+        assert_eq!(manifest["background_color"], "#0000ff");
+
+        // Given colors win, and a transparent edge gives a white background
+        let options = PackageOptions {
+            theme_color: Some(Rgba([17, 34, 51, 255])),
+            derive_colors: true,
+            ..Default::default()
+        };
+        let files = favicon_package(SQUARE_SVG.as_bytes(), &options).unwrap();
+        let manifest: serde_json::Value =
+            serde_json::from_slice(&files.last().unwrap().data).unwrap();
+        assert_eq!(manifest["theme_color"], "#112233");
+        assert_eq!(manifest["background_color"], "#ffffff");
+    }
+
+    #[test]
+    fn test_html_snippet() {
+        let options = PackageOptions {
+            base_url: "https://example.com/icons/".to_string(),
+            theme_color: Some(Rgba([17, 34, 51, 255])),
+            ..Default::default()
+        };
+        let files = favicon_package(SQUARE_SVG.as_bytes(), &options).unwrap();
+        assert_eq!(
+            html_snippet(&files, &options),
+            concat!(
+                r#"<link rel="icon" href="https://example.com/icons/favicon.ico" sizes="16x16 32x32 48x48">"#,
+                "\n",
+                r#"<link rel="icon" href="https://example.com/icons/favicon.svg" type="image/svg+xml">"#,
+                "\n",
+                r#"<link rel="apple-touch-icon" href="https://example.com/icons/apple-touch-icon.png">"#,
+                "\n",
+                r#"<link rel="manifest" href="https://example.com/icons/site.webmanifest">"#,
+                "\n",
+                r##"<meta name="theme-color" content="#112233">"##,
+                "\n",
+            )
+        );
+
+        // Without an SVG source, there is no link to it
+        let raster: Vec<_> = files
+            .into_iter()
+            .filter(|file| file.name != "favicon.svg")
+            .collect();
+        assert!(!html_snippet(&raster, &options).contains("svg"));
+    }
+
+    #[test]
+    fn test_main_package() {
+        let (temp_dir, svg_path) = create_temp_output_file("/logo.svg");
+        fs::write(&svg_path, SQUARE_SVG).unwrap();
+        let output_dir = temp_dir.path().join("public");
+
+        let assert = Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .args(["package", &svg_path, output_dir.to_str().unwrap()])
+            .args(["--sizes", "16,32", "--theme-color", "#ff8800"])
+            .assert()
+            .success();
+        let stdout = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+        assert!(stdout.starts_with(r#"<link rel="icon" href="/favicon.ico" sizes="16x16 32x32">"#));
+        assert!(stdout.ends_with("<meta name=\"theme-color\" content=\"#ff8800\">\n"));
+        let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+        assert!(stderr.contains("site.webmanifest"));
+
+        for name in [
+            "favicon.ico",
+            "favicon.svg",
+            "apple-touch-icon.png",
+            "android-chrome-192x192.png",
+            "android-chrome-512x512.png",
+            "site.webmanifest",
+        ] {
+            assert!(output_dir.join(name).is_file(), "{name}");
+        }
+        assert_eq!(entry_sizes(&output_dir.join("favicon.ico")), vec![16, 32]);
+    }
+
+    #[test]
+    fn test_main_package_merges_manifest() {
+        let (temp_dir, svg_path) = create_temp_output_file("/logo.svg");
+        fs::write(&svg_path, SQUARE_SVG).unwrap();
+        let manifest_path = temp_dir.path().join("site.webmanifest");
+        fs::write(&manifest_path, r#"{"name": "Dojo", "start_url": "/"}"#).unwrap();
+
+        Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .args(["package", &svg_path, temp_dir.path().to_str().unwrap()])
+            .assert()
+            .success();
+        let manifest: serde_json::Value =
+            serde_json::from_slice(&fs::read(&manifest_path).unwrap()).unwrap();
+        assert_eq!(manifest["name"], "Dojo");
+        assert_eq!(manifest["start_url"], "/");
+        assert_eq!(manifest["icons"].as_array().unwrap().len(), 3);
+
+        fs::write(&manifest_path, "not json").unwrap();
+        let assert = Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .args(["package", &svg_path, temp_dir.path().to_str().unwrap()])
+            .assert()
+            .code(EXIT_INVALID_ARGUMENTS);
+        let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+        assert!(stderr.contains("Invalid web app manifest"));
+        assert_eq!(fs::read(&manifest_path).unwrap(), b"not json");
+    }
+
+    #[test]
+    fn test_main_package_with_invalid_source() {
+        let (temp_dir, text_path) = create_temp_output_file("/logo.txt");
+        fs::write(&text_path, "not an image").unwrap();
+
+        Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .args(["package", &text_path, temp_dir.path().to_str().unwrap()])
+            .assert()
+            .code(EXIT_DECODE);
+        assert!(!temp_dir.path().join("favicon.ico").exists());
+    }
+
+    const PACKAGE_TAGS: &str = concat!(
+        r#"<link rel="icon" href="/favicon.ico" sizes="32x32">"#,
+        "\n",
+        r#"<link rel="manifest" href="/site.webmanifest">"#,
+        "\n",
+    );
+
+    #[test]
+    fn test_patch_html_replaces_stale_tags() {
+        let html = r##"<!DOCTYPE html>
+<html>
+  <head>
+    <meta charset="utf-8">
+    <LINK REL="Shortcut Icon" HREF=favicon.ico>
+    <title>Dojo</title>
+    <link rel="apple-touch-icon-precomposed" href="/touch.png" />
+    <meta name="Theme-Color" content="#000000">
+    <link rel="stylesheet" href="style.css">
+  </head>
+  <body>Okapi</body>
+</html>
+"##;
+        let expected = r##"<!DOCTYPE html>
+<html>
+  <head>
+    <meta charset="utf-8">
+    <link rel="icon" href="/favicon.ico" sizes="32x32">
+    <link rel="manifest" href="/site.webmanifest">
+    <title>Dojo</title>
+    <link rel="stylesheet" href="style.css">
+  </head>
+  <body>Okapi</body>
+</html>
+"##;
+        let patched = patch_html(html, PACKAGE_TAGS).unwrap();
+        assert_eq!(patched, expected);
+        assert_eq!(patch_html(&patched, PACKAGE_TAGS).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_patch_html_inserts_before_head_end() {
+        let html =
+            "<html>\r\n<head>\r\n\t<title>Dojo</title>\r\n</head>\r\n<body></body>\r\n</html>";
+        assert_eq!(
+            patch_html(html, PACKAGE_TAGS).unwrap(),
+            concat!(
+                "<html>\r\n<head>\r\n\t<title>Dojo</title>\r\n",
+                "\t<link rel=\"icon\" href=\"/favicon.ico\" sizes=\"32x32\">\r\n",
+                "\t<link rel=\"manifest\" href=\"/site.webmanifest\">\r\n",
+                "</head>\r\n<body></body>\r\n</html>"
+            )
+        );
+
+        // Without a line of its own for the end of the head, the tags go just before it
+        assert_eq!(
+            patch_html("<head><title>Dojo</title></head>", PACKAGE_TAGS).unwrap(),
+            concat!(
+                "<head><title>Dojo</title>\n",
+                "<link rel=\"icon\" href=\"/favicon.ico\" sizes=\"32x32\">\n",
+                "<link rel=\"manifest\" href=\"/site.webmanifest\">\n",
+                "</head>"
+            )
+        );
+    }
+
+    #[test]
+    fn test_patch_html_keeps_other_content() {
+        let html = r##"<head>
+  <!-- <link rel="icon" href="commented.png"> -->
+  <script>const tag = '<link rel="icon" href="script.png">';</script>
+  <link rel="mask-icon" href="/pinned.svg" color="#000"><link rel="icon" href="old.png">
+  <link rel="preload" href="font.woff2" as="font">
+</head>
+<body><header><link rel="icon" href="body.png"></header></body>
+"##;
+        let expected = r##"<head>
+  <!-- <link rel="icon" href="commented.png"> -->
+  <script>const tag = '<link rel="icon" href="script.png">';</script>
+  <link rel="mask-icon" href="/pinned.svg" color="#000"><link rel="icon" href="/favicon.ico" sizes="32x32">
+  <link rel="manifest" href="/site.webmanifest">
+  <link rel="preload" href="font.woff2" as="font">
+</head>
+<body><header><link rel="icon" href="body.png"></header></body>
+"##;
+        assert_eq!(patch_html(html, PACKAGE_TAGS).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_patch_html_without_head() {
+        assert!(matches!(
+            patch_html("<p>Okapi</p>", PACKAGE_TAGS),
+            Err(Error::InvalidHtml(_))
+        ));
+        // A template fragment with favicon tags can still be patched
+        assert_eq!(
+            patch_html(
+                "<link rel=\"icon\" href=\"old.png\">\n<title>Dojo</title>\n",
+                PACKAGE_TAGS
+            )
+            .unwrap(),
+            format!("{PACKAGE_TAGS}<title>Dojo</title>\n")
+        );
+    }
+
+    #[test]
+    fn test_main_package_patches_html() {
+        let (temp_dir, svg_path) = create_temp_output_file("/logo.svg");
+        fs::write(&svg_path, SQUARE_SVG).unwrap();
+        let html_path = temp_dir.path().join("index.html");
+        let html = "<html>\n<head>\n  <link rel=\"icon\" href=\"old.ico\">\n</head>\n<body>Dojo</body>\n</html>\n";
+        fs::write(&html_path, html).unwrap();
+
+        let assert = Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .args(["package", &svg_path, temp_dir.path().to_str().unwrap()])
+            .args([
+                "--base-url",
+                "/static/",
+                "--html",
+                html_path.to_str().unwrap(),
+            ])
+            .assert()
+            .success();
+        let stdout = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+        let patched = fs::read_to_string(&html_path).unwrap();
+        let indented: String = stdout.lines().map(|line| format!("  {line}\n")).collect();
+        assert_eq!(
+            patched,
+            format!("<html>\n<head>\n{indented}</head>\n<body>Dojo</body>\n</html>\n")
+        );
+
+        // A file that cannot take the tags stops the package before anything is written
+        let fragment_path = temp_dir.path().join("fragment.html");
+        fs::write(&fragment_path, "<p>Okapi</p>").unwrap();
+        let assert = Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .expect("Binary not found")
+            .args([
+                "package",
+                &svg_path,
+                temp_dir.path().join("new").to_str().unwrap(),
+            ])
+            .args(["--html", fragment_path.to_str().unwrap()])
+            .assert()
+            .code(EXIT_INVALID_ARGUMENTS);
+        let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+        assert!(stderr.contains("Invalid HTML file"));
+        assert!(!temp_dir.path().join("new").exists());
+    }
 }
@@ -0,0 +1,403 @@
+//! Producing the complete set of favicon files that a modern web site links to.
+
+use crate::{
+    detect_format, favicon_entries, format_hex_color, get_edge_color, reduce_colors,
+    square_images_from_bytes, svg, write_ico, Error, FaviconOptions, InputFormat, Result,
+};
+use image::codecs::png::PngEncoder;
+use image::{DynamicImage, ExtendedColorType, ImageEncoder, ImageReader, Rgba};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::io::Cursor;
+
+/// Size of the Apple touch icon, which iOS shows on the home screen.
+pub const APPLE_TOUCH_ICON_SIZE: u32 = 180;
+
+/// Sizes of the icons that Android uses from the web app manifest.
+pub const ANDROID_CHROME_SIZES: [u32; 2] = [192, 512];
+
+/// Sizes of the `favicon.ico` file of a package, for the browsers that do not use the other files.
+pub const PACKAGE_ICO_SIZES: [u32; 3] = [16, 32, 48];
+
+const ICO_NAME: &str = "favicon.ico";
+const SVG_NAME: &str = "favicon.svg";
+const APPLE_TOUCH_ICON_NAME: &str = "apple-touch-icon.png";
+const MANIFEST_NAME: &str = "site.webmanifest";
+const WHITE: Rgba<u8> = Rgba([255, 255, 255, 255]);
+// Size of the image the colors are derived from, enough to tell the dominant colors apart
+const COLOR_SAMPLE_SIZE: u32 = 64;
+
+/// Options for producing a favicon package with [`favicon_package`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct PackageOptions {
+    /// Options of the images. The sizes and the color reduction apply to `favicon.ico`,
+    /// while the PNG images keep true color.
+    pub favicon: FaviconOptions,
+    /// Path prefix of the URLs in the manifest and the HTML snippet, such as `/` or `/static/`.
+    pub base_url: String,
+    /// Name of the site in the web app manifest, replacing the name of an existing manifest.
+    pub name: Option<String>,
+    /// Color of the browser interface around the site. When not given, the color is derived
+    /// from the source image with `derive_colors`, or kept from an existing manifest,
+    /// or else white.
+    pub theme_color: Option<Rgba<u8>>,
+    /// Color of the splash screen shown while an installed web app starts, resolved like
+    /// `theme_color`.
+    pub background_color: Option<Rgba<u8>>,
+    /// Whether the colors that are not given are derived from the source image: the theme color
+    /// is its dominant opaque color and the background color the color of its edges.
+    pub derive_colors: bool,
+    /// Content of an existing web app manifest to merge into, keeping its other fields.
+    pub manifest: Option<Vec<u8>>,
+}
+
+impl Default for PackageOptions {
+    fn default() -> Self {
+        PackageOptions {
+            favicon: FaviconOptions {
+                sizes: PACKAGE_ICO_SIZES.to_vec(),
+                ..Default::default()
+            },
+            base_url: "/".to_string(),
+            name: None,
+            theme_color: None,
+            background_color: None,
+            derive_colors: false,
+            manifest: None,
+        }
+    }
+}
+
+/// A file of a favicon package, with the name it is served by.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackageFile {
+    /// File name, such as `apple-touch-icon.png`.
+    pub name: String,
+    /// Content of the file.
+    pub data: Vec<u8>,
+}
+
+/// Produces the favicon files of a web site from a single source image.
+///
+/// The package has `favicon.ico`, `apple-touch-icon.png` at 180 pixels,
+/// `android-chrome-192x192.png`, `android-chrome-512x512.png` and a `site.webmanifest`
+/// made by [`web_manifest`]. An SVG or SVGZ source is also included as `favicon.svg`,
+/// which modern browsers prefer, while a raster source has no SVG favicon.
+///
+/// Every image runs through the same pipeline as the icons of the ICO file, at its own size.
+///
+/// # Errors
+/// Returns the errors of decoding, resizing and encoding the images,
+/// and [`Error::InvalidManifest`] if the existing manifest is not a JSON object.
+///
+/// # Examples
+/// ```
+/// use chinenshichanaka::PackageOptions;
+/// let svg = br#"<svg width='32' height='32' xmlns='http://www.w3.org/2000/svg'/>"#;
+/// let files = chinenshichanaka::favicon_package(svg, &PackageOptions::default())?;
+/// let names: Vec<&str> = files.iter().map(|file| file.name.as_str()).collect();
+/// assert!(names.contains(&"favicon.svg"));
+/// assert!(names.contains(&"android-chrome-512x512.png"));
+/// # Ok::<(), chinenshichanaka::Error>(())
+/// ```
+pub fn favicon_package(input: &[u8], options: &PackageOptions) -> Result<Vec<PackageFile>> {
+    let favicon = &options.favicon;
+    let mut files = vec![PackageFile {
+        name: ICO_NAME.to_string(),
+        data: write_ico(&favicon_entries(input, favicon)?)?,
+    }];
+    match detect_format(input)? {
+        InputFormat::Svg => files.push(PackageFile {
+            name: SVG_NAME.to_string(),
+            data: input.to_vec(),
+        }),
+        InputFormat::Svgz => files.push(PackageFile {
+            name: SVG_NAME.to_string(),
+            data: svg::decompress_svgz(input)?,
+        }),
+        InputFormat::Raster(_) => {}
+    }
+
+    // Color reduction would only spoil the large PNG images
+    let png_options = FaviconOptions {
+        sizes: [APPLE_TOUCH_ICON_SIZE]
+            .into_iter()
+            .chain(ANDROID_CHROME_SIZES)
+            .collect(),
+        quantize: None,
+        ..favicon.clone()
+    };
+    let images = square_images_from_bytes(input, &png_options, svg::MAX_RENDER_SIZE)?;
+    for img in images {
+        let size = img.width();
+        let name = match size {
+            APPLE_TOUCH_ICON_SIZE => APPLE_TOUCH_ICON_NAME.to_string(),
+            _ => android_chrome_name(size),
+        };
+        files.push(PackageFile {
+            name,
+            data: encode_png(&img)?,
+        });
+    }
+
+    let manifest = match options.derive_colors {
+        true => {
+            let (theme_color, background_color) = source_colors(input, favicon)?;
+            web_manifest(
+                &files,
+                &PackageOptions {
+                    theme_color: options.theme_color.or(Some(theme_color)),
+                    background_color: options.background_color.or(Some(background_color)),
+                    ..options.clone()
+                },
+            )?
+        }
+        false => web_manifest(&files, options)?,
+    };
+    files.push(PackageFile {
+        name: MANIFEST_NAME.to_string(),
+        data: manifest.into_bytes(),
+    });
+    Ok(files)
+}
+
+/// Produces the web app manifest linking to the icons of a favicon package.
+///
+/// The `icons` array lists the Android PNG images and the SVG favicon of the files,
+/// with their `src`, `sizes`, `type` and `purpose`. When `options.manifest` has an existing
+/// manifest, the generated values are merged into it: the icons replace the entries
+/// with the same file name, and the given name and colors replace the old ones,
+/// while `start_url` and all the other fields are kept in their order.
+///
+/// # Errors
+/// Returns [`Error::InvalidManifest`] if the existing manifest is not a JSON object,
+/// and the decoding errors of the PNG images.
+///
+/// # Examples
+/// ```
+/// use chinenshichanaka::PackageOptions;
+/// let options = PackageOptions {
+///     manifest: Some(br#"{"name": "Okapi", "start_url": "/app"}"#.to_vec()),
+///     ..Default::default()
+/// };
+/// let manifest = chinenshichanaka::web_manifest(&[], &options)?;
+/// assert!(manifest.contains(r#""start_url": "/app""#));
+/// assert!(manifest.contains(r##""theme_color": "#ffffff""##));
+/// # Ok::<(), chinenshichanaka::Error>(())
+/// ```
+pub fn web_manifest(files: &[PackageFile], options: &PackageOptions) -> Result<String> {
+    let mut manifest = match &options.manifest {
+        Some(data) => match serde_json::from_slice(data) {
+            Ok(Value::Object(manifest)) => manifest,
+            Ok(_) => return Err(Error::InvalidManifest("expected a JSON object".to_string())),
+            Err(err) => return Err(Error::InvalidManifest(err.to_string())),
+        },
+        None => Map::new(),
+    };
+
+    if let Some(name) = &options.name {
+        manifest.insert("name".to_string(), Value::from(name.as_str()));
+        manifest.insert("short_name".to_string(), Value::from(name.as_str()));
+    }
+    let name = manifest
+        .entry("name")
+        .or_insert_with(|| Value::from(""))
+        .clone();
+    manifest.entry("short_name").or_insert(name);
+
+    let icons = manifest_icons(files, &options.base_url)?;
+    // Inserting an existing key keeps its position, unlike removing it first
+    let kept: Vec<Value> = match manifest.get("icons").cloned() {
+        Some(Value::Array(old_icons)) => old_icons
+            .into_iter()
+            .filter(|icon| {
+                let name = icon["src"].as_str().map(file_name);
+                !icons
+                    .iter()
+                    .any(|new| new["src"].as_str().map(file_name) == name)
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+    manifest.insert(
+        "icons".to_string(),
+        Value::Array(icons.into_iter().chain(kept).collect()),
+    );
+
+    for (key, color) in [
+        ("theme_color", options.theme_color),
+        ("background_color", options.background_color),
+    ] {
+        match color {
+            Some(color) => {
+                manifest.insert(key.to_string(), Value::from(format_hex_color(color)));
+            }
+            None => {
+                manifest
+                    .entry(key)
+                    .or_insert_with(|| Value::from(format_hex_color(WHITE)));
+            }
+        }
+    }
+    manifest
+        .entry("display")
+        .or_insert_with(|| Value::from("standalone"));
+    Ok(format!("{:#}\n", Value::Object(manifest)))
+}
+
+/// Lists the HTML tags that link to the files of a favicon package, one per line,
+/// for the `<head>` of the pages.
+///
+/// # Examples
+/// ```
+/// use chinenshichanaka::{PackageFile, PackageOptions};
+/// let files = vec![PackageFile { name: "favicon.ico".to_string(), data: Vec::new() }];
+/// let html = chinenshichanaka::html_snippet(&files, &PackageOptions::default());
+/// assert!(html.starts_with(r#"<link rel="icon" href="/favicon.ico" sizes="16x16 32x32 48x48">"#));
+/// ```
+pub fn html_snippet(files: &[PackageFile], options: &PackageOptions) -> String {
+    let has = |name: &str| files.iter().any(|file| file.name == name);
+    let url = |name: &str| format!("{}{name}", base_url(&options.base_url));
+    let mut lines = Vec::new();
+    if has(ICO_NAME) {
+        let sizes: Vec<String> = options
+            .favicon
+            .sizes
+            .iter()
+            .map(|size| format!("{size}x{size}"))
+            .collect();
+        lines.push(format!(
+            r#"<link rel="icon" href="{}" sizes="{}">"#,
+            url(ICO_NAME),
+            sizes.join(" ")
+        ));
+    }
+    if has(SVG_NAME) {
+        lines.push(format!(
+            r#"<link rel="icon" href="{}" type="image/svg+xml">"#,
+            url(SVG_NAME)
+        ));
+    }
+    if has(APPLE_TOUCH_ICON_NAME) {
+        lines.push(format!(
+            r#"<link rel="apple-touch-icon" href="{}">"#,
+            url(APPLE_TOUCH_ICON_NAME)
+        ));
+    }
+    if has(MANIFEST_NAME) {
+        lines.push(format!(
+            r#"<link rel="manifest" href="{}">"#,
+            url(MANIFEST_NAME)
+        ));
+    }
+    // The theme color of the manifest may come from the source image or an older manifest
+    let manifest_theme_color = files
+        .iter()
+        .find(|file| file.name == MANIFEST_NAME)
+        .and_then(|file| serde_json::from_slice::<Value>(&file.data).ok())
+        .and_then(|manifest| manifest["theme_color"].as_str().map(str::to_string));
+    let theme_color = options
+        .theme_color
+        .map(format_hex_color)
+        .or(manifest_theme_color)
+        .unwrap_or_else(|| format_hex_color(WHITE));
+    lines.push(format!(
+        r#"<meta name="theme-color" content="{theme_color}">"#
+    ));
+    lines.join("\n") + "\n"
+}
+
+fn android_chrome_name(size: u32) -> String {
+    format!("android-chrome-{size}x{size}.png")
+}
+
+// The base URL always ends with a slash, so that file names can be appended
+fn base_url(base_url: &str) -> String {
+    match base_url.ends_with('/') {
+        true => base_url.to_string(),
+        false => format!("{base_url}/"),
+    }
+}
+
+// Unlike the icons of an ICO file, the PNG images may be larger than 256 pixels
+fn encode_png(img: &DynamicImage) -> Result<Vec<u8>> {
+    let rgba = img.to_rgba8();
+    let mut data = Vec::new();
+    PngEncoder::new(&mut data)
+        .write_image(
+            rgba.as_raw(),
+            rgba.width(),
+            rgba.height(),
+            ExtendedColorType::Rgba8,
+        )
+        .map_err(Error::Encode)?;
+    Ok(data)
+}
+
+// The manifest icons of the Android images and the SVG favicon, all for any purpose
+fn manifest_icons(files: &[PackageFile], base: &str) -> Result<Vec<Value>> {
+    let mut icons = Vec::new();
+    for file in files {
+        let (sizes, mime) = if file.name == SVG_NAME {
+            ("any".to_string(), "image/svg+xml")
+        } else if ANDROID_CHROME_SIZES
+            .iter()
+            .any(|size| file.name == android_chrome_name(*size))
+        {
+            let (width, height) = ImageReader::new(Cursor::new(&file.data))
+                .with_guessed_format()
+                .map_err(|err| Error::Decode(err.into()))?
+                .into_dimensions()
+                .map_err(Error::Decode)?;
+            (format!("{width}x{height}"), "image/png")
+        } else {
+            continue;
+        };
+        icons.push(serde_json::json!({
+            "src": format!("{}{}", base_url(base), file.name),
+            "sizes": sizes,
+            "type": mime,
+            "purpose": "any",
+        }));
+    }
+    Ok(icons)
+}
+
+// The last segment of a URL, so that icons are matched even when the base URL changed
+fn file_name(src: &str) -> &str {
+    src.rsplit('/').next().unwrap_or(src)
+}
+
+// The dominant opaque color of the source image for the theme, and the color of its edges
+// for the background, with white for transparent edges
+fn source_colors(input: &[u8], options: &FaviconOptions) -> Result<(Rgba<u8>, Rgba<u8>)> {
+    let sample_options = FaviconOptions {
+        sizes: vec![COLOR_SAMPLE_SIZE],
+        quantize: None,
+        ..options.clone()
+    };
+    let images = square_images_from_bytes(input, &sample_options, COLOR_SAMPLE_SIZE)?;
+    let img = &images[0];
+    let background_color = match get_edge_color(img) {
+        color if color[3] == 255 => color,
+        _ => WHITE,
+    };
+
+    let reduced = reduce_colors(img, 8)?;
+    let mut counts: HashMap<[u8; 4], usize> = HashMap::new();
+    for pixel in reduced.to_rgba8().pixels().filter(|pixel| pixel[3] == 255) {
+        *counts.entry(pixel.0).or_default() += 1;
+    }
+    // The edge color would make the interface blend into the splash screen, so it is only
+    // the theme when the image has nothing else
+    let edge = get_edge_color(&reduced);
+    let theme_color = counts
+        .into_iter()
+        .filter(|(color, _)| Rgba(*color) != edge)
+        .max_by(|(color_a, count_a), (color_b, count_b)| {
+            count_a.cmp(count_b).then(color_b.cmp(color_a))
+        })
+        .map(|(color, _)| Rgba(color))
+        .unwrap_or(background_color);
+    Ok((theme_color, background_color))
+}
@@ -0,0 +1,278 @@
+//! Reading and writing palette files with a fixed list of colors.
+
+use crate::quantizer::PaletteQuantizer;
+use crate::{format_hex_color, parse_hex_color, ColorSpace, Error};
+use image::{DynamicImage, Rgba, RgbaImage};
+use std::collections::HashMap;
+use std::path::Path;
+
+// Adobe color tables hold 256 RGB colors, optionally followed by the count and transparent index
+const ACT_COLORS: usize = 256;
+const ACT_LENGTH: usize = ACT_COLORS * 3;
+const ACT_NO_TRANSPARENCY: u16 = 0xffff;
+
+const GPL_HEADER: &str = "GIMP Palette";
+
+/// File formats of palettes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaletteFormat {
+    /// GIMP palette text file, `.gpl`.
+    Gpl,
+    /// Adobe Color Table binary file, `.act`.
+    Act,
+    /// Text file with one hex color, such as `#ff8800`, per line.
+    Hex,
+}
+
+impl PaletteFormat {
+    /// Chooses the palette format by the suffix of a file name, using the hex list
+    /// for any other suffix than `.gpl` and `.act`.
+    ///
+    /// # Examples
+    /// ```
+    /// use chinenshichanaka::PaletteFormat;
+    /// assert_eq!(PaletteFormat::from_path("brand.GPL"), PaletteFormat::Gpl);
+    /// assert_eq!(PaletteFormat::from_path("brand.txt"), PaletteFormat::Hex);
+    /// ```
+    pub fn from_path(path: impl AsRef<Path>) -> PaletteFormat {
+        let extension = path
+            .as_ref()
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase);
+        match extension.as_deref() {
+            Some("gpl") => PaletteFormat::Gpl,
+            Some("act") => PaletteFormat::Act,
+            _ => PaletteFormat::Hex,
+        }
+    }
+}
+
+/// Parses the colors of a palette file.
+///
+/// The colors of GIMP and Adobe palettes are opaque, while a hex list may give
+/// an alpha value with the `#rrggbbaa` notation. The transparent entry of an
+/// Adobe palette is left out, since fully transparent pixels stay transparent.
+///
+/// # Arguments
+/// * `data` - Content of the palette file.
+/// * `format` - Format of the palette file.
+///
+/// # Errors
+/// Returns [`Error::InvalidPalette`] if the content is malformed or has no colors,
+/// and [`Error::InvalidColor`] for a malformed color in a hex list.
+///
+/// # Examples
+/// ```
+/// use chinenshichanaka::PaletteFormat;
+/// use image::Rgba;
+/// let gpl = "GIMP Palette\nName: Brand\n#\n255 136   0\tOrange\n  0   0   0\tBlack\n";
+/// let colors = chinenshichanaka::parse_palette(gpl.as_bytes(), PaletteFormat::Gpl)?;
+/// assert_eq!(colors, vec![Rgba([255, 136, 0, 255]), Rgba([0, 0, 0, 255])]);
+/// # Ok::<(), chinenshichanaka::Error>(())
+/// ```
+pub fn parse_palette(data: &[u8], format: PaletteFormat) -> Result<Vec<Rgba<u8>>, Error> {
+    let colors = match format {
+        PaletteFormat::Gpl => parse_gpl(text(data)?)?,
+        PaletteFormat::Act => parse_act(data)?,
+        PaletteFormat::Hex => parse_hex_list(text(data)?)?,
+    };
+    if colors.is_empty() {
+        return Err(Error::InvalidPalette("no colors found".to_string()));
+    }
+    Ok(colors)
+}
+
+/// Lists the colors used in the images, such as the reduced icons of a conversion.
+///
+/// Fully transparent pixels have no visible color, so they are left out.
+///
+/// # Returns
+/// The distinct colors, the most used first, with ties in numeric order.
+///
+/// # Examples
+/// ```
+/// use image::{DynamicImage, Rgba, RgbaImage};
+/// let mut img = RgbaImage::from_pixel(4, 4, Rgba([255, 0, 0, 255]));
+/// img.put_pixel(0, 0, Rgba([0, 0, 255, 255]));
+/// img.put_pixel(1, 0, Rgba([0, 0, 0, 0]));
+/// let colors = chinenshichanaka::used_colors(&[DynamicImage::ImageRgba8(img)]);
+/// assert_eq!(colors, vec![Rgba([255, 0, 0, 255]), Rgba([0, 0, 255, 255])]);
+/// ```
+pub fn used_colors(images: &[DynamicImage]) -> Vec<Rgba<u8>> {
+    let mut counts: HashMap<[u8; 4], usize> = HashMap::new();
+    for img in images {
+        for pixel in img.to_rgba8().pixels().filter(|pixel| pixel[3] > 0) {
+            *counts.entry(pixel.0).or_default() += 1;
+        }
+    }
+    let mut colors: Vec<([u8; 4], usize)> = counts.into_iter().collect();
+    colors.sort_by(|(color_a, count_a), (color_b, count_b)| {
+        count_b.cmp(count_a).then(color_a.cmp(color_b))
+    });
+    colors.into_iter().map(|(color, _)| Rgba(color)).collect()
+}
+
+/// Writes the colors into a palette file.
+///
+/// GIMP and Adobe palettes cannot store alpha values, so only the color channels are written.
+///
+/// # Arguments
+/// * `colors` - Colors of the palette.
+/// * `format` - Format of the palette file.
+///
+/// # Errors
+/// Returns [`Error::InvalidPalette`] if an Adobe palette would have more than 256 colors.
+///
+/// # Examples
+/// ```
+/// use chinenshichanaka::PaletteFormat;
+/// use image::Rgba;
+/// let colors = vec![Rgba([255, 136, 0, 255]), Rgba([0, 0, 0, 128])];
+/// let hex = chinenshichanaka::write_palette(&colors, PaletteFormat::Hex)?;
+/// assert_eq!(hex, b"#ff8800\n#00000080\n");
+/// # Ok::<(), chinenshichanaka::Error>(())
+/// ```
+pub fn write_palette(colors: &[Rgba<u8>], format: PaletteFormat) -> Result<Vec<u8>, Error> {
+    match format {
+        PaletteFormat::Gpl => {
+            let mut output = format!("{GPL_HEADER}\nName: {}\n#\n", env!("CARGO_PKG_NAME"));
+            for color in colors {
+                output += &format!(
+                    "{:>3} {:>3} {:>3}\t{}\n",
+                    color[0],
+                    color[1],
+                    color[2],
+                    format_hex_color(Rgba([color[0], color[1], color[2], 255]))
+                );
+            }
+            Ok(output.into_bytes())
+        }
+        PaletteFormat::Act => {
+            if colors.len() > ACT_COLORS {
+                return Err(Error::InvalidPalette(format!(
+                    "{} colors do not fit in an Adobe color table of {ACT_COLORS}",
+                    colors.len()
+                )));
+            }
+            let mut output = vec![0; ACT_LENGTH];
+            for (entry, color) in output.chunks_exact_mut(3).zip(colors) {
+                entry.copy_from_slice(&color.0[..3]);
+            }
+            output.extend_from_slice(&(colors.len() as u16).to_be_bytes());
+            output.extend_from_slice(&ACT_NO_TRANSPARENCY.to_be_bytes());
+            Ok(output)
+        }
+        PaletteFormat::Hex => Ok(colors
+            .iter()
+            .map(|&color| format_hex_color(color) + "\n")
+            .collect::<String>()
+            .into_bytes()),
+    }
+}
+
+fn text(data: &[u8]) -> Result<&str, Error> {
+    std::str::from_utf8(data)
+        .map_err(|_| Error::InvalidPalette("the file is not UTF-8 text".to_string()))
+}
+
+fn parse_gpl(text: &str) -> Result<Vec<Rgba<u8>>, Error> {
+    let mut lines = text.lines().enumerate();
+    match lines.next() {
+        Some((_, header)) if header.trim() == GPL_HEADER => {}
+        _ => {
+            return Err(Error::InvalidPalette(format!(
+                "missing the '{GPL_HEADER}' header"
+            )))
+        }
+    }
+    let mut colors = Vec::new();
+    for (index, line) in lines {
+        let line = line.trim();
+        if line.is_empty()
+            || line.starts_with('#')
+            || line.starts_with("Name:")
+            || line.starts_with("Columns:")
+        {
+            continue;
+        }
+        // The three channel values may be followed by the name of the color
+        let channels: Vec<u8> = line
+            .split_whitespace()
+            .take(3)
+            .map_while(|value| value.parse().ok())
+            .collect();
+        match channels[..] {
+            [red, green, blue] => colors.push(Rgba([red, green, blue, 255])),
+            _ => {
+                return Err(Error::InvalidPalette(format!(
+                    "line {} '{line}' is not a color",
+                    index + 1
+                )))
+            }
+        }
+    }
+    Ok(colors)
+}
+
+fn parse_act(data: &[u8]) -> Result<Vec<Rgba<u8>>, Error> {
+    let (count, transparent) = match data.len() {
+        ACT_LENGTH => (ACT_COLORS, None),
+        length if length == ACT_LENGTH + 4 => {
+            let count = u16::from_be_bytes([data[ACT_LENGTH], data[ACT_LENGTH + 1]]) as usize;
+            let transparent = u16::from_be_bytes([data[ACT_LENGTH + 2], data[ACT_LENGTH + 3]]);
+            (
+                count.min(ACT_COLORS),
+                (transparent != ACT_NO_TRANSPARENCY).then_some(transparent as usize),
+            )
+        }
+        length => {
+            return Err(Error::InvalidPalette(format!(
+                "an Adobe color table has {ACT_LENGTH} or {} bytes, not {length}",
+                ACT_LENGTH + 4
+            )))
+        }
+    };
+    Ok(data[..count * 3]
+        .chunks_exact(3)
+        .enumerate()
+        .filter(|&(index, _)| Some(index) != transparent)
+        .map(|(_, color)| Rgba([color[0], color[1], color[2], 255]))
+        .collect())
+}
+
+fn parse_hex_list(text: &str) -> Result<Vec<Rgba<u8>>, Error> {
+    let mut colors = Vec::new();
+    for line in text.lines() {
+        // Semicolons start a comment until the end of the line
+        let line = line.split(';').next().unwrap_or_default();
+        for value in line.split(|c: char| c.is_whitespace() || c == ',') {
+            if !value.is_empty() {
+                colors.push(parse_hex_color(value)?);
+            }
+        }
+    }
+    Ok(colors)
+}
+
+/// A palette quantizer that always chooses the given colors, for mapping icons onto a fixed palette.
+///
+/// # Examples
+/// ```
+/// use chinenshichanaka::{ColorSpace, Dither, FixedPalette};
+/// use image::{DynamicImage, GenericImageView, Rgba, RgbaImage};
+/// let brand = FixedPalette(vec![Rgba([255, 136, 0, 255]), Rgba([0, 0, 0, 255])]);
+/// let img = DynamicImage::ImageRgba8(RgbaImage::from_pixel(4, 4, Rgba([240, 120, 10, 255])));
+/// let reduced =
+///     chinenshichanaka::quantize_with(&img, &brand, 2, Dither::None, ColorSpace::Srgb)?;
+/// assert_eq!(reduced.get_pixel(0, 0), Rgba([255, 136, 0, 255]));
+/// # Ok::<(), chinenshichanaka::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FixedPalette(pub Vec<Rgba<u8>>);
+
+impl PaletteQuantizer for FixedPalette {
+    fn palette(&self, _img: &RgbaImage, _colors: usize, _color_space: ColorSpace) -> Vec<Rgba<u8>> {
+        self.0.clone()
+    }
+}
@@ -0,0 +1,360 @@
+//! Reducing the colors of an icon to a small palette.
+
+use crate::palette::FixedPalette;
+use crate::quantizer::{
+    MedianCutQuantizer, NeuQuantQuantizer, OctreeQuantizer, PaletteQuantizer, Quantizer,
+};
+use crate::{ColorSpace, Error, DEFAULT_COLORS};
+use image::{DynamicImage, Rgba, RgbaImage};
+use std::fmt;
+use std::str::FromStr;
+
+/// Sample factor used by default, sampling every pixel for the best quality.
+pub const DEFAULT_SAMPLE_FACTOR: i32 = 1;
+
+/// Largest sample factor accepted by NeuQuant, the fastest and roughest setting.
+pub const MAX_SAMPLE_FACTOR: i32 = 30;
+
+// Bayer threshold matrix for ordered dithering, values from 0 to 63
+const BAYER_8X8: [[u8; 8]; 8] = [
+    [0, 32, 8, 40, 2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44, 4, 36, 14, 46, 6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [3, 35, 11, 43, 1, 33, 9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47, 7, 39, 13, 45, 5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+/// How the pixels are mapped to the reduced palette.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Dither {
+    /// Map each pixel to its nearest palette color.
+    #[default]
+    None,
+    /// Diffuse the mapping error to the neighbouring pixels with Floyd–Steinberg weights.
+    FloydSteinberg,
+    /// Offset each pixel with an 8x8 Bayer threshold matrix before mapping.
+    Ordered,
+}
+
+impl FromStr for Dither {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "none" => Ok(Dither::None),
+            "floyd-steinberg" | "fs" => Ok(Dither::FloydSteinberg),
+            "ordered" | "bayer" => Ok(Dither::Ordered),
+            _ => Err(Error::InvalidOption {
+                name: "dither",
+                value: value.to_string(),
+                expected: "none, floyd-steinberg or ordered",
+            }),
+        }
+    }
+}
+
+impl fmt::Display for Dither {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Dither::None => write!(f, "none"),
+            Dither::FloydSteinberg => write!(f, "floyd-steinberg"),
+            Dither::Ordered => write!(f, "ordered"),
+        }
+    }
+}
+
+/// Options for reducing the colors of an image with [`quantize`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuantizeOptions {
+    /// Number of palette colors.
+    pub colors: usize,
+    /// Algorithm choosing the palette.
+    pub quantizer: Quantizer,
+    /// NeuQuant sample factor from 1 to [`MAX_SAMPLE_FACTOR`], higher is faster but rougher.
+    pub sample_factor: i32,
+    /// How the pixels are mapped to the palette.
+    pub dither: Dither,
+    /// Color space in which the palette is chosen and the nearest palette color of each
+    /// pixel is found.
+    pub color_space: ColorSpace,
+    /// Fixed colors to map onto, instead of the palette chosen by the quantizer.
+    pub palette: Option<Vec<Rgba<u8>>>,
+}
+
+impl Default for QuantizeOptions {
+    fn default() -> Self {
+        QuantizeOptions {
+            colors: DEFAULT_COLORS,
+            quantizer: Quantizer::default(),
+            sample_factor: DEFAULT_SAMPLE_FACTOR,
+            dither: Dither::default(),
+            color_space: ColorSpace::default(),
+            palette: None,
+        }
+    }
+}
+
+/// Reduces the number of colors in a `DynamicImage` using the chosen quantizer.
+///
+/// The alpha channel takes part in the quantization, so semi-transparent areas
+/// remain so in the reduced image, while fully transparent pixels are kept
+/// fully transparent regardless of the palette. The output is deterministic,
+/// the same image and options always produce the same pixels.
+///
+/// When a fixed palette is given, the pixels are mapped onto it and the color
+/// count, quantizer and sample factor are not used.
+///
+/// # Arguments
+/// * `img` - Reference to the input image.
+/// * `options` - Palette size, quantizer, sample factor, dithering, color space and fixed palette.
+///
+/// # Returns
+/// A new RGBA8 `DynamicImage` with reduced colors.
+///
+/// # Errors
+/// Returns [`Error::InvalidColorCount`] if the color count is zero,
+/// [`Error::InvalidOption`] if the sample factor is outside `1..=30`, and
+/// [`Error::InvalidPalette`] if the fixed palette is empty.
+///
+/// # Examples
+/// ```
+/// use chinenshichanaka::{Dither, QuantizeOptions};
+/// use image::{DynamicImage, Rgba, RgbaImage};
+/// let gradient = RgbaImage::from_fn(64, 8, |x, _| Rgba([x as u8 * 4, 0, 0, 255]));
+/// let options = QuantizeOptions { colors: 4, dither: Dither::FloydSteinberg, ..Default::default() };
+/// let reduced = chinenshichanaka::quantize(&DynamicImage::ImageRgba8(gradient), &options)?;
+/// let unique: std::collections::HashSet<_> = reduced.to_rgba8().pixels().copied().collect();
+/// assert!(unique.len() <= 4);
+/// # Ok::<(), chinenshichanaka::Error>(())
+/// ```
+pub fn quantize(img: &DynamicImage, options: &QuantizeOptions) -> Result<DynamicImage, Error> {
+    let (quantizer, colors) = palette_quantizer(options)?;
+    quantize_with(
+        img,
+        quantizer.as_ref(),
+        colors,
+        options.dither,
+        options.color_space,
+    )
+}
+
+/// Chooses the palette that [`quantize`] would map the pixels of an image onto.
+///
+/// # Arguments
+/// * `img` - Reference to the input image.
+/// * `options` - Palette size, quantizer, sample factor and fixed palette.
+///
+/// # Returns
+/// The palette colors, at most as many as the color count.
+///
+/// # Errors
+/// Returns the same errors as [`quantize`].
+///
+/// # Examples
+/// ```
+/// use chinenshichanaka::{QuantizeOptions, Quantizer};
+/// use image::{DynamicImage, Rgba, RgbaImage};
+/// let img = RgbaImage::from_fn(8, 8, |x, _| if x < 4 { Rgba([255, 0, 0, 255]) } else { Rgba([0, 0, 255, 255]) });
+/// let options = QuantizeOptions { colors: 4, quantizer: Quantizer::MedianCut, ..Default::default() };
+/// let palette = chinenshichanaka::choose_palette(&DynamicImage::ImageRgba8(img), &options)?;
+/// assert_eq!(palette, vec![Rgba([0, 0, 255, 255]), Rgba([255, 0, 0, 255])]);
+/// # Ok::<(), chinenshichanaka::Error>(())
+/// ```
+pub fn choose_palette(
+    img: &DynamicImage,
+    options: &QuantizeOptions,
+) -> Result<Vec<Rgba<u8>>, Error> {
+    let (quantizer, colors) = palette_quantizer(options)?;
+    Ok(quantizer.palette(&img.to_rgba8(), colors, options.color_space))
+}
+
+// Validates the options and returns the quantizer they describe, with its color count
+fn palette_quantizer(
+    options: &QuantizeOptions,
+) -> Result<(Box<dyn PaletteQuantizer>, usize), Error> {
+    if let Some(palette) = &options.palette {
+        if palette.is_empty() {
+            return Err(Error::InvalidPalette("no colors found".to_string()));
+        }
+        return Ok((Box::new(FixedPalette(palette.clone())), palette.len()));
+    }
+    if options.colors == 0 {
+        return Err(Error::InvalidColorCount(options.colors));
+    }
+    if !(1..=MAX_SAMPLE_FACTOR).contains(&options.sample_factor) {
+        return Err(Error::InvalidOption {
+            name: "sample factor",
+            value: options.sample_factor.to_string(),
+            expected: "a number between 1 and 30",
+        });
+    }
+    let quantizer: Box<dyn PaletteQuantizer> = match options.quantizer {
+        Quantizer::NeuQuant => Box::new(NeuQuantQuantizer {
+            sample_factor: options.sample_factor,
+        }),
+        Quantizer::MedianCut => Box::new(MedianCutQuantizer),
+        Quantizer::Octree => Box::new(OctreeQuantizer),
+    };
+    Ok((quantizer, options.colors))
+}
+
+/// Reduces the number of colors in a `DynamicImage` with a custom palette quantizer.
+///
+/// # Arguments
+/// * `img` - Reference to the input image.
+/// * `quantizer` - Algorithm choosing the palette.
+/// * `colors` - Number of palette colors.
+/// * `dither` - How the pixels are mapped to the palette.
+/// * `color_space` - Color space in which the palette is chosen and the nearest palette
+///   colors are found.
+///
+/// # Returns
+/// A new RGBA8 `DynamicImage` with reduced colors.
+///
+/// # Errors
+/// Returns [`Error::InvalidColorCount`] if `colors` is zero.
+///
+/// # Examples
+/// ```
+/// use chinenshichanaka::{ColorSpace, Dither, PaletteQuantizer};
+/// use image::{DynamicImage, GenericImageView, Rgba, RgbaImage};
+/// // A quantizer that always picks black and white
+/// struct Monochrome;
+/// impl PaletteQuantizer for Monochrome {
+///     fn palette(&self, _img: &RgbaImage, _colors: usize, _space: ColorSpace) -> Vec<Rgba<u8>> {
+///         vec![Rgba([0, 0, 0, 255]), Rgba([255, 255, 255, 255])]
+///     }
+/// }
+/// let img = DynamicImage::ImageRgba8(RgbaImage::from_pixel(4, 4, Rgba([200, 180, 190, 255])));
+/// let reduced =
+///     chinenshichanaka::quantize_with(&img, &Monochrome, 2, Dither::None, ColorSpace::Srgb)?;
+/// assert_eq!(reduced.get_pixel(0, 0), Rgba([255, 255, 255, 255]));
+/// # Ok::<(), chinenshichanaka::Error>(())
+/// ```
+pub fn quantize_with(
+    img: &DynamicImage,
+    quantizer: &dyn PaletteQuantizer,
+    colors: usize,
+    dither: Dither,
+    color_space: ColorSpace,
+) -> Result<DynamicImage, Error> {
+    if colors == 0 {
+        return Err(Error::InvalidColorCount(colors));
+    }
+    let rgba = img.to_rgba8();
+    let palette = quantizer.palette(&rgba, colors, color_space);
+    Ok(DynamicImage::ImageRgba8(map_to_palette(
+        &rgba,
+        &palette,
+        dither,
+        color_space,
+    )))
+}
+
+/// Reduces the number of colors in a `DynamicImage` using the NeuQuant algorithm.
+///
+/// Shorthand for [`quantize`] with the default quantizer and sample factor, and no dithering.
+///
+/// # Arguments
+/// * `img` - Reference to the input image.
+/// * `colors` - Number of colors to reduce to.
+///
+/// # Returns
+/// A new RGBA8 `DynamicImage` with reduced colors.
+///
+/// # Errors
+/// Returns [`Error::InvalidColorCount`] if `colors` is zero.
+///
+/// # Examples
+/// ```
+/// use image::{DynamicImage, GenericImageView, Rgba};
+/// let img = DynamicImage::new_rgba8(10, 10);
+/// let reduced = chinenshichanaka::reduce_colors(&img, 4)?;
+/// assert_eq!(reduced.dimensions(), (10, 10));
+/// # Ok::<(), chinenshichanaka::Error>(())
+/// ```
+pub fn reduce_colors(img: &DynamicImage, colors: usize) -> Result<DynamicImage, Error> {
+    quantize(
+        img,
+        &QuantizeOptions {
+            colors,
+            ..Default::default()
+        },
+    )
+}
+
+// Maps every pixel to a palette color, keeping fully transparent pixels fully transparent
+pub(crate) fn map_to_palette(
+    img: &RgbaImage,
+    palette: &[Rgba<u8>],
+    dither: Dither,
+    color_space: ColorSpace,
+) -> RgbaImage {
+    let (width, height) = img.dimensions();
+    let coordinates: Vec<[f32; 4]> = palette
+        .iter()
+        .map(|color| color_space.coordinates(color.0.map(f32::from)))
+        .collect();
+    let mut output = RgbaImage::new(width, height);
+    // Quantization error carried over to the pixels not yet mapped
+    let mut errors = vec![[0f32; 4]; (width * height) as usize];
+    // Ordered dithering offsets by up to half the distance between palette levels
+    let spread = 255.0 / (palette.len() as f32).cbrt();
+    for (x, y, pixel) in img.enumerate_pixels() {
+        if pixel[3] == 0 {
+            continue;
+        }
+        let index = (y * width + x) as usize;
+        let mut value = [0f32; 4];
+        for channel in 0..4 {
+            value[channel] = (pixel[channel] as f32 + errors[index][channel]).clamp(0.0, 255.0);
+        }
+        if dither == Dither::Ordered {
+            let threshold = (BAYER_8X8[(y % 8) as usize][(x % 8) as usize] as f32 + 0.5) / 64.0;
+            for channel in value.iter_mut().take(3) {
+                *channel = (*channel + (threshold - 0.5) * spread).clamp(0.0, 255.0);
+            }
+        }
+        let color = nearest_color(palette, &coordinates, color_space.coordinates(value));
+        output.put_pixel(x, y, color);
+
+        if dither == Dither::FloydSteinberg {
+            let mut error = [0f32; 4];
+            for channel in 0..4 {
+                error[channel] = value[channel] - color[channel] as f32;
+            }
+            let (x, y) = (x as i64, y as i64);
+            for (dx, dy, weight) in [(1, 0, 7.0), (-1, 1, 3.0), (0, 1, 5.0), (1, 1, 1.0)] {
+                let (nx, ny) = (x + dx, y + dy);
+                if nx < 0 || nx >= width as i64 || ny >= height as i64 {
+                    continue;
+                }
+                let neighbour = &mut errors[(ny * width as i64 + nx) as usize];
+                for channel in 0..4 {
+                    neighbour[channel] += error[channel] * weight / 16.0;
+                }
+            }
+        }
+    }
+    output
+}
+
+// Finds the palette color with the smallest squared distance of the coordinates,
+// the first one on ties
+fn nearest_color(palette: &[Rgba<u8>], coordinates: &[[f32; 4]], value: [f32; 4]) -> Rgba<u8> {
+    let distance = |color: &[f32; 4]| -> f32 {
+        color
+            .iter()
+            .zip(value)
+            .map(|(channel, value)| (value - channel).powi(2))
+            .sum()
+    };
+    coordinates
+        .iter()
+        .zip(palette)
+        .min_by(|a, b| distance(a.0).total_cmp(&distance(b.0)))
+        .map_or(Rgba([0, 0, 0, 0]), |(_, color)| *color)
+}
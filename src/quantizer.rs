@@ -0,0 +1,288 @@
+//! Algorithms for choosing the palette of a reduced color image.
+
+use crate::quantize::DEFAULT_SAMPLE_FACTOR;
+use crate::{ColorSpace, Error};
+use color_quant::NeuQuant;
+use image::{Rgba, RgbaImage};
+use std::fmt;
+use std::str::FromStr;
+
+/// Chooses the palette that the pixels of an image are mapped to.
+///
+/// Implementations must be deterministic, always returning the same palette for
+/// the same image and color count, so that regenerated icons do not change.
+pub trait PaletteQuantizer {
+    /// Returns at most `colors` palette colors for the image, where `colors` is at least one.
+    ///
+    /// Fully transparent pixels are always mapped to transparent black, so they
+    /// need not be represented in the palette. The color space is the one in which the
+    /// pixels are later mapped to the palette, so grouping the colors in it picks the
+    /// palette that those distances favour.
+    fn palette(&self, img: &RgbaImage, colors: usize, color_space: ColorSpace) -> Vec<Rgba<u8>>;
+}
+
+/// The built-in palette quantization algorithms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Quantizer {
+    /// Kohonen neural network quantization, see [`NeuQuantQuantizer`].
+    #[default]
+    NeuQuant,
+    /// Recursive splitting of the color space at the median, see [`MedianCutQuantizer`].
+    MedianCut,
+    /// Merging of the least used branches of a color tree, see [`OctreeQuantizer`].
+    Octree,
+}
+
+impl FromStr for Quantizer {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "neuquant" => Ok(Quantizer::NeuQuant),
+            "median-cut" => Ok(Quantizer::MedianCut),
+            "octree" => Ok(Quantizer::Octree),
+            _ => Err(Error::InvalidOption {
+                name: "quantizer",
+                value: value.to_string(),
+                expected: "neuquant, median-cut or octree",
+            }),
+        }
+    }
+}
+
+impl fmt::Display for Quantizer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Quantizer::NeuQuant => write!(f, "neuquant"),
+            Quantizer::MedianCut => write!(f, "median-cut"),
+            Quantizer::Octree => write!(f, "octree"),
+        }
+    }
+}
+
+/// The NeuQuant neural network quantizer from the `color_quant` crate.
+///
+/// The network learns in sRGB values, so the color space does not change the palette.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NeuQuantQuantizer {
+    /// Sample factor from 1, which samples every pixel, up to 30 which is the fastest.
+    pub sample_factor: i32,
+}
+
+impl Default for NeuQuantQuantizer {
+    fn default() -> Self {
+        NeuQuantQuantizer {
+            sample_factor: DEFAULT_SAMPLE_FACTOR,
+        }
+    }
+}
+
+impl PaletteQuantizer for NeuQuantQuantizer {
+    fn palette(&self, img: &RgbaImage, colors: usize, _color_space: ColorSpace) -> Vec<Rgba<u8>> {
+        NeuQuant::new(self.sample_factor, colors, img.as_raw())
+            .color_map_rgba()
+            .chunks_exact(4)
+            .map(|color| Rgba([color[0], color[1], color[2], color[3]]))
+            .collect()
+    }
+}
+
+/// Median cut quantizer, splitting the box with the widest channel range at its median
+/// until there is a box for each palette color.
+///
+/// The boxes are split along the coordinates of the color space, and each palette color
+/// is the average of the pixels in its box.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MedianCutQuantizer;
+
+// A pixel with its coordinates in the color space of the quantization
+type Sample = ([f32; 4], [u8; 4]);
+
+impl PaletteQuantizer for MedianCutQuantizer {
+    fn palette(&self, img: &RgbaImage, colors: usize, color_space: ColorSpace) -> Vec<Rgba<u8>> {
+        let pixels: Vec<Sample> = opaque_pixels(img)
+            .map(|pixel| (color_space.coordinates(pixel.map(f32::from)), pixel))
+            .collect();
+        if pixels.is_empty() {
+            return Vec::new();
+        }
+        let mut boxes = vec![pixels];
+        while boxes.len() < colors {
+            // The first of the boxes with the widest range, boxes of a single color cannot be split
+            let Some((index, channel, _)) = boxes
+                .iter()
+                .enumerate()
+                .map(|(index, pixels)| {
+                    let (channel, range) = widest_channel(pixels);
+                    (index, channel, range)
+                })
+                .filter(|&(_, _, range)| range > 0.0)
+                .max_by(|a, b| a.2.total_cmp(&b.2).then(b.0.cmp(&a.0)))
+            else {
+                break;
+            };
+            let mut pixels = boxes.remove(index);
+            // Sorting by the whole pixel after the channel keeps the split deterministic
+            pixels
+                .sort_unstable_by(|a, b| a.0[channel].total_cmp(&b.0[channel]).then(a.1.cmp(&b.1)));
+            // Splitting between different values keeps both halves non-empty
+            let median = pixels[pixels.len() / 2].0[channel];
+            let split = match pixels.partition_point(|pixel| pixel.0[channel] < median) {
+                0 => pixels.partition_point(|pixel| pixel.0[channel] <= median),
+                split => split,
+            };
+            let upper = pixels.split_off(split);
+            boxes.insert(index, pixels);
+            boxes.insert(index + 1, upper);
+        }
+        boxes.iter().map(|pixels| average(pixels)).collect()
+    }
+}
+
+// Returns the channel with the widest range of coordinates in the pixels, and that range
+fn widest_channel(pixels: &[Sample]) -> (usize, f32) {
+    (0..4)
+        .map(|channel| {
+            let (min, max) =
+                pixels
+                    .iter()
+                    .fold((f32::MAX, f32::MIN), |(min, max), (coordinates, _)| {
+                        (min.min(coordinates[channel]), max.max(coordinates[channel]))
+                    });
+            (channel, max - min)
+        })
+        .fold((0, 0.0), |best, candidate| {
+            if candidate.1 > best.1 {
+                candidate
+            } else {
+                best
+            }
+        })
+}
+
+fn average(pixels: &[Sample]) -> Rgba<u8> {
+    let mut sum = [0u64; 4];
+    for (_, pixel) in pixels {
+        for (total, &value) in sum.iter_mut().zip(pixel) {
+            *total += value as u64;
+        }
+    }
+    let count = pixels.len() as u64;
+    Rgba(sum.map(|total| ((total + count / 2) / count) as u8))
+}
+
+// The pixels that are not fully transparent
+fn opaque_pixels(img: &RgbaImage) -> impl Iterator<Item = [u8; 4]> + '_ {
+    img.pixels()
+        .filter(|pixel| pixel[3] > 0)
+        .map(|pixel| pixel.0)
+}
+
+/// Octree quantizer, building a tree of the colors one bit per channel at each level
+/// and merging the least used branches until there is a leaf for each palette color.
+///
+/// The channels of the tree are the coordinates of the color space scaled to bytes, and
+/// each palette color is the average of the pixels in its leaf.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct OctreeQuantizer;
+
+// Levels of the color tree, one for each bit of the channels
+const OCTREE_DEPTH: usize = 8;
+
+// The coordinates of a pixel scaled from the range of each channel in the color space to bytes
+fn tree_channels(color_space: ColorSpace, pixel: [u8; 4]) -> [u8; 4] {
+    let ranges: [(f32, f32); 4] = match color_space {
+        ColorSpace::Srgb => return pixel,
+        ColorSpace::Lab => [(0.0, 100.0), (-128.0, 128.0), (-128.0, 128.0), (0.0, 100.0)],
+        ColorSpace::Oklab => [(0.0, 1.0), (-0.4, 0.4), (-0.4, 0.4), (0.0, 1.0)],
+    };
+    let coordinates = color_space.coordinates(pixel.map(f32::from));
+    let mut channels = [0; 4];
+    for ((channel, value), (min, max)) in channels.iter_mut().zip(coordinates).zip(ranges) {
+        *channel = ((value - min) / (max - min) * 255.0)
+            .round()
+            .clamp(0.0, 255.0) as u8;
+    }
+    channels
+}
+
+#[derive(Default)]
+struct OctreeNode {
+    // Index of each child in the node list, zero for none since the root is never a child
+    children: [usize; 16],
+    count: u64,
+    sum: [u64; 4],
+}
+
+impl OctreeNode {
+    fn add(&mut self, pixel: [u8; 4]) {
+        self.count += 1;
+        for (total, value) in self.sum.iter_mut().zip(pixel) {
+            *total += value as u64;
+        }
+    }
+}
+
+impl PaletteQuantizer for OctreeQuantizer {
+    fn palette(&self, img: &RgbaImage, colors: usize, color_space: ColorSpace) -> Vec<Rgba<u8>> {
+        let mut nodes = vec![OctreeNode::default()];
+        // Nodes with children at each depth, in insertion order
+        let mut branches: Vec<Vec<usize>> = vec![Vec::new(); OCTREE_DEPTH];
+        let mut leaves = 0;
+        for pixel in opaque_pixels(img) {
+            let channels = tree_channels(color_space, pixel);
+            let mut node = 0;
+            for (depth, level) in branches.iter_mut().enumerate() {
+                nodes[node].add(pixel);
+                let bit = 7 - depth;
+                let child = channels.iter().fold(0, |index, &value| {
+                    index << 1 | ((value >> bit) & 1) as usize
+                });
+                if nodes[node].children[child] == 0 {
+                    if nodes[node].children.iter().all(|&child| child == 0) {
+                        level.push(node);
+                    }
+                    if depth == OCTREE_DEPTH - 1 {
+                        leaves += 1;
+                    }
+                    nodes[node].children[child] = nodes.len();
+                    nodes.push(OctreeNode::default());
+                }
+                node = nodes[node].children[child];
+            }
+            nodes[node].add(pixel);
+        }
+
+        // Fold the least used branches into leaves, the deepest ones first
+        for depth in (0..OCTREE_DEPTH).rev() {
+            let mut level = std::mem::take(&mut branches[depth]);
+            level.sort_by_key(|&node| (nodes[node].count, node));
+            for node in level {
+                if leaves <= colors {
+                    break;
+                }
+                let children = nodes[node].children.iter().filter(|&&c| c != 0).count();
+                nodes[node].children = [0; 16];
+                leaves = leaves + 1 - children;
+            }
+        }
+
+        let mut palette = Vec::with_capacity(leaves);
+        let mut stack = vec![0];
+        while let Some(node) = stack.pop() {
+            let node = &nodes[node];
+            if node.count == 0 {
+                continue;
+            }
+            if node.children.iter().all(|&child| child == 0) {
+                let count = node.count;
+                palette.push(Rgba(
+                    node.sum.map(|total| ((total + count / 2) / count) as u8),
+                ));
+            } else {
+                stack.extend(node.children.iter().rev().filter(|&&child| child != 0));
+            }
+        }
+        palette
+    }
+}
@@ -0,0 +1,312 @@
+//! The stages of fitting an input image into a square icon.
+
+use crate::Error;
+use image::{imageops, DynamicImage, GenericImageView, Rgba, RgbaImage};
+use std::fmt;
+use std::str::FromStr;
+
+/// Calculates new dimensions for resizing an image to fit within a square.
+///
+/// # Arguments
+/// * `input_width` - Width of the input image.
+/// * `input_height` - Height of the input image.
+/// * `output_size` - Desired output size (width and height).
+///
+/// # Returns
+/// Tuple of new width and height.
+///
+/// # Examples
+/// ```
+/// let (w, h) = chinenshichanaka::calculate_size(100, 150, 200);
+/// assert_eq!((w, h), (133, 200));
+/// ```
+pub fn calculate_size(input_width: u32, input_height: u32, output_size: u32) -> (u32, u32) {
+    // Calculate the scaling factor to fit the input image within a square of the desired size
+    let scale_factor = f64::min(
+        output_size as f64 / input_width as f64,
+        output_size as f64 / input_height as f64,
+    );
+
+    // Calculate the new dimensions after resizing
+    let new_width = (input_width as f64 * scale_factor) as u32;
+    let new_height = (input_height as f64 * scale_factor) as u32;
+
+    (new_width, new_height)
+}
+
+// Get the color of the top-left pixel
+/// Gets the color of the top-left pixel of an image.
+///
+/// # Arguments
+/// * `input_image` - Reference to the input image.
+///
+/// # Returns
+/// The color of the top-left pixel as `Rgba<u8>`.
+pub fn get_top_left_color(input_image: &DynamicImage) -> Rgba<u8> {
+    input_image.get_pixel(0, 0)
+}
+
+// Create a new square image with the desired output size and fill it with the background color
+/// Creates a new square image of the given size, filled with the specified background color.
+///
+/// # Arguments
+/// * `output_size` - Size of the square image (width and height).
+/// * `background_color` - Color to fill the image.
+///
+/// # Returns
+/// A new `DynamicImage` filled with the background color.
+pub fn create_square_image(output_size: u32, background_color: Rgba<u8>) -> DynamicImage {
+    DynamicImage::ImageRgba8(RgbaImage::from_pixel(
+        output_size,
+        output_size,
+        background_color,
+    ))
+}
+
+// Resize the input image using Lanczos3 filter for high-quality results
+/// Resizes an image to the specified dimensions using the Lanczos3 filter.
+///
+/// # Arguments
+/// * `input_image` - Reference to the input image.
+/// * `new_width` - Desired width.
+/// * `new_height` - Desired height.
+///
+/// # Returns
+/// A new `DynamicImage` with the resized dimensions.
+pub fn resize_image(input_image: &DynamicImage, new_width: u32, new_height: u32) -> DynamicImage {
+    input_image.resize_exact(new_width, new_height, imageops::FilterType::Lanczos3)
+}
+
+// Paste the resized image onto the square image at the specified position
+/// Pastes a resized image onto a square image at the specified position.
+///
+/// # Arguments
+/// * `square_image` - Mutable reference to the destination image.
+/// * `resized_image` - Reference to the image to paste.
+/// * `paste_x` - X coordinate for pasting.
+/// * `paste_y` - Y coordinate for pasting.
+pub fn paste_resized_image(
+    square_image: &mut DynamicImage,
+    resized_image: &DynamicImage,
+    paste_x: u32,
+    paste_y: u32,
+) {
+    imageops::overlay(square_image, resized_image, paste_x as i64, paste_y as i64);
+}
+
+// Resize input image to a square with the specified output size
+/// Resizes an image to a square of the specified size, centering the original image.
+///
+/// # Arguments
+/// * `input_image` - Reference to the input image.
+/// * `output_size` - Desired size for the square image.
+/// * `background_color` - Color of the areas not covered by the resized image.
+///
+/// # Returns
+/// A new `DynamicImage` resized and centered in a square.
+///
+/// # Examples
+/// ```
+/// use image::{DynamicImage, GenericImageView, Rgba};
+/// let img = DynamicImage::new_rgba8(100, 50);
+/// let square = chinenshichanaka::resize_to_square(&img, 32, Rgba([255, 255, 255, 255]));
+/// assert_eq!(square.dimensions(), (32, 32));
+/// // The letterbox bars are filled with the background color
+/// assert_eq!(square.get_pixel(0, 0), Rgba([255, 255, 255, 255]));
+/// ```
+pub fn resize_to_square(
+    input_image: &DynamicImage,
+    output_size: u32,
+    background_color: Rgba<u8>,
+) -> DynamicImage {
+    let (input_width, input_height) = input_image.dimensions();
+    let (new_width, new_height) = calculate_size(input_width, input_height, output_size);
+    let mut square_image = create_square_image(output_size, background_color);
+    let paste_x = (output_size - new_width) / 2;
+    let paste_y = (output_size - new_height) / 2;
+    let resized_image = resize_image(input_image, new_width, new_height);
+    paste_resized_image(&mut square_image, &resized_image, paste_x, paste_y);
+    square_image
+}
+
+/// How the input image is fitted into the square icon.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Fit {
+    /// Scale the whole image to fit and fill the rest with the background.
+    #[default]
+    Contain,
+    /// Scale the image to fill the square and crop the overflow around the center.
+    Cover,
+    /// Scale the width and height independently, distorting the aspect ratio.
+    Stretch,
+    /// Like `Cover`, but crop the window with the most visual detail.
+    Smart,
+}
+
+impl FromStr for Fit {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "contain" => Ok(Fit::Contain),
+            "cover" => Ok(Fit::Cover),
+            "stretch" => Ok(Fit::Stretch),
+            "smart" => Ok(Fit::Smart),
+            _ => Err(Error::InvalidOption {
+                name: "fit",
+                value: value.to_string(),
+                expected: "contain, cover, stretch or smart",
+            }),
+        }
+    }
+}
+
+impl fmt::Display for Fit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Fit::Contain => write!(f, "contain"),
+            Fit::Cover => write!(f, "cover"),
+            Fit::Stretch => write!(f, "stretch"),
+            Fit::Smart => write!(f, "smart"),
+        }
+    }
+}
+
+/// A rectangular area of an image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CropRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Calculates the largest centered square that fits within an image.
+///
+/// # Arguments
+/// * `input_width` - Width of the input image.
+/// * `input_height` - Height of the input image.
+///
+/// # Returns
+/// The square crop rectangle.
+///
+/// # Examples
+/// ```
+/// use chinenshichanaka::CropRect;
+/// let crop = chinenshichanaka::calculate_crop(300, 100);
+/// assert_eq!(crop, CropRect { x: 100, y: 0, width: 100, height: 100 });
+/// ```
+pub fn calculate_crop(input_width: u32, input_height: u32) -> CropRect {
+    let side = input_width.min(input_height);
+    CropRect {
+        x: (input_width - side) / 2,
+        y: (input_height - side) / 2,
+        width: side,
+        height: side,
+    }
+}
+
+/// Finds the square crop window with the most edge energy along the longer axis of an image.
+///
+/// The energy of each pixel is the sum of its horizontal and vertical luminance
+/// gradients. When several windows are equally detailed, the one closest to the
+/// center is chosen, so uniform images are cropped like [`calculate_crop`].
+///
+/// # Arguments
+/// * `input_image` - Reference to the input image.
+///
+/// # Returns
+/// The square crop rectangle.
+///
+/// # Examples
+/// ```
+/// use image::{DynamicImage, Rgba, RgbaImage};
+/// // A wide image with all the detail at its right end
+/// let img = DynamicImage::ImageRgba8(RgbaImage::from_fn(30, 10, |x, y| {
+///     if x >= 20 && (x + y) % 2 == 0 { Rgba([0, 0, 0, 255]) } else { Rgba([255, 255, 255, 255]) }
+/// }));
+/// assert_eq!(chinenshichanaka::smart_crop(&img).x, 20);
+/// ```
+pub fn smart_crop(input_image: &DynamicImage) -> CropRect {
+    let (input_width, input_height) = input_image.dimensions();
+    let centered = calculate_crop(input_width, input_height);
+    if input_width == input_height {
+        return centered;
+    }
+
+    let luma = input_image.to_luma8();
+    let wide = input_width > input_height;
+    // Energy summed across the shorter axis, one value per position along the longer axis
+    let length = input_width.max(input_height) as usize;
+    let mut energy = vec![0u64; length];
+    for (x, y, pixel) in luma.enumerate_pixels() {
+        let value = pixel[0] as i32;
+        let right = luma.get_pixel(x.saturating_add(1).min(input_width - 1), y)[0] as i32;
+        let below = luma.get_pixel(x, y.saturating_add(1).min(input_height - 1))[0] as i32;
+        let gradient = (right - value).unsigned_abs() + (below - value).unsigned_abs();
+        let position = if wide { x } else { y } as usize;
+        energy[position] += gradient as u64;
+    }
+
+    let side = centered.width as usize;
+    let center = if wide { centered.x } else { centered.y } as usize;
+    let mut window: u64 = energy[..side].iter().sum();
+    let mut best = (window, 0usize);
+    for start in 1..=length - side {
+        window = window + energy[start + side - 1] - energy[start - 1];
+        let closer = start.abs_diff(center) < best.1.abs_diff(center);
+        if window > best.0 || (window == best.0 && closer) {
+            best = (window, start);
+        }
+    }
+
+    if wide {
+        CropRect {
+            x: best.1 as u32,
+            ..centered
+        }
+    } else {
+        CropRect {
+            y: best.1 as u32,
+            ..centered
+        }
+    }
+}
+
+/// Fits an image into a square of the specified size using the given fit mode.
+///
+/// # Arguments
+/// * `input_image` - Reference to the input image.
+/// * `output_size` - Desired size for the square image.
+/// * `background_color` - Color of the areas not covered by the image, used by `Fit::Contain`.
+/// * `fit` - How the image is fitted into the square.
+///
+/// # Returns
+/// A new square `DynamicImage`.
+///
+/// # Examples
+/// ```
+/// use chinenshichanaka::Fit;
+/// use image::{DynamicImage, GenericImageView, Rgba, RgbaImage};
+/// let img = DynamicImage::ImageRgba8(RgbaImage::from_pixel(100, 50, Rgba([255, 0, 0, 255])));
+/// let square = chinenshichanaka::fit_to_square(&img, 32, Rgba([0, 0, 0, 0]), Fit::Cover);
+/// assert_eq!(square.dimensions(), (32, 32));
+/// // No letterbox bars, the image covers the whole square
+/// assert_eq!(square.get_pixel(0, 0), Rgba([255, 0, 0, 255]));
+/// ```
+pub fn fit_to_square(
+    input_image: &DynamicImage,
+    output_size: u32,
+    background_color: Rgba<u8>,
+    fit: Fit,
+) -> DynamicImage {
+    let (input_width, input_height) = input_image.dimensions();
+    let crop = match fit {
+        Fit::Contain => return resize_to_square(input_image, output_size, background_color),
+        Fit::Stretch => return resize_image(input_image, output_size, output_size),
+        Fit::Cover => calculate_crop(input_width, input_height),
+        Fit::Smart => smart_crop(input_image),
+    };
+    let cropped = input_image.crop_imm(crop.x, crop.y, crop.width, crop.height);
+    resize_image(&cropped, output_size, output_size)
+}
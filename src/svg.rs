@@ -0,0 +1,134 @@
+//! Rendering SVG documents with resvg at any requested size.
+
+use crate::{Error, Result};
+use image::{DynamicImage, RgbaImage};
+use resvg::tiny_skia::{Pixmap, Transform};
+use resvg::usvg::{Options, Tree};
+
+/// Size of the longer side used when an SVG is rendered without a specific target size,
+/// for example for sampling the background color or finding the content for trimming.
+pub const SVG_REFERENCE_SIZE: u32 = 512;
+
+/// Largest size of the longer side that an SVG is rendered at for a single icon.
+pub(crate) const MAX_RENDER_SIZE: u32 = 4096;
+
+/// A parsed SVG document together with its rendering at [`SVG_REFERENCE_SIZE`].
+///
+/// The document is parsed once and can then be rendered at any number of sizes, while the
+/// reference rendering serves for sampling the background color and finding the content
+/// for trimming.
+#[derive(Debug, Clone)]
+pub struct SvgDocument {
+    tree: Tree,
+    reference: DynamicImage,
+}
+
+impl SvgDocument {
+    /// Parses SVG data and renders it at [`SVG_REFERENCE_SIZE`].
+    ///
+    /// # Errors
+    /// Returns [`Error::SvgParse`] if the SVG data cannot be parsed.
+    ///
+    /// # Examples
+    /// ```
+    /// use chinenshichanaka::SvgDocument;
+    /// use image::GenericImageView;
+    /// let svg = br#"<svg viewBox='0 0 200 100' xmlns='http://www.w3.org/2000/svg'/>"#;
+    /// let document = SvgDocument::parse(svg)?;
+    /// assert_eq!(document.reference().dimensions(), (512, 256));
+    /// assert_eq!(document.render(32)?.dimensions(), (32, 16));
+    /// # Ok::<(), chinenshichanaka::Error>(())
+    /// ```
+    pub fn parse(input: &[u8]) -> Result<SvgDocument> {
+        let tree = parse_svg(input)?;
+        let reference = render_tree(&tree, SVG_REFERENCE_SIZE)?;
+        Ok(SvgDocument { tree, reference })
+    }
+
+    /// The rendering of the document at [`SVG_REFERENCE_SIZE`].
+    pub fn reference(&self) -> &DynamicImage {
+        &self.reference
+    }
+
+    /// Renders the document so that its longer side is `size` pixels, as with
+    /// [`render_svg_to_image`].
+    ///
+    /// # Errors
+    /// Returns [`Error::InvalidSize`] if `size` is zero.
+    pub fn render(&self, size: u32) -> Result<DynamicImage> {
+        render_tree(&self.tree, size)
+    }
+}
+
+/// Parses SVG data into a tree that can be rendered at several sizes.
+pub(crate) fn parse_svg(input: &[u8]) -> Result<Tree> {
+    Tree::from_data(input, &Options::default()).map_err(Error::SvgParse)
+}
+
+/// Decompresses an SVGZ document into SVG.
+pub(crate) fn decompress_svgz(input: &[u8]) -> Result<Vec<u8>> {
+    resvg::usvg::decompress_svgz(input).map_err(Error::SvgParse)
+}
+
+/// Renders a parsed SVG so that its longer side is `size` pixels, preserving the aspect ratio.
+pub(crate) fn render_tree(tree: &Tree, size: u32) -> Result<DynamicImage> {
+    if size == 0 {
+        return Err(Error::InvalidSize {
+            width: 0,
+            height: 0,
+        });
+    }
+    let document = tree.size();
+    let scale = size as f32 / document.width().max(document.height());
+    // The shorter side is rounded, but never below one pixel
+    let width = ((document.width() * scale).round() as u32).clamp(1, size);
+    let height = ((document.height() * scale).round() as u32).clamp(1, size);
+    let mut pixmap = Pixmap::new(width, height).ok_or(Error::InvalidSize { width, height })?;
+    resvg::render(
+        tree,
+        Transform::from_scale(scale, scale),
+        &mut pixmap.as_mut(),
+    );
+    let pixels: Vec<u8> = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let color = pixel.demultiply();
+            [color.red(), color.green(), color.blue(), color.alpha()]
+        })
+        .collect();
+    Ok(DynamicImage::ImageRgba8(
+        RgbaImage::from_raw(width, height, pixels)
+            .expect("Pixmap data matches the image dimensions"),
+    ))
+}
+
+/// Renders SVG data to a `DynamicImage` using resvg, scaled to the requested size.
+///
+/// The document size, including any `viewBox`, is scaled so that its longer side is
+/// `size` pixels, preserving the aspect ratio. The rendered pixels are converted from
+/// premultiplied alpha to straight alpha, so semi-transparent colors keep their
+/// original values.
+///
+/// # Arguments
+/// * `input` - SVG data as a byte slice.
+/// * `size` - Length of the longer side of the rendered image in pixels.
+///
+/// # Returns
+/// A `DynamicImage` containing the rendered SVG.
+///
+/// # Errors
+/// Returns [`Error::SvgParse`] if the SVG data cannot be parsed, and
+/// [`Error::InvalidSize`] if `size` is zero.
+///
+/// # Examples
+/// ```
+/// use image::GenericImageView;
+/// let svg = br#"<svg viewBox='0 0 200 100' xmlns='http://www.w3.org/2000/svg'><rect width='200' height='100' style='fill:rgb(255,0,0);'/></svg>"#;
+/// let img = chinenshichanaka::render_svg_to_image(svg, 32)?;
+/// assert_eq!(img.dimensions(), (32, 16));
+/// # Ok::<(), chinenshichanaka::Error>(())
+/// ```
+pub fn render_svg_to_image(input: &[u8], size: u32) -> Result<DynamicImage> {
+    render_tree(&parse_svg(input)?, size)
+}
@@ -0,0 +1,170 @@
+//! Removing uniform margins around the content of an image and adding controlled ones back.
+
+use crate::{get_edge_color, CropRect, Error};
+use image::{imageops, DynamicImage, GenericImageView, Rgba, RgbaImage};
+
+/// Color tolerance used by default when detecting the content of an image.
+pub const DEFAULT_TRIM_TOLERANCE: u8 = 16;
+
+/// Largest padding on each side, as a fraction of the longer side of the image.
+pub const MAX_PADDING: f32 = 1.0;
+
+// A pixel belongs to the margin when it is as transparent as a transparent border,
+// or when all its channels are within the tolerance of the border color.
+fn is_margin(pixel: Rgba<u8>, border: Rgba<u8>, tolerance: u8) -> bool {
+    if border[3] <= tolerance && pixel[3] <= tolerance {
+        return true;
+    }
+    pixel
+        .0
+        .iter()
+        .zip(border.0.iter())
+        .all(|(a, b)| a.abs_diff(*b) <= tolerance)
+}
+
+/// Finds the bounding box of the content of an image.
+///
+/// The margin color is the most common color along the borders of the image. When
+/// that color is transparent, any pixel with an alpha within the tolerance is part of
+/// the margin, otherwise all channels have to be within the tolerance of it.
+///
+/// # Arguments
+/// * `input_image` - Reference to the input image.
+/// * `tolerance` - Largest per-channel difference still counted as the margin color.
+///
+/// # Returns
+/// The content rectangle, or `None` when the image has no content at all.
+///
+/// # Examples
+/// ```
+/// use chinenshichanaka::CropRect;
+/// use image::{DynamicImage, Rgba, RgbaImage};
+/// let mut img = RgbaImage::from_pixel(10, 10, Rgba([255, 255, 255, 255]));
+/// img.put_pixel(3, 4, Rgba([0, 0, 0, 255]));
+/// img.put_pixel(5, 6, Rgba([0, 0, 0, 255]));
+/// let bounds = chinenshichanaka::content_bounds(&DynamicImage::ImageRgba8(img), 16);
+/// assert_eq!(bounds, Some(CropRect { x: 3, y: 4, width: 3, height: 3 }));
+/// ```
+pub fn content_bounds(input_image: &DynamicImage, tolerance: u8) -> Option<CropRect> {
+    let border = get_edge_color(input_image);
+    let mut bounds: Option<(u32, u32, u32, u32)> = None;
+    for (x, y, pixel) in input_image.pixels() {
+        if is_margin(pixel, border, tolerance) {
+            continue;
+        }
+        bounds = Some(match bounds {
+            None => (x, y, x, y),
+            Some((left, top, right, bottom)) => {
+                (left.min(x), top.min(y), right.max(x), bottom.max(y))
+            }
+        });
+    }
+    bounds.map(|(left, top, right, bottom)| CropRect {
+        x: left,
+        y: top,
+        width: right - left + 1,
+        height: bottom - top + 1,
+    })
+}
+
+/// Crops an image to the bounding box of its content.
+///
+/// Images without any content are returned unchanged.
+///
+/// # Arguments
+/// * `input_image` - Reference to the input image.
+/// * `tolerance` - Largest per-channel difference still counted as the margin color.
+///
+/// # Returns
+/// A new `DynamicImage` without the uniform margins.
+///
+/// # Examples
+/// ```
+/// use image::{DynamicImage, GenericImageView, Rgba, RgbaImage};
+/// let mut img = RgbaImage::new(10, 10);
+/// img.put_pixel(3, 4, Rgba([255, 0, 0, 255]));
+/// let trimmed = chinenshichanaka::trim(&DynamicImage::ImageRgba8(img), 16);
+/// assert_eq!(trimmed.dimensions(), (1, 1));
+/// ```
+pub fn trim(input_image: &DynamicImage, tolerance: u8) -> DynamicImage {
+    match content_bounds(input_image, tolerance) {
+        Some(crop) => input_image.crop_imm(crop.x, crop.y, crop.width, crop.height),
+        None => input_image.clone(),
+    }
+}
+
+/// Adds a margin around an image, relative to its longer side.
+///
+/// # Arguments
+/// * `input_image` - Reference to the input image.
+/// * `padding` - Margin on each side as a fraction of the longer side, `0.1` being 10%.
+/// * `fill_color` - Color of the added margin.
+///
+/// # Returns
+/// A new, larger RGBA8 `DynamicImage` with the input centered in it.
+///
+/// # Errors
+/// Returns [`Error::InvalidSize`] if the padded size does not fit in `u32`.
+///
+/// # Examples
+/// ```
+/// use image::{DynamicImage, GenericImageView, Rgba};
+/// let img = DynamicImage::new_rgba8(100, 50);
+/// let padded = chinenshichanaka::add_padding(&img, 0.1, Rgba([0, 0, 0, 0]))?;
+/// assert_eq!(padded.dimensions(), (120, 70));
+/// # Ok::<(), chinenshichanaka::Error>(())
+/// ```
+pub fn add_padding(
+    input_image: &DynamicImage,
+    padding: f32,
+    fill_color: Rgba<u8>,
+) -> Result<DynamicImage, Error> {
+    let (width, height) = input_image.dimensions();
+    let margin = (width.max(height) as f32 * padding).round() as u32;
+    let padded_side = |side: u32| {
+        margin
+            .checked_mul(2)
+            .and_then(|both| side.checked_add(both))
+    };
+    let (Some(padded_width), Some(padded_height)) = (padded_side(width), padded_side(height))
+    else {
+        return Err(Error::InvalidSize { width, height });
+    };
+    let mut padded = RgbaImage::from_pixel(padded_width, padded_height, fill_color);
+    imageops::overlay(
+        &mut padded,
+        &input_image.to_rgba8(),
+        margin as i64,
+        margin as i64,
+    );
+    Ok(DynamicImage::ImageRgba8(padded))
+}
+
+/// Parses a padding given as a percentage, such as `10%` or `10`, into a fraction.
+///
+/// # Errors
+/// Returns [`Error::InvalidOption`] if the value is not a number between 0% and 100%.
+///
+/// # Examples
+/// ```
+/// assert_eq!(chinenshichanaka::parse_padding("10%")?, 0.1);
+/// assert_eq!(chinenshichanaka::parse_padding("25")?, 0.25);
+/// # Ok::<(), chinenshichanaka::Error>(())
+/// ```
+pub fn parse_padding(value: &str) -> Result<f32, Error> {
+    let invalid = || Error::InvalidOption {
+        name: "padding",
+        value: value.to_string(),
+        expected: "a percentage between 0% and 100%, such as 10%",
+    };
+    let percent: f32 = value
+        .trim()
+        .trim_end_matches('%')
+        .parse()
+        .map_err(|_| invalid())?;
+    let padding = percent / 100.0;
+    if !(0.0..=MAX_PADDING).contains(&padding) {
+        return Err(invalid());
+    }
+    Ok(padding)
+}